@@ -1,15 +1,22 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use futures_concurrency::future::TryJoin as _;
 
-use driver_engine::{Blob, query};
+use driver_engine::{Blob, query_with_hash};
 use driver_query_ssg::boa::{RunJs, parse_args};
-use driver_query_ssg::{QueryContext, QueryOutput, WriteOutput};
+use driver_query_ssg::post_process::PostProcess;
+use driver_query_ssg::{HashKey, QueryContext, QueryOutput, WriteOutput, input_files};
 use driver_util::WriteOutputDiff;
 
 pub struct RunOutput {
+    /// The key that was run at the top level.
+    root: HashKey,
     prev: Option<WriteOutput>,
     curr: WriteOutput,
+    /// Whether the script itself ran successfully.
+    ok: bool,
 }
 
 pub async fn run<'a>(
@@ -17,28 +24,35 @@ pub async fn run<'a>(
     file: PathBuf,
     args: impl IntoIterator<Item = &'a str>,
 ) -> driver_util::Result<RunOutput> {
-    let key = RunJs {
-        file,
+    let run = RunJs {
+        file: driver_util::normalize_path(&file),
         arg: parse_args(args),
     };
+    // The post-processing rules are part of the key, so if they change, there's no previous output
+    // to diff against & everything gets re-written.
+    let key = PostProcess::new(run, root.options())?;
     // SAFETY: we are the one place this function is allowed to be called.
     let prev = match unsafe { root.db().get_value(&key.clone().into()) } {
         None => None,
-        Some(QueryOutput::RunJs(v)) => Some(v.writes),
+        Some(QueryOutput::PostProcess(v)) => Some(v.writes),
         Some(other) => {
             return Err(driver_util::Error::new(&format!(
-                "expected RunJs, got {other:?}"
+                "expected PostProcess, got {other:?}"
             )));
         }
     };
 
-    let output = query(root, key).await;
-    if let Err(e) = output.export {
+    let (hash, output) = query_with_hash(root, key).await;
+    // Whatever got output is probably missing pieces, so better not to write any of it
+    root.cancellation().check()?;
+    if let Err(e) = &output.export {
         eprintln!("{e}");
     }
     Ok(RunOutput {
+        root: hash,
         prev,
         curr: output.writes,
+        ok: output.export.is_ok(),
     })
 }
 
@@ -48,26 +62,319 @@ pub struct WriteOptions {
     pub output_path: PathBuf,
     /// If this is specified, we only write new files, never delete old ones.
     pub no_delete_missing: bool,
+    /// If this is specified, we build the whole output in a staging directory next to
+    /// `output_path`, then swap it in, but only if the build succeeded. That way, anything serving
+    /// `output_path` never sees a half-written site.
+    pub atomic: bool,
+    /// If this is specified, we'll delete things in `output_path` even if it doesn't look like
+    /// something we wrote.
+    pub force: bool,
+}
+
+/// Written into every output directory, so we know it's safe to delete things from it later.
+/// Without this, pointing `--dist` at the wrong directory would wipe it.
+const MARKER: &str = ".driver-output";
+
+/// Where output files actually end up. Normally that's just the disk, but it's nice to be able to
+/// write everything into memory instead, say for tests.
+pub trait OutputFs: Sync {
+    /// Writes the contents of `blob` to `path`, creating parent directories as needed.
+    fn write(&self, root: &QueryContext, path: &Path, blob: &Blob) -> driver_util::Result<()>;
+    /// Removes a single file.
+    fn remove(&self, path: &Path) -> driver_util::Result<()>;
+    /// Removes an entire directory. It not existing is fine.
+    fn remove_all(&self, path: &Path) -> driver_util::Result<()>;
+    /// Creates an empty file, creating parent directories as needed.
+    fn touch(&self, path: &Path) -> driver_util::Result<()>;
+    /// Whether a file exists.
+    fn exists(&self, path: &Path) -> bool;
+    /// Whether a directory has nothing in it. It not existing counts as empty.
+    fn is_empty_dir(&self, path: &Path) -> bool;
+}
+
+/// Makes sure `dir` exists & can be written to, so that a bad `--cache` or `--dist` fails with a
+/// clear error right away, instead of after the whole build has run.
+pub fn ensure_writable(flag: &str, dir: &Path) -> driver_util::Result<()> {
+    let probe = dir.join(".driver-write-check");
+    std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(&probe, b""))
+        .and_then(|()| std::fs::remove_file(&probe))
+        .map_err(|err| {
+            driver_util::Error::new(&format!("can't write to {flag} {}: {err}", dir.display()))
+        })
+}
+
+/// Refuses to let anything get deleted from `base` unless we're the ones who put it there.
+fn check_marker(fs: &impl OutputFs, base: &Path, force: bool) -> driver_util::Result<()> {
+    if force || fs.exists(&base.join(MARKER)) || fs.is_empty_dir(base) {
+        return Ok(());
+    }
+    Err(driver_util::Error::new(&format!(
+        "{} doesn't look like a driver output directory (no {MARKER} file), refusing to delete \
+         anything in it; pass --force if you're sure",
+        base.display()
+    )))
+}
+
+/// Deletes the output directory `dist`, as long as we're the ones who wrote it. Returns whether
+/// there was anything to delete.
+pub fn remove_output_dir(dist: &Path, force: bool) -> driver_util::Result<bool> {
+    if !dist.exists() {
+        return Ok(false);
+    }
+    check_marker(&DiskFs, dist, force)?;
+    std::fs::remove_dir_all(dist)?;
+    Ok(true)
+}
+
+/// Writes to the real filesystem, hardlinking out of the blob store.
+pub struct DiskFs;
+
+impl OutputFs for DiskFs {
+    fn write(&self, root: &QueryContext, path: &Path, blob: &Blob) -> driver_util::Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        root.db().blobs.copy(root.options(), blob, path)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> driver_util::Result<()> {
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn remove_all(&self, path: &Path) -> driver_util::Result<()> {
+        // Ignore errors removing directory; it's just a safety measure
+        std::fs::remove_dir_all(path).unwrap_or_default();
+        Ok(())
+    }
+
+    fn touch(&self, path: &Path) -> driver_util::Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, b"")?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_empty_dir(&self, path: &Path) -> bool {
+        match std::fs::read_dir(path) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Keeps all the output files in memory instead of touching the disk.
+#[derive(Default)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct MemoryFs {
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl MemoryFs {
+    /// Gets the contents of a file that was written, if any.
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path).cloned()
+    }
+
+    /// Lists all files currently written, in sorted order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+impl OutputFs for MemoryFs {
+    fn write(&self, root: &QueryContext, path: &Path, blob: &Blob) -> driver_util::Result<()> {
+        let contents = root.load_bytes(blob)?;
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), contents);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> driver_util::Result<()> {
+        match self.files.lock().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(driver_util::Error::new(&format!(
+                "{} does not exist",
+                path.display()
+            ))),
+        }
+    }
+
+    fn remove_all(&self, path: &Path) -> driver_util::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .retain(|file, _| !file.starts_with(path));
+        Ok(())
+    }
+
+    fn touch(&self, path: &Path) -> driver_util::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), Vec::new());
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn is_empty_dir(&self, path: &Path) -> bool {
+        !self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|file| file.starts_with(path))
+    }
 }
 
 impl RunOutput {
+    /// A hash of every file the build wants written, for telling whether two builds are identical.
+    pub fn fingerprint(&self) -> driver_util::Hash {
+        self.curr.fingerprint()
+    }
+
+    /// Everything the build wants written.
+    pub fn outputs(&self) -> &WriteOutput {
+        &self.curr
+    }
+
+    /// Adds a file on top of everything the script itself wrote, for things generated from the
+    /// whole build (like a sitemap).
+    pub fn push_output(&mut self, path: PathBuf, blob: Blob) {
+        let mut builder = WriteOutput::builder();
+        let script = std::mem::replace(&mut self.curr, WriteOutput::builder().finalize());
+        builder.merge(self.root, script);
+        builder.push(path, blob);
+        self.curr = builder.finalize();
+    }
+
+    /// Every file & directory the build read. SHOULD only be called when computation isn't taking
+    /// place.
+    pub fn input_files(&self, root: &QueryContext) -> BTreeSet<PathBuf> {
+        input_files(root, self.root)
+    }
+
+    /// Writes a Makefile-style depfile saying that `target` depends on every file the build read.
+    pub fn write_depfile(
+        &self,
+        root: &QueryContext,
+        target: &Path,
+        depfile: &Path,
+    ) -> driver_util::Result<()> {
+        fn escape(path: &Path) -> String {
+            let mut out = String::new();
+            for c in path.to_string_lossy().chars() {
+                match c {
+                    ' ' | '#' | '\\' => {
+                        out.push('\\');
+                        out.push(c);
+                    }
+                    '$' => out.push_str("$$"),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+
+        let mut contents = escape(target);
+        contents.push(':');
+        for file in self.input_files(root) {
+            contents.push_str(" \\\n  ");
+            contents.push_str(&escape(&file));
+        }
+        contents.push('\n');
+
+        std::fs::write(depfile, contents)?;
+        Ok(())
+    }
+
     pub async fn write(
         self,
         root: &QueryContext,
         options: &WriteOptions,
+    ) -> driver_util::Result<()> {
+        if options.atomic {
+            self.write_atomic(root, options).await
+        } else {
+            self.write_to(root, options, &DiskFs).await
+        }
+    }
+
+    async fn write_atomic(
+        self,
+        root: &QueryContext,
+        options: &WriteOptions,
+    ) -> driver_util::Result<()> {
+        let output = &options.output_path;
+        if !self.ok {
+            return Err(driver_util::Error::new(&format!(
+                "build failed, leaving {} alone",
+                output.display()
+            )));
+        }
+
+        let sibling = |suffix: &str| {
+            let mut name = output.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            output.with_file_name(name)
+        };
+        let staging = sibling(".staging");
+        let old = sibling(".old");
+
+        // Build the full set of files from scratch, so stale files never make it over. This is
+        // cheap, since it's all hardlinks.
+        let staged = RunOutput { prev: None, ..self };
+        let staging_options = WriteOptions {
+            output_path: staging.clone(),
+            // Only we ever write here
+            force: true,
+            ..Default::default()
+        };
+        check_marker(&DiskFs, output, options.force)?;
+        staged.write_to(root, &staging_options, &DiskFs).await?;
+
+        // Not _quite_ atomic, since there's a moment between the renames where nothing's there,
+        // but readers will never see a mix of old & new files.
+        DiskFs.remove_all(&old)?;
+        if std::fs::exists(output)? {
+            std::fs::rename(output, &old)?;
+        }
+        std::fs::rename(&staging, output)?;
+        DiskFs.remove_all(&old)?;
+        Ok(())
+    }
+
+    pub async fn write_to(
+        self,
+        root: &QueryContext,
+        options: &WriteOptions,
+        fs: &impl OutputFs,
     ) -> driver_util::Result<()> {
         let base = &options.output_path;
+        let deletes = self.prev.is_none() || !options.no_delete_missing;
+        if deletes {
+            check_marker(fs, base, options.force)?;
+        }
         match self.prev {
             None => {
-                // Ignore errors removing directory; it's just a safety measure
-                std::fs::remove_dir_all(base).unwrap_or_default();
-                write(root, base, self.curr.iter()).await
+                fs.remove_all(base)?;
+                write(root, fs, base, self.curr.iter()).await?;
             }
             Some(prev) => {
                 let diff = WriteOutputDiff::diff(&self.curr, &prev);
                 let ((), ()) = (
-                    write(root, base, diff.to_write.into_iter()),
+                    write(root, fs, base, diff.to_write.into_iter()),
                     remove(
+                        fs,
                         base,
                         if options.no_delete_missing {
                             Default::default()
@@ -79,40 +386,204 @@ impl RunOutput {
                 )
                     .try_join()
                     .await?;
-                Ok(())
             }
         }
+        fs.touch(&base.join(MARKER))
     }
 }
 
 async fn write(
     root: &QueryContext,
+    fs: &impl OutputFs,
     base: &Path,
     iter: impl Iterator<Item = (&PathBuf, &Blob)>,
 ) -> driver_util::Result<()> {
     let mut futs = Vec::new();
     for (path, blob) in iter {
         let full_path = base.join(path);
-        futs.push(async move {
-            std::fs::create_dir_all(full_path.parent().unwrap())?;
-            root.db().blobs.copy(root.options(), blob, &full_path)?;
-            driver_util::Result::Ok(())
-        });
+        futs.push(async move { fs.write(root, &full_path, blob) });
     }
     let _ = futs.try_join().await?;
     Ok(())
 }
 
-async fn remove(base: &Path, iter: impl Iterator<Item = &PathBuf>) -> driver_util::Result<()> {
+async fn remove(
+    fs: &impl OutputFs,
+    base: &Path,
+    iter: impl Iterator<Item = &PathBuf>,
+) -> driver_util::Result<()> {
     let mut futs = Vec::new();
     for path in iter {
         let full_path = base.join(path);
         // TODO: should we be removing empty directories too? How?
-        futs.push(async move {
-            std::fs::remove_file(&full_path)?;
-            driver_util::Result::Ok(())
-        });
+        futs.push(async move { fs.remove(&full_path) });
     }
     let _ = futs.try_join().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future;
+
+    use super::*;
+
+    /// Any key will do for the root, so use one that fails quickly.
+    fn missing_root(root: &QueryContext, dir: &Path) -> HashKey {
+        let (hash, _) = future::block_on(query_with_hash(
+            root,
+            RunJs {
+                file: dir.join("missing.js"),
+                arg: parse_args([]),
+            },
+        ));
+        hash
+    }
+
+    #[test]
+    fn checks_writable_up_front() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        ensure_writable("--dist", &dir.join("a/b")).unwrap();
+        assert!(dir.join("a/b").is_dir());
+        // Nothing left behind
+        assert_eq!(std::fs::read_dir(dir.join("a/b")).unwrap().count(), 0);
+
+        std::fs::write(dir.join("file"), "").unwrap();
+        let err = ensure_writable("--dist", &dir.join("file/dist")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("can't write to --dist"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn only_removes_our_output_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dist");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("precious.txt"), "").unwrap();
+        assert!(remove_output_dir(&dir, false).is_err());
+        assert!(dir.join("precious.txt").exists());
+
+        std::fs::write(dir.join(MARKER), "").unwrap();
+        assert!(remove_output_dir(&dir, false).unwrap());
+        assert!(!dir.exists());
+        // Already gone is fine
+        assert!(!remove_output_dir(&dir, false).unwrap());
+    }
+
+    #[test]
+    fn write_to_memory() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("index.js");
+        std::fs::write(
+            &script,
+            r#"import { store, write_output } from "driver";
+write_output("index.html", store("hello"));
+write_output("a/b.txt", store("world"));
+"#,
+        )
+        .unwrap();
+        let root = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+
+        let output = future::block_on(run(&root, script, [])).unwrap();
+        assert!(output.ok);
+        let fs = MemoryFs::default();
+        future::block_on(output.write_to(&root, &WriteOptions::default(), &fs)).unwrap();
+        assert_eq!(
+            fs.paths(),
+            [
+                PathBuf::from(MARKER),
+                PathBuf::from("a/b.txt"),
+                PathBuf::from("index.html")
+            ]
+        );
+        assert_eq!(fs.read(Path::new("index.html")), Some(b"hello".to_vec()));
+        assert_eq!(fs.read(Path::new("a/b.txt")), Some(b"world".to_vec()));
+
+        root.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn failed_atomic_build_keeps_old_output() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let root = QueryContext::create_root(driver_engine::Options::with_base_dir(dir), None);
+        let hash = missing_root(&root, dir);
+
+        let dist = dir.join("dist");
+        std::fs::create_dir_all(&dist).unwrap();
+        std::fs::write(dist.join("index.html"), "old").unwrap();
+        // As if we'd built there before
+        std::fs::write(dist.join(MARKER), "").unwrap();
+        let options = WriteOptions {
+            output_path: dist.clone(),
+            atomic: true,
+            ..Default::default()
+        };
+
+        let output = |ok| {
+            let mut curr = WriteOutput::builder();
+            curr.push("index.html".into(), root.store(b"new".to_vec()).unwrap());
+            RunOutput {
+                root: hash,
+                prev: None,
+                curr: curr.finalize(),
+                ok,
+            }
+        };
+
+        assert!(future::block_on(output(false).write(&root, &options)).is_err());
+        assert_eq!(std::fs::read(dist.join("index.html")).unwrap(), b"old");
+
+        future::block_on(output(true).write(&root, &options)).unwrap();
+        assert_eq!(std::fs::read(dist.join("index.html")).unwrap(), b"new");
+        assert!(!std::fs::exists(dir.join("dist.staging")).unwrap());
+        assert!(!std::fs::exists(dir.join("dist.old")).unwrap());
+
+        root.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn refuses_to_prune_unmarked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let root = QueryContext::create_root(driver_engine::Options::with_base_dir(dir), None);
+        let hash = missing_root(&root, dir);
+
+        // Somebody's home directory, say
+        let fs = MemoryFs::default();
+        fs.touch(Path::new("home/notes.txt")).unwrap();
+
+        let mut prev = WriteOutput::builder();
+        prev.push("notes.txt".into(), root.store(b"notes".to_vec()).unwrap());
+        let prev = prev.finalize();
+        // The build no longer writes notes.txt, so it should get pruned
+        let output = || RunOutput {
+            root: hash,
+            prev: Some(prev.clone()),
+            curr: WriteOutput::builder().finalize(),
+            ok: true,
+        };
+        let mut options = WriteOptions {
+            output_path: "home".into(),
+            ..Default::default()
+        };
+
+        assert!(future::block_on(output().write_to(&root, &options, &fs)).is_err());
+        assert!(fs.exists(Path::new("home/notes.txt")));
+
+        options.force = true;
+        future::block_on(output().write_to(&root, &options, &fs)).unwrap();
+        assert!(!fs.exists(Path::new("home/notes.txt")));
+        assert!(fs.exists(&Path::new("home").join(MARKER)));
+
+        root.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! `driver init`: writes a starter project, so there's something to build right away.
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Every file in the starter project, relative to where it gets written.
+const FILES: &[(&str, &str)] = &[
+    ("build.js", include_str!("init/build.js")),
+    ("content/index.md", include_str!("init/index.md")),
+    (".gitignore", "/.driver/\n/dist/\n"),
+];
+
+/// Writes the starter project into `dir`, returning the paths of everything written. Refuses to
+/// overwrite anything, in which case nothing is written at all.
+pub fn init(dir: &Path) -> driver_util::Result<Vec<PathBuf>> {
+    for (name, _) in FILES {
+        let path = dir.join(name);
+        if path.exists() {
+            return Err(driver_util::Error::new(&format!(
+                "{} already exists, refusing to overwrite it",
+                path.display()
+            )));
+        }
+    }
+
+    let mut written = Vec::new();
+    for (name, contents) in FILES {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Still don't clobber anything that showed up since we checked
+        std::fs::File::create_new(&path)?.write_all(contents.as_bytes())?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future;
+
+    use super::*;
+    use crate::fs;
+    use driver_query_ssg::QueryContext;
+
+    #[test]
+    fn starter_project_builds() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        init(dir).unwrap();
+        assert!(init(dir).is_err());
+
+        let root = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = future::block_on(fs::run(
+            &root,
+            dir.join("build.js"),
+            [dir.to_str().unwrap()],
+        ))
+        .unwrap();
+        let outputs: Vec<_> = output.outputs().iter().collect();
+        assert_eq!(outputs.len(), 1);
+        let (path, blob) = outputs[0];
+        assert_eq!(path, Path::new("index.html"));
+        let page = root.load_string(blob).unwrap();
+        assert!(page.contains("<h1") && page.contains("Hello!"), "{page}");
+
+        root.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,22 @@
+import { markdown_to_html, read_file, store, write_output } from "driver";
+
+// Paths are relative to wherever you run driver from. To build this project from somewhere else,
+// pass its directory after `--`, like `driver run path/to/build.js -- path/to`.
+const [root = "."] = ARG;
+
+const markdown = await read_file(`${root}/content/index.md`);
+const body = await markdown_to_html(markdown);
+
+const page = `<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>My site</title>
+  </head>
+  <body>
+${body}
+  </body>
+</html>
+`;
+
+write_output("index.html", store(page));
@@ -0,0 +1,30 @@
+//! Stopping a build on Ctrl-C without losing the cache. The first Ctrl-C cancels the build, which
+//! still saves everything that finished before it; a second one exits right away.
+
+use std::sync::OnceLock;
+
+use driver_engine::CancellationToken;
+
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    let Some(token) = TOKEN.get() else {
+        return;
+    };
+    if token.is_cancelled() {
+        // SAFETY: _exit is async-signal-safe, unlike exit
+        unsafe { libc::_exit(130) };
+    }
+    token.cancel();
+}
+
+/// Cancels `token` on SIGINT. Only the first token passed in is ever cancelled.
+pub fn cancel_on_interrupt(token: CancellationToken) {
+    if TOKEN.set(token).is_err() {
+        return;
+    }
+    // SAFETY: the handler only touches atomics
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as libc::sighandler_t);
+    }
+}
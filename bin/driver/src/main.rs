@@ -4,12 +4,22 @@ use std::path::PathBuf;
 use clap::{Arg, ArgAction, Command, arg, command, value_parser};
 use futures_lite::future;
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer as _;
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use driver_query_ssg::QueryContext;
+use driver_engine::{Snapshot, query};
+use driver_query_ssg::links::check_links;
+use driver_query_ssg::manifest::Manifest;
+use driver_query_ssg::profile::Profiler;
+use driver_query_ssg::sitemap::Sitemap;
+use driver_query_ssg::{QueryContext, QueryKey, QueryOutput};
 
 mod fs;
+mod init;
+mod interrupt;
+mod strict;
 mod watches;
 
 fn main() {
@@ -17,11 +27,15 @@ fn main() {
         Ok(()) => {}
         Err(e) => {
             eprintln!("{e}");
-            std::process::exit(1);
+            // Same as being killed by the Ctrl-C outright, which is what shells expect
+            std::process::exit(if e.is_cancelled() { 130 } else { 1 });
         }
     }
 }
 
+/// How long to wait for files to stop changing before rebuilding in watch mode.
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(100);
+
 fn time<T>(message: &'static str, f: impl FnOnce() -> T) -> T {
     let start = std::time::Instant::now();
     let out = f();
@@ -38,24 +52,44 @@ fn build_command(command: Command) -> Command {
                 .default_value("./dist"),
         )
         .arg(arg!(--"no-delete-missing" "Only adds new output files, never deletes old ones"))
+        .arg(arg!(--atomic "Builds into a staging directory & swaps it in only if the build succeeds").conflicts_with("no-delete-missing"))
+        .arg(arg!(--force "Deletes stale files from the output directory even if driver didn't create it"))
+        .arg(
+            arg!(--depfile <file> "Also writes a Makefile-style depfile listing every file the build read.")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(arg!(--"print-fingerprint" "Prints a hash of every output file, which is the same for identical builds"))
+        .arg(arg!(--"check-links" "Fails the build if any output HTML links to a page that isn't output"))
+        .arg(arg!(--stats "Prints how many queries were reused from the cache versus recomputed, and what's in the cache"))
+        .arg(arg!(--profile "Prints how much time each kind of query spent being produced"))
+        .arg(arg!(--"write-manifest" <file> "Writes every output file's path, hash & size to a JSON manifest").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--diff <manifest> "Prints what changed since the build that wrote this manifest").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"diff-json" "Prints the --diff as JSON instead").requires("diff"))
+        .arg(arg!(--sitemap "Also writes a sitemap.xml listing every output HTML page; needs --base-url"))
+        .arg(arg!(--"sitemap-lastmod" "Stamps every page in the sitemap with the time of the build").requires("sitemap"))
         .arg(arg!(<script> "The file to run").value_parser(value_parser!(PathBuf)))
         .arg(Arg::new("remaining").last(true).action(ArgAction::Append))
         .long_about("These arguments are provided as an array of strings to the file being run.")
 }
 
 fn real_main() -> driver_util::Result<()> {
-    tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| ["warn"].join(",").into()))
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_ansi(false)
-                .without_time(),
-        )
-        .init();
-
     let _ = include_str!("../Cargo.toml");
     let matches = command!()
         .arg(arg!(--cache <dir> "Where to save the cache.").value_parser(value_parser!(PathBuf)).default_value("./.driver"))
+        .arg(arg!(--root <dir> "Refuses to read anything outside this directory. Defaults to the script's directory, so scripts in a subdirectory (like scripts/build.js) need --root . to read the rest of the project").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--ignore <glob> "Leaves matching entries out of directory listings").action(ArgAction::Append))
+        .arg(arg!(--"no-gitignore" "Don't respect .gitignore files when listing directories"))
+        .arg(arg!(--"remotes-lock" <file> "Records every fetched URL & its content hash in this lockfile").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"module-extension" <ext> "Also lets scripts import files with this extension, as Javascript").action(ArgAction::Append))
+        .arg(arg!(--"minify-preserve" <tag> "Also leaves the contents of these elements alone when minifying HTML").action(ArgAction::Append))
+        .arg(arg!(--"post-process" <rule> "Runs a transform over every output matching a glob before writing it, like '*.html=minify_html'").action(ArgAction::Append))
+        .arg(arg!(--"words-per-minute" <wpm> "Reading speed used by reading_time()").value_parser(value_parser!(u32).range(1..)).default_value("200"))
+        .arg(arg!(--"wasm-opt-level" <level> "How hard to optimize WebAssembly modules, from 0 to 2").value_parser(value_parser!(u8).range(0..=2)).default_value("2"))
+        .arg(arg!(--"env-file" <file> "A .env file scripts can read variables from with env()").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"base-url" <url> "What the output directory is served at, like https://example.com").global(true))
+        .arg(arg!(--"trace-query" <key> "Logs what happens to this one query (as printed) & its dependencies"))
+        .arg(arg!(--locked "Errors if fetched content doesn't match the lockfile").requires("remotes-lock"))
+        .arg(arg!(--strict "Fails the build if anything logs a warning"))
         .subcommand(build_command(
             Command::new("run")
                 .long_about("Runs a Javascript file, writing all files it outputs")
@@ -64,25 +98,139 @@ fn real_main() -> driver_util::Result<()> {
             Command::new("watch")
                 .long_about("Runs a Javascript file, writing all files it outputs, then watches for changes to re-run the build.")
         ))
-        .subcommand(Command::new("print-graph").arg(arg!(--"with-outputs" "In addition to printing each dependency key, also print each dependency output")))
+        .subcommand(Command::new("init").about("Writes a starter project to build with `driver run build.js`.")
+            .arg(arg!([dir] "Where to write it").value_parser(value_parser!(PathBuf)).default_value("."))
+        )
+        .subcommand(Command::new("print-graph")
+            .arg(arg!(--"with-outputs" "In addition to printing each dependency key, also print each dependency output"))
+            .arg(arg!(--format <format> "How to print the graph; dot is for piping into Graphviz").value_parser(["text", "dot"]).default_value("text"))
+        )
+        .subcommand(Command::new("snapshot").about("Writes everything the database knows (besides blob contents) to a single file, for attaching to bug reports.")
+            .arg(arg!(<file> "The snapshot file").value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--inspect "Prints an existing snapshot instead of writing a new one"))
+        )
         .subcommand(Command::new("clean").about("Allows for cleaning the database and blob store.")
             .arg(arg!(--key <prefix> "Removes all keys starting with the given prefix from the database").action(ArgAction::Append))
             .arg(arg!(--db "Cleans the entire database"))
             .arg(arg!(--remotes "Cleans the remote cache"))
             .arg(arg!(--dist [dir] "Cleans the output directory.").value_parser(value_parser!(PathBuf)).default_value("./dist"))
+            .arg(arg!(--"cache-only" "Leaves the output directory alone"))
+            .arg(arg!(--force "Cleans the output directory even if driver didn't create it"))
+            .arg(arg!(--all "Deletes everything in the cache, including every blob").conflicts_with_all(["key", "db", "remotes", "gc"]))
             .arg(arg!(--gc "Keeps only blobs in the blob store that are referenced in either the db or remote cache"))
         )
         .get_matches();
 
+    let trace_query = matches.get_one::<String>("trace-query").cloned();
+    let mut filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| ["warn"].join(",").into());
+    if trace_query.is_some() {
+        filter = filter.add_directive(
+            format!("{}=info", driver_engine::TRACE_QUERY)
+                .parse()
+                .expect("valid directive"),
+        );
+    }
+    // Only filters what gets printed, so --strict sees every warning no matter what RUST_LOG is.
+    let strict = matches
+        .get_flag("strict")
+        .then(strict::WarningCollector::default);
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .without_time()
+                .with_filter(filter),
+        )
+        .with(
+            strict
+                .clone()
+                .map(|strict| strict.with_filter(LevelFilter::WARN)),
+        )
+        .init();
+
     let cache = matches
         .get_one::<PathBuf>("cache")
         .expect("--cache must be provided");
-    let options = driver_engine::Options::with_base_dir(cache);
+    // Only builds need to write to the cache, & checking creates it, which other commands (like
+    // `clean` on a cache that's already gone) shouldn't do as a side effect.
+    if matches!(matches.subcommand_name(), Some("run" | "watch")) {
+        fs::ensure_writable("--cache", cache)?;
+    }
+    let mut options = driver_engine::Options::with_base_dir(cache);
+    options.ignore_globs = matches
+        .get_many::<String>("ignore")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    options.respect_gitignore = !matches.get_flag("no-gitignore");
+    options.remotes_lock_path = matches.get_one::<PathBuf>("remotes-lock").cloned();
+    options.enforce_remotes_lock = matches.get_flag("locked");
+    options.trace_query = trace_query;
+    options.base_url = matches.get_one::<String>("base-url").cloned();
+    options.env_file = matches.get_one::<PathBuf>("env-file").cloned();
+    options.words_per_minute = *matches
+        .get_one::<u32>("words-per-minute")
+        .expect("--words-per-minute has a default");
+    options.wasm_opt_level = *matches
+        .get_one::<u8>("wasm-opt-level")
+        .expect("--wasm-opt-level has a default");
+    options.project_root = match matches.get_one::<PathBuf>("root") {
+        Some(root) => Some(root.clone()),
+        None => matches
+            .subcommand()
+            .and_then(|(_, sub_matches)| sub_matches.try_get_one::<PathBuf>("script").ok())
+            .flatten()
+            .map(|script| match script.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            }),
+    };
+    options.module_extensions.extend(
+        matches
+            .get_many::<String>("module-extension")
+            .unwrap_or_default()
+            .map(|ext| ext.trim_start_matches('.').to_string()),
+    );
+    options.minify_preserve_tags.extend(
+        matches
+            .get_many::<String>("minify-preserve")
+            .unwrap_or_default()
+            .cloned(),
+    );
+    for rule in matches
+        .get_many::<String>("post-process")
+        .unwrap_or_default()
+    {
+        let (glob, transform) = rule.split_once('=').ok_or_else(|| {
+            driver_util::Error::new(&format!(
+                "invalid --post-process \"{rule}\", expected <glob>=<transform>"
+            ))
+        })?;
+        options.post_process.push(driver_engine::PostProcessRule {
+            glob: glob.to_string(),
+            transform: transform.to_string(),
+        });
+    }
 
     if let Some(run_matches) = matches.subcommand_matches("run") {
+        // Checked before building, so a typo doesn't cost a whole build
+        let sitemap_base_url = run_matches
+            .get_flag("sitemap")
+            .then(|| {
+                options.base_url.clone().ok_or_else(|| {
+                    driver_util::Error::new("--sitemap needs --base-url to know where pages are")
+                })
+            })
+            .transpose()?;
+        let profiler = run_matches.get_flag("profile").then(Profiler::new);
         let root = time("restored database", || {
-            QueryContext::create_root(options, None)
-        });
+            let hooks = profiler.clone().map(|profiler| {
+                Box::new(profiler) as Box<dyn driver_engine::Hooks<QueryKey> + Send + Sync>
+            });
+            QueryContext::try_create_root(options, hooks)
+        })?;
+        interrupt::cancel_on_interrupt(root.cancellation().clone());
 
         let filename = run_matches
             .get_one::<PathBuf>("script")
@@ -90,9 +238,12 @@ fn real_main() -> driver_util::Result<()> {
         let dist = run_matches
             .get_one::<PathBuf>("dist")
             .expect("--dist must be provided");
+        fs::ensure_writable("--dist", dist)?;
         let write_options = fs::WriteOptions {
             output_path: dist.clone(),
             no_delete_missing: run_matches.get_flag("no-delete-missing"),
+            atomic: run_matches.get_flag("atomic"),
+            force: run_matches.get_flag("force"),
         };
         let args = run_matches
             .get_many::<String>("remaining")
@@ -102,20 +253,87 @@ fn real_main() -> driver_util::Result<()> {
         let output = time("ran query", || {
             future::block_on(fs::run(&root, filename.into(), args))
         });
+        if run_matches.get_flag("stats") {
+            println!("{}", root.hit_stats());
+            println!("{}", root.cache_stats()?);
+        }
+        if let Some(profiler) = &profiler {
+            print!("{}", profiler.report());
+        }
         match output {
-            Ok(output) => time("wrote output", || {
-                future::block_on(output.write(&root, &write_options))
-            })?,
+            Ok(mut output) => {
+                if let Some(base_url) = &sitemap_base_url {
+                    // Same time scripts see from build_time(), so the whole build agrees on it
+                    let lastmod = match run_matches.get_flag("sitemap-lastmod") {
+                        true => Some(jiff::Timestamp::try_from(root.build_time())?),
+                        false => None,
+                    };
+                    let sitemap = Sitemap::new(output.outputs(), base_url, lastmod);
+                    let blob = future::block_on(query(&root, sitemap))?;
+                    output.push_output("sitemap.xml".into(), blob);
+                }
+                if let Some(depfile) = run_matches.get_one::<PathBuf>("depfile") {
+                    output.write_depfile(&root, dist, depfile)?;
+                }
+                let write_manifest = run_matches.get_one::<PathBuf>("write-manifest");
+                let diff = run_matches.get_one::<PathBuf>("diff");
+                if write_manifest.is_some() || diff.is_some() {
+                    let manifest = Manifest::from_outputs(&root, output.outputs())?;
+                    if let Some(old) = diff {
+                        let diff = manifest.diff(&Manifest::read(old)?);
+                        if run_matches.get_flag("diff-json") {
+                            println!("{}", diff.to_json()?);
+                        } else {
+                            println!("{diff}");
+                        }
+                    }
+                    if let Some(path) = write_manifest {
+                        manifest.write(path)?;
+                    }
+                }
+                if run_matches.get_flag("print-fingerprint") {
+                    println!("fingerprint: {}", hex::encode(output.fingerprint()));
+                }
+                if run_matches.get_flag("check-links") {
+                    let dangling = time("checked links", || {
+                        future::block_on(check_links(&root, output.outputs()))
+                    })?;
+                    if !dangling.is_empty() {
+                        for link in &dangling {
+                            eprintln!("{link}");
+                        }
+                        time("saved database", || root.destroy_root())?;
+                        return Err(driver_util::Error::new(&format!(
+                            "found {} broken link(s)",
+                            dangling.len()
+                        )));
+                    }
+                }
+                time("wrote output", || {
+                    future::block_on(output.write(&root, &write_options))
+                })?
+            }
+            // Reported below, once whatever did finish is saved
+            Err(e) if e.is_cancelled() => {}
             Err(e) => eprintln!("{e}"),
         };
 
+        let cancelled = root.cancellation().check();
         time("saved database", || root.destroy_root())?;
+        cancelled?;
+        if let Some(strict) = &strict {
+            strict.check()?;
+        }
     } else if let Some(watch_matches) = matches.subcommand_matches("watch") {
-        let notifier = inotify::Inotify::init().expect("could not initialize inotify");
-        let watches = watches::WatchHooks::new(watches::Watches::new(notifier.watches()));
+        let mut watcher = watches::Watcher::new()?;
+        let profiler = watch_matches.get_flag("profile").then(Profiler::new);
         let root = time("restored database", || {
-            QueryContext::create_root(options, Some(Box::new(watches.clone())))
-        });
+            let hooks = profiler.clone().map(|profiler| {
+                Box::new(profiler) as Box<dyn driver_engine::Hooks<QueryKey> + Send + Sync>
+            });
+            QueryContext::try_create_root(options, hooks)
+        })?;
+        interrupt::cancel_on_interrupt(root.cancellation().clone());
 
         let filename = watch_matches
             .get_one::<PathBuf>("script")
@@ -123,44 +341,117 @@ fn real_main() -> driver_util::Result<()> {
         let dist = watch_matches
             .get_one::<PathBuf>("dist")
             .expect("--dist must be provided");
+        fs::ensure_writable("--dist", dist)?;
         let write_options = fs::WriteOptions {
             output_path: dist.clone(),
             no_delete_missing: watch_matches.get_flag("no-delete-missing"),
+            atomic: watch_matches.get_flag("atomic"),
+            force: watch_matches.get_flag("force"),
         };
-        let args = watch_matches
+        let args: Vec<&str> = watch_matches
             .get_many::<String>("remaining")
             .unwrap_or_default()
-            .map(|s| s.deref());
+            .map(|s| s.deref())
+            .collect();
 
-        let output = time("ran query", || {
-            future::block_on(fs::run(&root, filename.into(), args))
-        });
-        {
-            // Start watching all the files that were referenced in the query.
-            watches.lock().commit();
+        loop {
+            let output = time("ran query", || {
+                future::block_on(fs::run(&root, filename.into(), args.iter().copied()))
+            });
+            if watch_matches.get_flag("stats") {
+                println!("{}", root.hit_stats());
+                println!("{}", root.cache_stats()?);
+            }
+            if let Some(profiler) = &profiler {
+                print!("{}", profiler.report());
+            }
+            match output {
+                Ok(output) => {
+                    // Whatever got read this time is what to watch for next time. If the run
+                    // failed outright, we keep watching whatever we were before.
+                    watcher.update(output.input_files(&root));
+                    let written = time("wrote output", || {
+                        future::block_on(output.write(&root, &write_options))
+                    });
+                    if let Err(e) = written {
+                        eprintln!("{e}");
+                    }
+                }
+                // Reported once we stop watching, below
+                Err(e) if e.is_cancelled() => {}
+                Err(e) => eprintln!("{e}"),
+            };
+
+            println!("watching {} paths for changes", watcher.len());
+            if !watcher.wait(WATCH_DEBOUNCE, root.cancellation())? {
+                break;
+            }
+            root.new_revision();
+            // So `--stats` & `--profile` cover just the next build, not everything since we
+            // started watching
+            root.hit_stats().reset();
+            if let Some(profiler) = &profiler {
+                profiler.reset();
+            }
         }
-        match output {
-            Ok(output) => time("wrote output", || {
-                future::block_on(output.write(&root, &write_options))
-            })?,
-            Err(e) => eprintln!("{e}"),
-        };
 
-        // TODO: start reading from event stream that the notifier gives us. Need some way to
-        // invalidate ReadFile & ListDirectory keys in the context.
+        let cancelled = root.cancellation().check();
+        time("saved database", || root.destroy_root())?;
+        cancelled?;
+    } else if let Some(init_matches) = matches.subcommand_matches("init") {
+        let dir = init_matches
+            .get_one::<PathBuf>("dir")
+            .expect("[dir] has a default");
+        for path in init::init(dir)? {
+            println!("wrote {}", path.display());
+        }
     } else if let Some(print_matches) = matches.subcommand_matches("print-graph") {
         let root = time("restored database", || {
-            QueryContext::create_root(options, None)
-        });
-        if print_matches.get_flag("with-outputs") {
+            QueryContext::try_create_root(options, None)
+        })?;
+        let format = print_matches
+            .get_one::<String>("format")
+            .expect("--format has a default");
+        if format == "dot" {
+            println!("{}", root.db().display_dep_graph_dot());
+        } else if print_matches.get_flag("with-outputs") {
             println!("{}", root.db().display_dep_graph_with_outputs());
         } else {
             println!("{}", root.db().display_dep_graph());
         }
+    } else if let Some(snapshot_matches) = matches.subcommand_matches("snapshot") {
+        let file = snapshot_matches
+            .get_one::<PathBuf>("file")
+            .expect("<file> must be provided");
+        if snapshot_matches.get_flag("inspect") {
+            let snapshot = Snapshot::<QueryKey, QueryOutput>::read(file, &options.version)?;
+            println!("{snapshot}");
+        } else {
+            let root = time("restored database", || {
+                QueryContext::try_create_root(options, None)
+            })?;
+            root.db().snapshot(root.options()).write(file)?;
+            time("saved database", || root.destroy_root())?;
+        }
     } else if let Some(forget_matches) = matches.subcommand_matches("clean") {
+        let dist = forget_matches
+            .get_one::<PathBuf>("dist")
+            .filter(|_| !forget_matches.get_flag("cache-only"));
+        if forget_matches.get_flag("all") {
+            for path in options.remove_saved()? {
+                println!("removed {}", path.display());
+            }
+            if let Some(dist) = dist
+                && fs::remove_output_dir(dist, forget_matches.get_flag("force"))?
+            {
+                println!("removed {}", dist.display());
+            }
+            return Ok(());
+        }
+
         let root = time("restored database", || {
-            QueryContext::create_root(options, None)
-        });
+            QueryContext::try_create_root(options, None)
+        })?;
         if forget_matches.get_flag("db") {
             // Delete entire database
             root.db().clear();
@@ -174,12 +465,14 @@ fn real_main() -> driver_util::Result<()> {
             root.db().clear_remote();
         }
 
-        if let Some(dist) = forget_matches.get_one::<PathBuf>("dist") {
+        if let Some(dist) = dist {
             // Delete all keys with no parents (keys that we ran at the top level & produced output
             // from) from the database.
             root.db().remove_root_keys();
             // Then, actually delete the output directory.
-            std::fs::remove_dir_all(dist)?;
+            if fs::remove_output_dir(dist, forget_matches.get_flag("force"))? {
+                println!("removed {}", dist.display());
+            }
         }
 
         if forget_matches.get_flag("gc") {
@@ -0,0 +1,77 @@
+//! Support for `--strict`, where any warning logged during a build fails it. Lots of things only
+//! warn when they fall back to something worse (like a remote without freshness info), which is
+//! easy to miss in CI.
+
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// A [`Layer`] that keeps every warning (or error) event it sees.
+#[derive(Clone, Default)]
+pub struct WarningCollector(Arc<Mutex<Vec<String>>>);
+
+impl WarningCollector {
+    /// Errors with a summary of every warning seen so far, if there were any.
+    pub fn check(&self) -> driver_util::Result<()> {
+        let warnings = self.0.lock().unwrap();
+        if warnings.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!("--strict: build logged {} warning(s):", warnings.len());
+        for warning in warnings.iter() {
+            message.push_str("\n  ");
+            message.push_str(warning);
+        }
+        Err(driver_util::Error::new(&message))
+    }
+}
+
+impl<S: Subscriber> Layer<S> for WarningCollector {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // More verbose levels compare greater, so this is WARN & ERROR
+        if *event.metadata().level() > Level::WARN {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let warning = format!("{}: {}", event.metadata().target(), visitor.0);
+        self.0.lock().unwrap().push(warning);
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[test]
+    fn warning_fails_build() {
+        let collector = WarningCollector::default();
+        let subscriber = tracing_subscriber::registry().with(collector.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("not a problem");
+            assert!(collector.check().is_ok());
+            tracing::warn!(uri = "https://example.com", "no freshness info");
+        });
+
+        let err = collector.check().unwrap_err();
+        assert!(err.to_string().contains("no freshness info"), "{err}");
+        assert!(err.to_string().contains("https://example.com"), "{err}");
+    }
+}
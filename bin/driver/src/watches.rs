@@ -1,248 +1,128 @@
-use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
-
-use inotify::{WatchDescriptor, WatchMask};
-
-use driver_query_ssg::{HashKey, QueryContext, QueryKey};
-
-/// Represents a single watch of a path.
-struct Watch {
-    /// The inode that we're watching.
-    descriptor: WatchDescriptor,
-    /// Reference count for how many different consumers are watching this path. SHOULD be non-zero
-    /// (but isn't because NonZeroU32 is hard to use)
-    count: u32,
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+
+use driver_engine::CancellationToken;
+
+/// Everything we want to hear about, for both files & directories. Directories also report writes
+/// to the files in them, which is more than a directory listing cares about, but the worst that
+/// does is start a rebuild that finds nothing changed.
+const MASK: WatchMask = WatchMask::CLOSE_WRITE
+    .union(WatchMask::CREATE)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::MOVE_SELF);
+
+/// Watches the inputs of a build, so it can be re-run when they change.
+pub struct Watcher {
+    inotify: Inotify,
+    /// Every path we were asked to watch, along with what's actually watching it. Several paths
+    /// can share a descriptor, like when a file doesn't exist yet & we're watching its directory
+    /// for it to show up.
+    paths: HashMap<PathBuf, WatchDescriptor>,
 }
 
-/// Represents a set of watches that are currently active. That is, if we have an object of this
-/// type, then we also have an [`inotify::Watches`] that is watching the files described here.
-struct ActiveWatches {
-    /// The actual watches to keep in sync.
-    watches: inotify::Watches,
-    /// Reference count for how many paths are watching this descriptor.
-    descriptors: HashMap<WatchDescriptor, u32>,
-    paths: HashMap<PathBuf, Watch>,
-}
-
-impl ActiveWatches {
-    fn new(watches: inotify::Watches) -> Self {
-        Self {
-            watches,
-            descriptors: Default::default(),
+impl Watcher {
+    pub fn new() -> driver_util::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init()?,
             paths: Default::default(),
-        }
-    }
-
-    fn add_file(&mut self, path: PathBuf, count: u32) {
-        self.paths
-            .entry(path.clone())
-            .and_modify(|w| w.count += count)
-            .or_insert_with(|| {
-                let descriptor = self
-                    .watches
-                    .add(
-                        &path,
-                        WatchMask::CLOSE_WRITE | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF,
-                    )
-                    .expect("TODO");
-
-                *self.descriptors.entry(descriptor.clone()).or_insert(0) += 1;
-                Watch { descriptor, count }
-            });
-    }
-
-    fn add_directory(&mut self, path: PathBuf, count: u32) {
-        self.paths
-            .entry(path.clone())
-            .and_modify(|w| w.count += count)
-            .or_insert_with(|| {
-                let descriptor = self
-                    .watches
-                    .add(
-                        &path,
-                        WatchMask::CREATE
-                            | WatchMask::DELETE
-                            | WatchMask::MOVED_FROM
-                            | WatchMask::MOVED_TO
-                            | WatchMask::DELETE_SELF
-                            | WatchMask::MOVE_SELF,
-                    )
-                    .expect("TODO");
-
-                *self.descriptors.entry(descriptor.clone()).or_insert(0) += 1;
-                Watch { descriptor, count }
-            });
-    }
-
-    fn remove(&mut self, path: &PathBuf, count: u32) {
-        let watch = match self.paths.get_mut(path) {
-            Some(watch) => watch,
-            None => return,
-        };
-
-        if watch.count > count {
-            watch.count -= count;
-            return;
-        }
-
-        if count > watch.count {
-            panic!("tried to remove more times than we had {}", path.display());
-        }
-
-        // If removing the last reference, also remove the watch.
-        let descriptor = self.paths.remove(path).unwrap().descriptor;
-        self.remove_descriptor(descriptor);
-    }
-
-    fn remove_descriptor(&mut self, descriptor: WatchDescriptor) {
-        let count = self
-            .descriptors
-            .get_mut(&descriptor)
-            .expect("tried to get unknown descriptor");
-        if *count > 1 {
-            *count -= 1;
-            return;
-        }
-
-        self.descriptors.remove(&descriptor).unwrap();
-        self.watches.remove(descriptor).expect("TODO");
-    }
-}
-
-/// Represents a potentially out-of-date set of watches. Efficiently keeps track of the diff between
-/// the active watch set and the new desired watch set, which can be applied all at once.
-pub struct Watches {
-    active_watches: ActiveWatches,
-    pending_files: HashMap<PathBuf, i32>,
-    pending_directories: HashMap<PathBuf, i32>,
-}
-
-impl Watches {
-    pub fn new(watches: inotify::Watches) -> Self {
-        Self {
-            active_watches: ActiveWatches::new(watches),
-            pending_files: Default::default(),
-            pending_directories: Default::default(),
-        }
-    }
-
-    pub fn add_file(&mut self, path: PathBuf) {
-        *self.pending_files.entry(path).or_insert(0) += 1;
-    }
-
-    pub fn add_directory(&mut self, path: PathBuf) {
-        *self.pending_directories.entry(path).or_insert(0) += 1;
-    }
-
-    pub fn remove_file(&mut self, path: PathBuf) {
-        *self.pending_files.entry(path).or_insert(0) -= 1;
+        })
     }
 
-    pub fn remove_directory(&mut self, path: PathBuf) {
-        *self.pending_directories.entry(path).or_insert(0) -= 1;
+    /// How many paths are being watched.
+    pub fn len(&self) -> usize {
+        self.paths.len()
     }
 
-    pub fn commit(&mut self) {
-        let files = std::mem::take(&mut self.pending_files);
-        let directories = std::mem::take(&mut self.pending_directories);
-
-        for (file, count) in files {
-            if count > 0 {
-                self.active_watches
-                    .add_file(file, count.try_into().unwrap());
-            } else if count < 0 {
-                self.active_watches
-                    .remove(&file, (-count).try_into().unwrap());
+    /// Makes it so exactly `paths` are being watched. The inputs of a build can be different every
+    /// time it runs, so this should be called with all of them after each run.
+    pub fn update(&mut self, paths: BTreeSet<PathBuf>) {
+        let stale: Vec<_> = self
+            .paths
+            .keys()
+            .filter(|path| !paths.contains(*path))
+            .cloned()
+            .collect();
+        for path in stale {
+            let descriptor = self.paths.remove(&path).unwrap();
+            if !self.paths.values().any(|other| *other == descriptor) {
+                // Fails if the kernel already dropped the watch, which is fine
+                let _ = self.inotify.watches().remove(descriptor);
             }
         }
 
-        for (directory, count) in directories {
-            if count > 0 {
-                self.active_watches
-                    .add_directory(directory, count.try_into().unwrap());
-            } else if count < 0 {
-                self.active_watches
-                    .remove(&directory, (-count).try_into().unwrap());
+        for path in paths {
+            if self.paths.contains_key(&path) {
+                continue;
+            }
+            match self.add(&path) {
+                Ok(descriptor) => {
+                    self.paths.insert(path, descriptor);
+                }
+                Err(e) => eprintln!("not watching {}: {e}", path.display()),
             }
         }
     }
-}
-
-/// Wrapper around [`Watches`] for use in [`driver_engine::Hooks`]
-#[derive(Clone)]
-pub struct WatchHooks(Arc<Mutex<Watches>>);
-
-impl WatchHooks {
-    pub fn new(watches: Watches) -> Self {
-        Self(Arc::new(Mutex::new(watches)))
-    }
 
-    pub fn lock(&self) -> MutexGuard<'_, Watches> {
-        self.0.lock().unwrap()
+    /// Watches `path`, or if it doesn't exist, the directory it would be created in.
+    fn add(&self, path: &Path) -> std::io::Result<WatchDescriptor> {
+        match self.inotify.watches().add(path, MASK) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => match path.parent() {
+                Some(parent) => self.inotify.watches().add(parent, MASK),
+                None => Err(e),
+            },
+            otherwise => otherwise,
+        }
     }
-}
 
-impl driver_engine::Hooks<QueryKey> for WatchHooks {
-    fn on_compute(
-        &self,
-        _ctx: &QueryContext,
-        _key: QueryKey,
-        _old_deps: HashSet<HashKey>,
-        _new_deps: HashSet<HashKey>,
-    ) {
-        /*
-                let only_in_old = old_deps.difference(&new_deps);
-                let only_in_new = new_deps.difference(&old_deps);
+    /// Blocks until something being watched changes, then until nothing else has changed for
+    /// `debounce`, so saving a bunch of files at once only causes one rebuild. Returns `false`
+    /// without waiting for that if `cancellation` gets cancelled.
+    pub fn wait(
+        &mut self,
+        debounce: Duration,
+        cancellation: &CancellationToken,
+    ) -> driver_util::Result<bool> {
+        let mut buffer = [0; 4096];
+        let mut changed = false;
+        loop {
+            if cancellation.is_cancelled() {
+                return Ok(false);
+            }
 
-                let mut this = self.0.lock().unwrap();
-                for key in only_in_old {
-                    this.recursive_remove(ctx, key);
+            let mut fresh = false;
+            loop {
+                let events = match self.inotify.read_events(&mut buffer) {
+                    Ok(events) => events,
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e.into()),
+                };
+                let mut any = false;
+                for event in events {
+                    any = true;
+                    if event.mask.contains(EventMask::IGNORED) {
+                        // The kernel stopped watching this (say, because the file got deleted),
+                        // so forget about it. Whatever it was gets watched again after the
+                        // rebuild, if it's still an input.
+                        self.paths.retain(|_, descriptor| *descriptor != event.wd);
+                    }
                 }
-
-                for key in only_in_new {
-                    ctx.db().get(*key, async |entry| {
-                        match entry {
-                            Some((_, QueryKey::ReadFile(file), _)) => {
-                                this.add_file(file.0.clone());
-                            }
-                            Some((_, QueryKey::ListDirectory(directory), _)) => {
-                                this.add_directory(directory.0.clone());
-                            }
-                            _otherwise => {
-                                // If the query is newly run, it will have already added the watches thru this hook.
-                            }
-                        }
-                    })
+                if !any {
+                    break;
                 }
-        */
-        todo!()
-    }
-}
+                fresh = true;
+            }
 
-impl Watches {
-    /// Removes all watches under the given key.
-    fn recursive_remove(&mut self, _ctx: &QueryContext, _key: &HashKey) {
-        /*
-                match key {
-                    QueryKey::ReadFile(file) => {
-                        self.remove_file(file.0.clone());
-                    }
-                    QueryKey::ListDirectory(directory) => {
-                        self.remove_directory(directory.0.clone());
-                    }
-                    otherwise => {
-                        for dep in ctx
-                            .db()
-                            .dependencies::<Vec<_>>(otherwise)
-                            .into_iter()
-                            .flatten()
-                        {
-                            self.recursive_remove(ctx, &dep);
-                        }
-                    }
-                }
-        */
-        todo!()
+            if changed && !fresh {
+                return Ok(true);
+            }
+            changed |= fresh;
+            std::thread::sleep(debounce);
+        }
     }
 }
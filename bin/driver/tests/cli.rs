@@ -0,0 +1,59 @@
+//! Runs the actual binary, the same way CI would.
+
+use std::path::Path;
+use std::process::Command;
+
+fn driver(dir: &Path, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_driver"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "driver {args:?} failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn writes_to_the_given_dirs() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::write(
+        dir.join("index.js"),
+        r#"import { store, write_output } from "driver";
+write_output("index.html", store("hello"));
+"#,
+    )
+    .unwrap();
+
+    driver(
+        dir,
+        &[
+            "--cache", "ci/cache", "run", "--dist", "ci/dist", "index.js",
+        ],
+    );
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("ci/dist/index.html")).unwrap(),
+        "hello"
+    );
+    assert!(std::fs::read_dir(dir.join("ci/cache")).unwrap().count() > 0);
+    // Nothing went to the defaults
+    assert!(!dir.join("dist").exists());
+    assert!(!dir.join(".driver").exists());
+}
+
+#[test]
+fn cleaning_doesnt_create_the_cache() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+
+    driver(
+        dir,
+        &["--cache", "ci/cache", "clean", "--all", "--cache-only"],
+    );
+
+    assert!(!dir.join("ci").exists());
+}
@@ -8,6 +8,10 @@ declare module "driver" {
    * 4. Call any transformation that accepts a `StoreObject`.
    */
   interface StoreObject {
+    /**
+     * The sha256 of the contents, as lowercase hex. Handy for cache-busting filenames, like
+     * `style.${css.hash().slice(0, 8)}.css`.
+     */
     hash(): string;
     data(): Uint8Array;
     toString(): string;
@@ -26,23 +30,142 @@ declare module "driver" {
 
   ////////// 2 //////////
   // NOTE: All paths are relative to the PROJECT ROOT, where the driver binary is run!!
-  /** Given a file, gets its contents */
+  // But only files under the directory of the script passed to `driver run` (or `--root`, if
+  // given) can be read. A script at `scripts/build.js` can't read `content/x.md` unless run with
+  // `--root .`.
+  /**
+   * Given a file, gets its contents. If the file doesn't exist, the error thrown has a `code` of
+   * `"ENOENT"`, so falling back to something else can be told apart from other failures.
+   */
   function read_file(filename: string): Promise<StoreObject>;
   /** Given a directory, lists all the files/subdirectories in it. */
   function list_directory(dirname: string): Promise<string[]>;
+  /** Like `list_directory()`, but lists every file in every subdirectory too, sorted. */
+  function walk_directory(dirname: string): Promise<string[]>;
+  /** Like `list_directory()`, but calls `callback` with each entry instead of returning a big
+   * array. Return `false` from the callback to stop early. Throws if the directory changes while
+   * it's being listed. */
+  function for_each_in_directory(
+    dirname: string,
+    callback: (entry: string) => boolean | void,
+  ): Promise<void>;
+  /**
+   * Finds every path matching a glob like `content/**/*.md`, sorted. `*` doesn't match `/`, but
+   * `**` matches any number of directories. Adding or removing a matching file reruns the caller.
+   */
+  function glob(pattern: string): Promise<string[]>;
   /** Returns the type of a local file. For use in determining how to operate on the entries of
    * `list_directory()`. */
   function file_type(name: string): "file" | "dir" | "symlink" | "unknown";
 
+  /**
+   * Gets a variable from the `.env` file passed with `--env-file`, falling back to the process's
+   * environment, or `undefined` if it isn't set in either. The values are never saved in the
+   * cache.
+   */
+  function env(name: string): Promise<string | undefined>;
+
+  /**
+   * When this build started, as an RFC 3339 timestamp. Every script in a build sees the same
+   * time. Calling this doesn't make a script re-run, so a script that was cached keeps the time
+   * from whenever it last ran.
+   *
+   * NOTE: the time itself isn't saved in the cache. Each run of driver is a new build with a new
+   * time, which only the scripts that re-run (because something they read changed) will see.
+   */
+  function build_time(): string;
+
   ////////// 3 //////////
   /** Given a URL, gets its contents. Cached according to remote headers. */
   function get_url(url: string): Promise<StoreObject>;
+  /** Like `get_url()`, but throws unless the contents have the given sha256 (as hex). */
+  function get_url_verified(url: string, sha256: string): Promise<StoreObject>;
 
   ////////// 4 //////////
-  /** Converts a markdown string into an HTML string. */
-  function markdown_to_html(md: StoreObject): Promise<StoreObject>;
-  /** Minifies a given HTML string. */
-  function minify_html(html: StoreObject): Promise<StoreObject>;
+  type MarkdownOptions = {
+    strikethrough?: boolean;
+    table?: boolean;
+    autolink?: boolean;
+    tasklist?: boolean;
+    superscript?: boolean;
+    subscript?: boolean;
+    footnotes?: boolean;
+    math_dollars?: boolean;
+    shortcodes?: boolean;
+    underline?: boolean;
+    spoiler?: boolean;
+    subtext?: boolean;
+    highlight?: boolean;
+    block_directive?: boolean;
+    smart?: boolean;
+    hardbreaks?: boolean;
+  };
+  /**
+   * Converts a markdown string into an HTML string. Any options given override the defaults;
+   * unknown options throw.
+   *
+   * Code fences with a language get highlighted inside a `<pre class="syntax-highlighting">`, using
+   * class names rather than inline colors. That means the theme (including any dark mode variant)
+   * comes entirely from the site's own stylesheet, and changing it never re-renders anything.
+   */
+  function markdown_to_html(
+    md: StoreObject,
+    options?: MarkdownOptions,
+  ): Promise<StoreObject>;
+  /**
+   * Minifies a given HTML string. By default comments & closing tags are kept, and inline CSS/JS
+   * is minified; any options given override that. Unknown options throw.
+   */
+  function minify_html(
+    html: StoreObject,
+    options?: {
+      keep_closing_tags?: boolean;
+      keep_comments?: boolean;
+      minify_css?: boolean;
+      minify_js?: boolean;
+    },
+  ): Promise<StoreObject>;
+  /**
+   * Compiles SCSS into CSS. `@import`s & `@use`s are relative to the project root, and editing any
+   * of them recompiles.
+   */
+  function compile_scss(scss: StoreObject): Promise<StoreObject>;
+  /**
+   * Joins objects end-to-end, like for bundling CSS partials into one file. Works on the raw
+   * bytes, so nothing has to be text.
+   */
+  function concat(objects: StoreObject[]): Promise<StoreObject>;
+  /**
+   * Shrinks a WebAssembly module, throwing if it isn't valid. How hard it tries is set by
+   * `--wasm-opt-level`.
+   */
+  function optimize_wasm(wasm: StoreObject): Promise<StoreObject>;
+  /**
+   * Counts the words in some HTML or markdown, and estimates how many minutes it takes to read
+   * (at `--words-per-minute`, default 200). Chinese & Japanese count one word per character.
+   */
+  function reading_time(
+    text: StoreObject,
+  ): Promise<{ words: number; minutes: number }>;
+  /** Parses a JSON object. Objects, arrays, numbers, strings, booleans & null all come out as
+   * you'd expect. */
+  function parse_json(json: StoreObject): Promise<Arg>;
+  /**
+   * Like `JSON.stringify()`, except it throws on store objects/images. By default it also throws
+   * on `NaN`/`Infinity`, so a bad calculation can't quietly end up in the output; pass
+   * `{ non_finite: "null" }` to write `null` for them instead, like `JSON.stringify()` does.
+   */
+  function to_json(
+    value: Arg,
+    options?: { non_finite?: "error" | "null" },
+  ): StoreObject;
+  /**
+   * Splits the front matter off the top of a markdown file. `---` fences are parsed as YAML, and
+   * `+++` fences as TOML. If there isn't any, `data` is empty and `body` is `contents` unchanged.
+   */
+  function parse_front_matter(
+    contents: StoreObject,
+  ): Promise<{ data: { [key: string]: Arg }; body: StoreObject }>;
 
   type ImageFormat = "jpeg" | "jxl" | "png" | "webp";
   type ImageSize = { width: number; height: number };
@@ -75,12 +198,15 @@ declare module "driver" {
     | "sinc"    
     | "bilinear";
 
-  /** Converts an image into a different size/format. */
+  /**
+   * Converts an image into a different size/format. If only one of `width`/`height` is given,
+   * the other is picked to keep the aspect ratio.
+   */
   function convert_image(
     image: StoreImage,
     opts: {
       format?: ImageFormat;
-      size?: ImageSize;
+      size?: { width: number; height?: number } | { width?: number; height: number };
       fit?: "fill" | "contain" | "cover";
       encoder_options?: EncoderOptions;
       resize_method?: ResizeMethod;
@@ -115,11 +241,28 @@ declare module "driver" {
     filename: string,
     arg: { [key in string]?: Arg },
   ): Promise<Arg>;
+  /**
+   * Like `run_tera()`, but for a template that's already in the store, like one fetched with
+   * `get_url()`. Changing either the template or `arg` renders it again.
+   */
+  function render_tera(
+    template: StoreObject,
+    arg: { [key in string]?: Arg },
+  ): Promise<Arg>;
 
 /**
    * Writes an object from the store to a path relative to the build directory.
    */
   function write_output(pathname: string, content: StoreObject): void;
+
+  /**
+   * Picks one output, by name, out of something that produced several at once. Throws if there's
+   * no output with that name.
+   */
+  function pick_output(
+    outputs: { [key in string]?: StoreObject },
+    name: string,
+  ): StoreObject;
 }
 
 declare global {
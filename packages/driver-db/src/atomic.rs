@@ -0,0 +1,53 @@
+use std::path::Path;
+
+/// Has `f` write to a temporary file next to `path`, then moves it into place. Renames within a
+/// directory are atomic, so if we die partway through, `path` is left exactly as it was instead of
+/// half-written.
+pub(crate) fn write(
+    path: &Path,
+    f: impl FnOnce(&mut std::fs::File) -> driver_util::Result<()>,
+) -> driver_util::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| driver_util::Error::new(&format!("{} has no parent", path.display())))?;
+    // A bare filename (like a lockfile given on the command line) is in the current directory
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    f(file.as_file_mut())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Whether `path` looks like one of the temporary files [`write`] makes, left behind by a crash.
+pub(crate) fn is_leftover(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b".tmp"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn failed_write_keeps_old_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.zst");
+        write(&path, |file| Ok(file.write_all(b"old")?)).unwrap();
+
+        let result = write(&path, |file| {
+            file.write_all(b"half of the new")?;
+            Err(driver_util::Error::new("died partway through"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        // And nothing's left lying around
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -1,15 +1,33 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use sha2::Digest as _;
 
-use crate::Options;
+use crate::{Options, atomic};
 use driver_util::{Blob, SerializedMap};
 
 /// A store for all strings/blobs that would otherwise be too large to persist to disk multiple
 /// times. "Uniquely" keyed by the hashes of the strings/blobs it stores.
-#[derive(Debug, Default, PartialEq)]
+///
+/// Every blob lives on disk, so the in-memory copies are just a cache: once there's more than
+/// [`Options::blob_cache_bytes`] of them, the least recently used ones get dropped, and are read
+/// back in if anyone asks for them again.
+#[derive(Debug, Default)]
 pub struct Blobs {
     cache: SerializedMap<Blob, Vec<u8>>,
+    usage: Mutex<Usage>,
+}
+
+/// Roughly how much memory `Blobs::cache` is using, and what in it was used most recently.
+#[derive(Debug, Default)]
+struct Usage {
+    bytes: usize,
+    clock: u64,
+    last_used: HashMap<Blob, u64>,
+    /// The reverse of `last_used`, so the oldest entry is always first.
+    by_age: BTreeMap<u64, Blob>,
 }
 
 impl Blobs {
@@ -41,26 +59,61 @@ impl Blobs {
             // TODO: should we use async_fs here, or is our existing threadpool enough?
             // Right now I don't want to color all the functions, so let's hope the threadpool is
             // enough lol.
-            std::fs::write(&filename, &contents)?;
+            atomic::write(&filename, |file| Ok(file.write_all(&contents)?))?;
         }
 
         // Then, we insert the file
-        let _ = self.cache.insert_sync(blob.clone(), contents);
+        let len = contents.len();
+        let inserted = self.cache.insert_sync(blob.clone(), contents).is_ok();
+        self.used(options, &blob, inserted.then_some(len));
         Ok(())
     }
 
     /// This will return an error if the file doesn't exist, because the only way we should have
     /// access to blobs is by having created a file beforehand.
     pub fn load(&self, options: &Options, blob: Blob) -> driver_util::Result<Vec<u8>> {
-        Ok(match self.cache.entry_sync(blob.clone()) {
+        let (value, inserted) = match self.cache.entry_sync(blob.clone()) {
             scc::hash_map::Entry::Vacant(entry) => {
                 let filename = self.blob_filename(options, &blob);
                 let value = std::fs::read(&filename)?;
                 let _ = entry.insert_entry(value.clone());
-                value
+                (value, true)
             }
-            scc::hash_map::Entry::Occupied(entry) => entry.get().clone(),
-        })
+            scc::hash_map::Entry::Occupied(entry) => (entry.get().clone(), false),
+        };
+        self.used(options, &blob, inserted.then_some(value.len()));
+        Ok(value)
+    }
+
+    /// Marks `blob` as just used, `added` being how big it is if it was just put in the cache.
+    /// Then, evicts whatever was used longest ago until we're back under budget.
+    fn used(&self, options: &Options, blob: &Blob, added: Option<usize>) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.clock += 1;
+        let now = usage.clock;
+        if let Some(before) = usage.last_used.insert(blob.clone(), now) {
+            usage.by_age.remove(&before);
+        }
+        usage.by_age.insert(now, blob.clone());
+        usage.bytes += added.unwrap_or_default();
+
+        while usage.bytes > options.blob_cache_bytes {
+            let Some((_, oldest)) = usage.by_age.pop_first() else {
+                break;
+            };
+            usage.last_used.remove(&oldest);
+            if let Some((_, contents)) = self.cache.remove_sync(&oldest) {
+                usage.bytes -= contents.len();
+            }
+        }
+    }
+
+    /// Streams the contents of a blob straight off the disk, for when it's too big to comfortably
+    /// hold in memory all at once. Every stored blob has a file, so this never needs to look at
+    /// what's cached in memory.
+    pub fn open(&self, options: &Options, blob: &Blob) -> driver_util::Result<impl std::io::Read> {
+        let file = std::fs::File::open(self.blob_filename(options, blob))?;
+        Ok(std::io::BufReader::new(file))
     }
 
     /// # Safety
@@ -92,7 +145,14 @@ impl Blobs {
         if std::fs::exists(output_filename)? {
             std::fs::remove_file(output_filename)?;
         }
-        std::fs::hard_link(&input_filename, output_filename)?;
+        match std::fs::hard_link(&input_filename, output_filename) {
+            // Can't hardlink to another filesystem, so stream a copy over instead
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                let mut output = std::fs::File::create(output_filename)?;
+                std::io::copy(&mut self.open(options, blob)?, &mut output)?;
+            }
+            result => result?,
+        }
         Ok(())
     }
 
@@ -109,11 +169,16 @@ impl Blobs {
         f: impl Fn(&Blob) -> bool,
     ) -> driver_util::Result<()> {
         self.cache.clear_sync();
+        *self.usage.lock().unwrap() = Usage::default();
 
         // Read from the filesystem to get a list of all possible blobs
         for file in std::fs::read_dir(&options.blobs_path)? {
             let file = file?;
             let path = file.path();
+            if atomic::is_leftover(&path) {
+                std::fs::remove_file(path)?;
+                continue;
+            }
             let hash = path.file_name().expect("blob didn't have filename?");
             let hash: [u8; 32] = hex::FromHex::from_hex(hash.as_encoded_bytes())?;
             // SAFETY: Object was read from filesystem
@@ -128,3 +193,50 @@ impl Blobs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Read as _;
+
+    use super::*;
+
+    #[test]
+    fn open_streams_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(dir.path());
+        std::fs::create_dir_all(&options.blobs_path).unwrap();
+
+        let blobs = Blobs::new();
+        let contents = vec![7u8; 1 << 20];
+        let blob = blobs.store(&options, contents.clone()).unwrap();
+
+        let mut read = Vec::new();
+        blobs
+            .open(&options, &blob)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, contents);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = Options::with_base_dir(dir.path());
+        options.blob_cache_bytes = 25;
+        std::fs::create_dir_all(&options.blobs_path).unwrap();
+
+        let blobs = Blobs::new();
+        let first = blobs.store(&options, vec![1; 10]).unwrap();
+        let second = blobs.store(&options, vec![2; 10]).unwrap();
+        let third = blobs.store(&options, vec![3; 10]).unwrap();
+
+        assert!(!blobs.cache.contains_sync(&first));
+        assert!(blobs.cache.contains_sync(&second));
+        assert!(blobs.cache.contains_sync(&third));
+        // Still on disk though
+        assert_eq!(blobs.load(&options, first.clone()).unwrap(), vec![1; 10]);
+        assert!(blobs.cache.contains_sync(&first));
+        assert!(!blobs.cache.contains_sync(&second));
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// What's in the cache, for figuring out why it's as big as it is. Unlike [`crate::HitStats`],
+/// this covers everything saved, not just what the current run touched.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// How many cached queries there are of each kind.
+    pub kinds: BTreeMap<&'static str, usize>,
+    /// How many queries have dependencies recorded.
+    pub nodes: usize,
+    /// How many dependencies are recorded, across all queries.
+    pub edges: usize,
+    /// How many blobs are in the blob store.
+    pub objects: usize,
+    /// How much space those blobs take up on disk, in bytes.
+    pub object_bytes: u64,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let queries: usize = self.kinds.values().sum();
+        writeln!(
+            f,
+            "{queries} cached queries, {} with dependencies ({} edges)",
+            self.nodes, self.edges
+        )?;
+        for (kind, count) in self.kinds.iter() {
+            writeln!(f, "\t{kind}: {count}")?;
+        }
+        write!(
+            f,
+            "{} objects, {} bytes on disk",
+            self.objects, self.object_bytes
+        )
+    }
+}
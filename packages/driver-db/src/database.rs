@@ -1,8 +1,8 @@
 use std::collections::{BTreeSet, HashSet};
 use std::fmt::Display;
 use std::hash::Hash;
-use std::io::Read;
 use std::ops::Deref;
+use std::path::Path;
 use std::sync::{
     Arc,
     atomic::{AtomicUsize, Ordering},
@@ -11,13 +11,17 @@ use std::sync::{
 use scc::hash_map::Entry as SccEntry;
 use serde::{Deserialize, Serialize};
 
+use crate::dep_graph::{DotGraph, TextGraph};
 use crate::hashed_key::Hashed;
-use crate::{Blob, Blobs, Options, RemoteBlobs};
-use driver_util::SerializedMap;
+use crate::{
+    Blob, Blobs, CacheStats, HitStats, Options, RemoteBlobs, RemoteLock, Snapshot, SnapshotEntry,
+    atomic, versioned,
+};
+use driver_util::{BlobTrace as _, SerializedMap};
 
 /// Tracks the range [changed_at, verified_at], to confirm the value is corresponds to is the same
 /// for that entire range of revisions.
-#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Revision {
     /// The revision at which we've executed a query and noticed that the value has changed.
     pub changed_at: usize,
@@ -65,6 +69,9 @@ struct CacheEntry<Key, Output> {
 pub struct Core<Key, Output> {
     #[serde(skip)]
     pub revision: AtomicUsize,
+    /// How well the cache did this run.
+    #[serde(skip)]
+    pub hit_stats: HitStats,
 
     /// Used to check that, when a `LogicalValue::Computing` is inserted/taken out, we get the same
     /// one back. We do this because we can't compare the `oneshot::Receiver`s directly.
@@ -79,6 +86,7 @@ impl<Key, Output> Default for Core<Key, Output> {
     fn default() -> Self {
         Self {
             revision: Default::default(),
+            hit_stats: Default::default(),
             with_entry_nonce: Default::default(),
             cache: Default::default(),
             dep_graph: Default::default(),
@@ -121,6 +129,41 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Core<Key, Output> {
         Some(deps.get().iter().cloned().collect())
     }
 
+    /// The keys that directly depend on `child`. There's no reverse index, so this looks at every
+    /// edge in the graph; fine for one-off questions like "what does changing this file affect?",
+    /// but not for calling in a loop.
+    pub fn dependents<T: FromIterator<Hashed<Key>>>(&self, child: &Hashed<Key>) -> T {
+        let mut parents = Vec::new();
+        self.dep_graph.iter_sync(|parent, deps| {
+            if deps.contains(child) {
+                parents.push(*parent);
+            }
+            true
+        });
+        parents.into_iter().collect()
+    }
+
+    /// Finds all keys that the given key (transitively) depends on, including itself. Entries that
+    /// are currently being computed are still included, since the key itself never changes.
+    pub fn transitive_keys(&self, root: Hashed<Key>) -> Vec<Arc<Key>> {
+        let mut seen = HashSet::from([root]);
+        let mut stack = vec![root];
+        let mut keys = Vec::new();
+        while let Some(hashed) = stack.pop() {
+            if let Some(entry) = self.cache.get_sync(&hashed) {
+                keys.push(entry.get().key.clone());
+            }
+            if let Some(deps) = self.dep_graph.get_sync(&hashed) {
+                for dep in deps.get().iter() {
+                    if seen.insert(*dep) {
+                        stack.push(*dep);
+                    }
+                }
+            }
+        }
+        keys
+    }
+
     /// Running this acquires a lock on the given entry, meaning the current task will suspend
     /// until the entry is unlocked by the task that currently has it acquired. This is necessary
     /// for us to run each query exactly once per revision, otherwise we could be running the same
@@ -243,6 +286,19 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Core<Key, Output> {
         }).unwrap_or_else(|| panic!("got None after computing value; expected LogicalValue::Computing"));
     }
 
+    /// Gets the value associated with an entry, but only if it's already been verified at (or
+    /// after) the given revision. Doesn't take the entry lock; if someone else is holding it, this
+    /// just returns `None` and the caller should go the slow way.
+    pub fn get_verified(&self, key: &Key, revision: usize) -> Option<(Hashed<Key>, Output)> {
+        let hashed = Hashed::new(key);
+        match &self.cache.get_sync(&hashed)?.get().value {
+            LogicalValue::Materialized(value) if value.revision.verified_at >= revision => {
+                Some((hashed, value.value.clone()))
+            }
+            _ => None,
+        }
+    }
+
     /// Gets the value associated with an entry.
     ///
     /// SHOULD only be used to compute diffs between past known values and queried values; SHOULD
@@ -340,16 +396,17 @@ impl<Output: driver_util::Output> Entry<Output> {
 
 impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
     pub fn save(self, options: &Options) -> driver_util::Result<()> {
+        // Volatile keys are meant to be re-run every time anyways, so don't bother keeping them
+        // around. Anything depending on them will see them as missing & re-run too, as desired.
+        self.cache.retain_sync(|_, entry| !entry.key.is_volatile());
+
         std::fs::create_dir_all(
             options
                 .cache_path
                 .parent()
                 .ok_or_else(|| driver_util::Error::new("invalid cache path"))?,
         )?;
-        let file = std::fs::File::create(&options.cache_path)?;
-        let file = zstd::Encoder::new(file, 1)?;
-        let file = postcard::to_io(&self.core, file)?;
-        file.finish()?;
+        versioned::write(&options.cache_path, &options.version, &self.core)?;
 
         // TODO: allow saving two files concurrently with async
         std::fs::create_dir_all(
@@ -358,55 +415,88 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
                 .parent()
                 .ok_or_else(|| driver_util::Error::new("invalid remotes path"))?,
         )?;
-        let file = std::fs::File::create(&options.remotes_path)?;
-        let file = zstd::Encoder::new(file, 1)?;
-        let file = postcard::to_io(&self.remotes, file)?;
-        file.finish()?;
+        versioned::write(&options.remotes_path, &options.version, &self.remotes)?;
+
+        // When enforcing, the lock is the source of truth, so it only ever changes on purpose
+        if let Some(lock_path) = &options.remotes_lock_path
+            && !options.enforce_remotes_lock
+        {
+            RemoteLock::from_remotes(&self.remotes).write(lock_path)?;
+        }
 
         // self.blobs are already saved as part of normal operation
         Ok(())
     }
 
-    pub fn restore(options: &Options) -> Self {
-        std::fs::create_dir_all(&options.blobs_path)
-            .expect("could not create/read object directory");
+    /// Restores whatever was saved last time. Nothing having been saved yet is fine, and gives an
+    /// empty database; anything that was saved but can't be read is reported, then thrown out.
+    ///
+    /// A missing object directory is treated as an empty object store: the saved entries are kept,
+    /// and any that point at blobs that are gone now fail when those blobs are read.
+    ///
+    /// Errors if the object directory can't be created, or if we're enforcing a lock that can't be
+    /// read, since it's better to stop than to silently not enforce anything.
+    pub fn restore(options: &Options) -> driver_util::Result<Self> {
+        if !options.blobs_path.is_dir() && options.cache_path.exists() {
+            tracing::warn!(
+                "{} is missing; cached outputs that point at objects in it will fail to load \
+                 until they're cleaned",
+                options.blobs_path.display()
+            );
+        }
+        std::fs::create_dir_all(&options.blobs_path).map_err(|err| {
+            driver_util::Error::new(&format!(
+                "could not create object directory {}: {}",
+                options.blobs_path.display(),
+                err
+            ))
+        })?;
         let blobs = Blobs::new();
 
-        let core = (|| {
-            let file = std::fs::File::open(&options.cache_path)?;
-            let mut file = zstd::Decoder::new(file)?;
-            let mut bytes = Vec::<u8>::new();
-            file.read_to_end(&mut bytes)?;
-            let core: Core<Key, Output> = postcard::from_bytes(&bytes)?;
-            driver_util::Result::Ok(core)
-        })()
-        .unwrap_or_else(|err| {
-            eprintln!("error restoring {}: {}", options.cache_path.display(), err);
-            Default::default()
-        });
-
+        let core = restore_file::<Core<Key, Output>>(&options.cache_path, options);
         // TODO: allow restoring from both files concurrently
-        let remotes = (|| {
-            let file = std::fs::File::open(&options.remotes_path)?;
-            let mut file = zstd::Decoder::new(file)?;
-            let mut bytes = Vec::<u8>::new();
-            file.read_to_end(&mut bytes)?;
-            let remotes: RemoteBlobs = postcard::from_bytes(&bytes)?;
-            driver_util::Result::Ok(remotes)
-        })()
-        .unwrap_or_else(|err| {
-            eprintln!(
-                "error restoring {}: {}",
-                options.remotes_path.display(),
-                err
-            );
-            Default::default()
-        });
+        let mut remotes = restore_file::<RemoteBlobs>(&options.remotes_path, options);
+
+        if options.enforce_remotes_lock
+            && let Some(lock_path) = &options.remotes_lock_path
+        {
+            let lock = RemoteLock::read(lock_path).map_err(|err| {
+                driver_util::Error::new(&format!("error reading {}: {}", lock_path.display(), err))
+            })?;
+            remotes.lock = Some(lock);
+        }
 
-        Self {
+        Ok(Self {
             core,
             remotes,
             blobs,
+        })
+    }
+}
+
+/// Reads one of the files written by [`Database::save`], or the default if there isn't one or it
+/// can't be used.
+fn restore_file<T: Default + for<'de> Deserialize<'de>>(path: &Path, options: &Options) -> T {
+    if !path.exists() {
+        return T::default();
+    }
+    match versioned::read_compatible(path, &options.version) {
+        Ok(Ok(value)) => value,
+        // Expected right after upgrading, & everything just gets rebuilt, so this isn't worth
+        // failing `--strict` over
+        Ok(Err(written_by)) => {
+            tracing::info!(
+                "{} was written by driver {written_by}, which is incompatible with {}; starting \
+                 fresh",
+                path.display(),
+                options.version
+            );
+            T::default()
+        }
+        // Whereas this means the file got corrupted somehow, which is worth knowing about
+        Err(err) => {
+            tracing::warn!("error restoring {}: {err}; starting fresh", path.display());
+            T::default()
         }
     }
 }
@@ -419,6 +509,12 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
         self.dep_graph.clear_sync();
     }
 
+    /// Removes the given keys. Anything that depended on them will see them as missing & re-run.
+    pub fn remove_keys(&self, keys: &HashSet<Hashed<Key>>) {
+        self.cache.retain_sync(|key, _| !keys.contains(key));
+        self.dep_graph.retain_sync(|key, _| !keys.contains(key));
+    }
+
     pub fn remove_keys_matching_prefixes(&self, _prefixes: &[&String]) {
         /*
         let mut keys_to_remove = vec![];
@@ -441,6 +537,69 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
         todo!()
     }
 
+    /// Every materialized entry, sorted by key. Entries still being computed are left out.
+    fn entries(&self) -> Vec<SnapshotEntry<Key, Output>> {
+        let mut entries = Vec::new();
+        self.cache.iter_sync(|hashed, entry| {
+            if let LogicalValue::Materialized(value) = &entry.value {
+                entries.push(SnapshotEntry {
+                    hash: *hashed,
+                    key: (*entry.key).clone(),
+                    output: value.value.clone(),
+                    revision: value.revision,
+                    dependencies: self.dependencies(hashed).unwrap_or_default(),
+                });
+            }
+            true
+        });
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+
+    /// Flattens everything into a [`Snapshot`] for debugging. SHOULD only be called when
+    /// computation isn't taking place; entries still being computed are left out.
+    pub fn snapshot(&self, options: &Options) -> Snapshot<Key, Output> {
+        let entries = self.entries();
+
+        let mut remotes = Vec::new();
+        self.remotes.cache.iter_sync(|uri, remote_blob| {
+            remotes.push((uri.to_string(), remote_blob.blob.clone()));
+            true
+        });
+        remotes.sort();
+
+        Snapshot {
+            version: options.version.clone(),
+            revision: self.revision.load(Ordering::SeqCst),
+            entries,
+            remotes,
+        }
+    }
+
+    /// Counts up what's in the cache. Blob sizes come from the files themselves, so they're
+    /// exactly what's taking up space.
+    pub fn cache_stats(&self, options: &Options) -> driver_util::Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        self.cache.iter_sync(|_, entry| {
+            *stats.kinds.entry(entry.key.kind()).or_default() += 1;
+            true
+        });
+        self.dep_graph.iter_sync(|_, deps| {
+            stats.nodes += 1;
+            stats.edges += deps.len();
+            true
+        });
+        for file in std::fs::read_dir(&options.blobs_path)? {
+            let file = file?;
+            if atomic::is_leftover(&file.path()) {
+                continue;
+            }
+            stats.objects += 1;
+            stats.object_bytes += file.metadata()?.len();
+        }
+        Ok(stats)
+    }
+
     pub fn clear_remote(&self) {
         self.remotes.cache.clear_sync();
     }
@@ -471,114 +630,45 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
 
     /// Finds all [`Blob`]s that are referenced in the local and remote caches.
     fn collect_blobs(&self) -> HashSet<Blob> {
-        /*
-        let mut objects = HashSet::new();
-
-        self.cache.iter_sync(|key, value| {
-            objects.extend(key.trace().cloned());
-            objects.extend(
-                match value {
-                    LogicalValue::Materialized(value) => &value.value,
-                    LogicalValue::Computing(_) => {
-                        panic!("should not be computing {key}")
-                    }
-                }
-                .trace()
-                .cloned(),
-            );
+        let mut blobs = HashSet::new();
+
+        self.cache.iter_sync(|hashed, entry| {
+            blobs.extend(entry.key.trace().cloned());
+            match &entry.value {
+                LogicalValue::Materialized(value) => blobs.extend(value.value.trace().cloned()),
+                LogicalValue::Computing(_) => panic!("should not be computing {hashed:?}"),
+            }
             true
         });
 
-        self.remotes.cache.iter_sync(|_key, value| {
-            objects.insert(value.blob.clone());
+        self.remotes.cache.iter_sync(|_uri, remote_blob| {
+            blobs.insert(remote_blob.blob.clone());
             true
         });
 
-        objects
-        */
-        todo!()
+        blobs
     }
 
     pub fn display_dep_graph(&self) -> impl Display + '_ {
-        /*
-                struct GraphDisplayer<'a, Key: Hash + Eq>(&'a scc::HashMap<Key, BTreeSet<Key>>);
-
-                impl<'a, Key: driver_util::Key> Display for GraphDisplayer<'a, Key> {
-                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                        let mut keys = Vec::<Key>::with_capacity(self.0.len());
-                        let mut entry = self.0.begin_sync();
-                        while let Some(e) = entry {
-                            keys.push(e.key().clone());
-                            entry = e.next_sync();
-                        }
-
-                        keys.sort();
-
-                        for key in keys {
-                            write!(f, "{}: ", key)?;
-
-                            if let Some(deps) = self.0.get_sync(&key)
-                                && !deps.is_empty()
-                            {
-                                writeln!(f, "[")?;
-                                for dep in deps.iter() {
-                                    writeln!(f, "\t{},", dep)?;
-                                }
-                                writeln!(f, "]")?;
-                            } else {
-                                writeln!(f, "None")?;
-                            };
-                        }
-
-                        Ok(())
-                    }
-                }
-
-                GraphDisplayer(&self.dep_graph)
-        */
-        "TODO"
+        TextGraph {
+            entries: self.entries(),
+            with_outputs: false,
+        }
     }
 
     pub fn display_dep_graph_with_outputs(&self) -> impl Display + '_ {
-        /*
-                struct GraphAndOutputDisplayer<'a, Key: Hash + Ord + Eq, Output>(&'a Core<Key, Output>);
-
-                impl<'a, Key: driver_util::Key, Output: driver_util::Output> Display
-                    for GraphAndOutputDisplayer<'a, Key, Output>
-                {
-                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                        let mut keys = Vec::<Key>::with_capacity(self.0.dep_graph.len());
-                        let mut entry = self.0.dep_graph.begin_sync();
-                        while let Some(e) = entry {
-                            keys.push(e.key().clone());
-                            entry = e.next_sync();
-                        }
-
-                        keys.sort();
-
-                        for key in keys {
-                            write!(f, "{} -> {:?}: ", key, self.0.get_value(&key))?;
-
-                            if let Some(deps) = self.0.dep_graph.get_sync(&key)
-                                && !deps.is_empty()
-                            {
-                                writeln!(f, "[")?;
-                                for dep in deps.iter() {
-                                    writeln!(f, "\t{} -> {:?},", dep, self.0.get_value(dep))?;
-                                }
-                                writeln!(f, "]")?;
-                            } else {
-                                writeln!(f, "None")?;
-                            };
-                        }
-
-                        Ok(())
-                    }
-                }
+        TextGraph {
+            entries: self.entries(),
+            with_outputs: true,
+        }
+    }
 
-                GraphAndOutputDisplayer(self)
-        */
-        "TODO"
+    /// The dependency graph in Graphviz DOT format.
+    pub fn display_dep_graph_dot(&self) -> impl Display + '_ {
+        DotGraph {
+            entries: self.entries(),
+            revision: self.revision.load(Ordering::SeqCst),
+        }
     }
 }
 
@@ -594,3 +684,222 @@ impl<Key: driver_util::Key, Output: driver_util::Output> Database<Key, Output> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    driver_util::key!(
+        #[input=|_| false]
+        struct TestKey(u32);
+    );
+    driver_util::no_blobs!(TestKey);
+    impl Display for TestKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TestKey({})", self.0)
+        }
+    }
+
+    #[test]
+    fn dependents_mirror_dependencies() {
+        let db = Database::<TestKey, Blob>::empty();
+        let [a, b, c, d] = [1, 2, 3, 4].map(|n| Hashed::new(&TestKey(n)));
+        db.add_dependency(a, b);
+        db.add_dependency(a, c);
+        db.add_dependency(d, b);
+
+        assert_eq!(
+            db.dependencies::<BTreeSet<_>>(&a),
+            Some(BTreeSet::from([b, c]))
+        );
+        assert_eq!(db.dependents::<BTreeSet<_>>(&b), BTreeSet::from([a, d]));
+        assert_eq!(db.dependents::<BTreeSet<_>>(&c), BTreeSet::from([a]));
+        assert!(db.dependents::<Vec<_>>(&a).is_empty());
+        // Every edge shows up from both ends
+        for parent in [a, b, c, d] {
+            for child in db.dependencies::<Vec<_>>(&parent).unwrap_or_default() {
+                assert!(db.dependents::<Vec<_>>(&child).contains(&parent));
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_collects_evicted_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(dir.path());
+
+        let db = Database::<TestKey, Blob>::restore(&options).unwrap();
+        let live = db.blobs.store(&options, b"live".to_vec()).unwrap();
+        let dead = db.blobs.store(&options, b"dead".to_vec()).unwrap();
+        let (parent, ()) = block_on(db.upsert(TestKey(1), async |_, _, entry| {
+            entry.insert(1, dead.clone())
+        }));
+        let (child, ()) = block_on(db.upsert(TestKey(2), async |_, _, entry| {
+            entry.insert(1, live.clone())
+        }));
+        db.add_dependency(parent, child);
+        // Evicts TestKey(1), but not TestKey(2), which it depended on
+        db.remove_root_keys();
+        db.save(&options).unwrap();
+
+        let db = Database::<TestKey, Blob>::restore(&options).unwrap();
+        db.garbage_collect(&options).unwrap();
+        assert_eq!(db.blobs.load(&options, live).unwrap(), b"live");
+        assert!(db.blobs.load(&options, dead).is_err());
+    }
+
+    /// Runs each connection on its own thread, which is plenty for a test.
+    struct ThreadExecutor;
+
+    impl<F> hyper::rt::Executor<F> for ThreadExecutor
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        fn execute(&self, fut: F) {
+            std::thread::spawn(move || block_on(fut));
+        }
+    }
+
+    /// Answers exactly one request with `body`, returning the URL to request.
+    fn serve_once(body: &'static [u8]) -> String {
+        use std::io::{Read as _, Write as _};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // The request is small enough to come in one read, and we don't care what's in it
+            let _ = stream.read(&mut [0; 4096]).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        });
+        format!("http://{addr}/style.css")
+    }
+
+    #[test]
+    fn enforced_lock_is_left_alone() {
+        use sha2::Digest as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join("remotes.lock");
+        let mut options = Options::with_base_dir(dir.path());
+        options.remotes_lock_path = Some(lock_path.clone());
+        options.enforce_remotes_lock = true;
+
+        // Upstream changed since the lock was written
+        let url = serve_once(b"body { color: blue }");
+        let locked = format!(
+            "{url} {} 2026-01-02T03:04:05Z\n",
+            hex::encode(sha2::Sha256::digest(b"body { color: red }"))
+        );
+        std::fs::write(&lock_path, &locked).unwrap();
+
+        let db = Database::<TestKey, Blob>::restore(&options).unwrap();
+        let uri = crate::Uri(url.parse().unwrap());
+        let err = block_on(
+            db.remotes
+                .fetch(&ThreadExecutor, &options, &db.blobs, uri.clone()),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("doesn't match lock"), "{err}");
+        assert!(db.remotes.cache.get_sync(&uri).is_none());
+
+        db.save(&options).unwrap();
+        assert_eq!(std::fs::read_to_string(&lock_path).unwrap(), locked);
+    }
+
+    #[test]
+    fn unreadable_lock_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join("remotes.lock");
+        std::fs::write(&lock_path, "not-a-lock\n").unwrap();
+        let mut options = Options::with_base_dir(dir.path());
+        options.remotes_lock_path = Some(lock_path);
+        options.enforce_remotes_lock = true;
+
+        let err = Database::<TestKey, Blob>::restore(&options).unwrap_err();
+        assert!(err.to_string().contains("invalid lock line 1"), "{err}");
+    }
+
+    /// Saves a database with one entry in it, returning the blob that entry points at.
+    fn save_one(options: &Options) -> Blob {
+        let db = Database::<TestKey, Blob>::restore(options).unwrap();
+        let blob = db.blobs.store(options, b"saved".to_vec()).unwrap();
+        block_on(db.upsert(TestKey(1), async |_, _, entry| {
+            entry.insert(1, blob.clone())
+        }));
+        db.save(options).unwrap();
+        blob
+    }
+
+    fn restored_entries(options: &Options) -> usize {
+        let db = Database::<TestKey, Blob>::restore(options).unwrap();
+        db.snapshot(options).entries.len()
+    }
+
+    #[test]
+    fn restores_nothing_on_first_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(&dir.path().join(".driver"));
+        assert_eq!(restored_entries(&options), 0);
+        assert!(options.blobs_path.is_dir());
+
+        save_one(&options);
+        assert_eq!(restored_entries(&options), 1);
+    }
+
+    #[test]
+    fn restores_cache_without_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(dir.path());
+        let blob = save_one(&options);
+        std::fs::remove_dir_all(&options.blobs_path).unwrap();
+
+        // The entries are kept, but the blob they point at is gone, so reading it fails
+        let db = Database::<TestKey, Blob>::restore(&options).unwrap();
+        assert!(options.blobs_path.is_dir());
+        assert_eq!(db.snapshot(&options).entries.len(), 1);
+        assert!(db.blobs.load(&options, blob).is_err());
+    }
+
+    #[test]
+    fn restores_nothing_from_corrupt_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(dir.path());
+        save_one(&options);
+        std::fs::write(&options.cache_path, b"definitely not zstd").unwrap();
+
+        assert_eq!(restored_entries(&options), 0);
+        // But it's still usable afterwards
+        save_one(&options);
+        assert_eq!(restored_entries(&options), 1);
+    }
+
+    #[test]
+    fn counts_what_is_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::with_base_dir(dir.path());
+        save_one(&options);
+
+        let db = Database::<TestKey, Blob>::restore(&options).unwrap();
+        let blob = db.blobs.store(&options, b"more".to_vec()).unwrap();
+        let (parent, ()) =
+            block_on(db.upsert(TestKey(2), async |_, _, entry| entry.insert(1, blob)));
+        db.add_dependency(parent, Hashed::new(&TestKey(1)));
+
+        let stats = db.cache_stats(&options).unwrap();
+        assert_eq!(stats.kinds, BTreeMap::from([("TestKey", 2)]));
+        assert_eq!((stats.nodes, stats.edges), (1, 1));
+        assert_eq!((stats.objects, stats.object_bytes), (2, 9));
+    }
+}
@@ -0,0 +1,143 @@
+//! Ways of printing out the dependency graph, for figuring out why something did (or didn't) get
+//! re-run.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::{Hashed, SnapshotEntry};
+
+/// One line per key, listing what it depends on. Optionally includes the outputs too.
+pub(crate) struct TextGraph<Key, Output> {
+    pub entries: Vec<SnapshotEntry<Key, Output>>,
+    pub with_outputs: bool,
+}
+
+impl<Key: driver_util::Key, Output: driver_util::Output> Display for TextGraph<Key, Output> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let by_hash: HashMap<Hashed<Key>, &SnapshotEntry<Key, Output>> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.hash, entry))
+            .collect();
+        let write_key = |f: &mut std::fmt::Formatter<'_>,
+                         entry: &SnapshotEntry<Key, Output>|
+         -> std::fmt::Result {
+            write!(f, "{}", entry.key)?;
+            if self.with_outputs {
+                write!(f, " -> {:?}", entry.output)?;
+            }
+            Ok(())
+        };
+
+        for entry in self.entries.iter() {
+            write_key(f, entry)?;
+            if entry.dependencies.is_empty() {
+                writeln!(f, ": None")?;
+                continue;
+            }
+            writeln!(f, ": [")?;
+            for dep in entry.dependencies.iter() {
+                write!(f, "\t")?;
+                match by_hash.get(dep) {
+                    Some(dep) => write_key(f, dep)?,
+                    None => write!(f, "{dep:?} (missing)")?,
+                }
+                writeln!(f, ",")?;
+            }
+            writeln!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Graphviz, for anything too big to make sense of as text: `driver print-graph --format dot |
+/// dot -Tsvg > graph.svg`.
+///
+/// Inputs are boxes, everything else is an ellipse. Keys that have been verified this revision are
+/// green, everything else (stale, or not looked at yet) is red. Only the in-memory revision
+/// counts, so right after a restore everything is red.
+pub(crate) struct DotGraph<Key, Output> {
+    pub entries: Vec<SnapshotEntry<Key, Output>>,
+    pub revision: usize,
+}
+
+impl<Key: driver_util::Key, Output: driver_util::Output> Display for DotGraph<Key, Output> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ids: HashMap<Hashed<Key>, usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.hash, i))
+            .collect();
+
+        writeln!(f, "digraph driver {{")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let shape = if entry.key.is_input() {
+                "box"
+            } else {
+                "ellipse"
+            };
+            let color = if entry.revision.verified_at >= self.revision {
+                "green"
+            } else {
+                "red"
+            };
+            writeln!(
+                f,
+                "\tn{i} [label=\"{}\", shape={shape}, color={color}];",
+                escape(&entry.key.to_string())
+            )?;
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            for dep in entry.dependencies.iter() {
+                // Dependencies that were never materialized have nothing to point at
+                if let Some(j) = ids.get(dep) {
+                    writeln!(f, "\tn{i} -> n{j};")?;
+                }
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future::block_on;
+
+    use crate::Database;
+
+    driver_util::key!(
+        #[input=|this: &TestKey| this.0 == 0]
+        struct TestKey(u32);
+    );
+    driver_util::no_blobs!(TestKey);
+    impl std::fmt::Display for TestKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TestKey(\"{}\")", self.0)
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let db = Database::<TestKey, u32>::empty();
+        let (input, ()) = block_on(db.upsert(TestKey(0), async |_, _, entry| entry.insert(1, 0)));
+        let (derived, ()) = block_on(db.upsert(TestKey(1), async |_, _, entry| entry.insert(1, 1)));
+        db.add_dependency(derived, input);
+
+        let dot = db.display_dep_graph_dot().to_string();
+        assert!(dot.starts_with("digraph driver {\n"), "{dot}");
+        assert!(
+            dot.contains(r#"n0 [label="TestKey(\"0\")", shape=box"#),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(r#"n1 [label="TestKey(\"1\")", shape=ellipse"#),
+            "{dot}"
+        );
+        assert!(dot.contains("n1 -> n0;"), "{dot}");
+    }
+}
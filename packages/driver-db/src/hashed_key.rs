@@ -13,7 +13,7 @@ pub struct Hashed<Key>(Hash, PhantomData<Key>);
 /// unfortunately...
 impl<Key> std::fmt::Debug for Hashed<Key> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Debug::fmt(&self.0, f)
+        std::fmt::Debug::fmt(&driver_util::short_hex(&self.0), f)
     }
 }
 
@@ -0,0 +1,73 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts how many queries were answered straight out of the cache (hits) versus had to be
+/// produced again (misses). Only covers the current run: it's never saved along with the cache.
+#[derive(Debug, Default)]
+pub struct HitStats {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl HitStats {
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Starts counting from zero again, like for each build when watching for changes.
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Fraction of queries that were hits, from 0 to 1. A run with no queries at all counts as 0.
+    pub fn ratio(&self) -> f64 {
+        let (hits, misses) = (self.hits(), self.misses());
+        if hits + misses == 0 {
+            return 0.0;
+        }
+        hits as f64 / (hits + misses) as f64
+    }
+}
+
+impl Display for HitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (hits, misses) = (self.hits(), self.misses());
+        write!(
+            f,
+            "{hits}/{} queries cached ({:.1}%), {misses} produced",
+            hits + misses,
+            self.ratio() * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reset_starts_over() {
+        let stats = HitStats::default();
+        stats.record_hit();
+        stats.record_miss();
+        stats.record_miss();
+        assert_eq!(stats.to_string(), "1/3 queries cached (33.3%), 2 produced");
+
+        stats.reset();
+        stats.record_hit();
+        assert_eq!(stats.to_string(), "1/1 queries cached (100.0%), 0 produced");
+    }
+}
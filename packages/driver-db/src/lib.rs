@@ -3,6 +3,14 @@ pub use database::Database;
 pub use database::Entry;
 pub use database::Revision;
 
+mod hit_stats;
+pub use hit_stats::HitStats;
+
+mod cache_stats;
+pub use cache_stats::CacheStats;
+
+mod dep_graph;
+
 mod hashed_key;
 pub use hashed_key::Hashed;
 
@@ -11,6 +19,19 @@ pub use blobs::Blobs;
 
 mod options;
 pub use options::Options;
+pub use options::PostProcessRule;
+
+mod atomic;
+
+mod snapshot;
+pub use snapshot::Snapshot;
+pub use snapshot::SnapshotEntry;
+
+mod versioned;
+
+mod remote_lock;
+pub use remote_lock::LockEntry;
+pub use remote_lock::RemoteLock;
 
 mod remote_blobs;
 pub use remote_blobs::RemoteBlob;
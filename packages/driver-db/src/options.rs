@@ -1,10 +1,69 @@
 use std::path::{Path, PathBuf};
 
+/// NOTE: anything in here that changes what a query produces MUST be copied into that query's key
+/// when it's created (see `MinifyHtml::new()` for an example), rather than read off the options at
+/// produce-time. Otherwise, changing it won't invalidate anything already in the cache.
 #[derive(Debug)]
 pub struct Options {
     pub cache_path: PathBuf,
     pub remotes_path: PathBuf,
     pub blobs_path: PathBuf,
+    /// Roughly how many bytes of blob contents to keep in memory. Past that, the least recently
+    /// used ones are dropped, & read back off the disk when needed.
+    pub blob_cache_bytes: usize,
+    /// Extra globs to leave out of directory listings, on top of whatever .gitignore says.
+    pub ignore_globs: Vec<String>,
+    /// Whether directory listings respect .gitignore files.
+    pub respect_gitignore: bool,
+    /// Version stamped into saved caches. Caches from incompatible versions are discarded on
+    /// restore. Every driver crate shares the workspace's version, so this is also what the CLI
+    /// reports.
+    pub version: String,
+    /// If set, a lockfile of every fetched URL & its content hash gets written here on save.
+    pub remotes_lock_path: Option<PathBuf>,
+    /// Refuse to use remote content that doesn't match what's in `remotes_lock_path`. Also means
+    /// the lock is left as-is when saving, instead of being rewritten.
+    pub enforce_remotes_lock: bool,
+    /// Elements whose contents HTML minification leaves exactly as-is.
+    pub minify_preserve_tags: Vec<String>,
+    /// Reading speed used for reading time estimates.
+    pub words_per_minute: u32,
+    /// How aggressively to optimize WebAssembly modules, from 0 (just validate) to 2.
+    pub wasm_opt_level: u8,
+    /// What the output directory is served at, like `https://example.com`. Needed for anything
+    /// that has to know absolute URLs, like sitemaps.
+    pub base_url: Option<String>,
+    /// A `.env` file scripts can read variables out of.
+    pub env_file: Option<PathBuf>,
+    /// Transforms run over every output file, after the build but before anything's written.
+    /// Applied in order, so later rules see the output of earlier ones.
+    pub post_process: Vec<PostProcessRule>,
+    /// If set, logs (at info level, under `driver_engine::TRACE_QUERY`) what happens to the key
+    /// that displays as this, and everything it depends on.
+    pub trace_query: Option<String>,
+    /// How many dependencies to check at once when working out whether a cached key is still
+    /// up-to-date. Checking a dependency can mean re-running it, so this is also roughly how many
+    /// of them can be re-running at once. 1 checks them one at a time, in the order they were
+    /// first queried.
+    pub max_concurrent_checks: usize,
+    /// How deep queries can nest, like scripts running scripts that run scripts. Going deeper is
+    /// an error instead of a stack overflow. Only checked by the queries that can nest that deep.
+    pub max_query_depth: usize,
+    /// Extensions (without the dot) of the files scripts are allowed to import, with an empty one
+    /// meaning files without an extension. `json` files get imported as their parsed contents;
+    /// everything else is parsed as Javascript.
+    pub module_extensions: Vec<String>,
+    /// If set, reading files & listing directories outside of this is an error, so a build script
+    /// can't go poking around the rest of the disk.
+    pub project_root: Option<PathBuf>,
+}
+
+/// Runs the transform named `transform` (like `minify_html`) over every output file whose path
+/// matches `glob`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostProcessRule {
+    pub glob: String,
+    pub transform: String,
 }
 
 impl Options {
@@ -13,7 +72,44 @@ impl Options {
             cache_path: dir.join("cache.zst"),
             remotes_path: dir.join("remotes.zst"),
             blobs_path: dir.join("objects"),
+            blob_cache_bytes: 256 << 20,
+            ignore_globs: Vec::new(),
+            respect_gitignore: true,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            remotes_lock_path: None,
+            enforce_remotes_lock: false,
+            minify_preserve_tags: vec!["pre".to_string(), "textarea".to_string()],
+            words_per_minute: 200,
+            wasm_opt_level: 2,
+            base_url: None,
+            env_file: None,
+            post_process: Vec::new(),
+            trace_query: None,
+            max_concurrent_checks: 8,
+            max_query_depth: 256,
+            module_extensions: ["js", "mjs", "cjs", "json", ""].map(String::from).to_vec(),
+            project_root: None,
+        }
+    }
+}
+
+impl Options {
+    /// Deletes everything `Database::save` writes (the caches & every blob), returning whatever
+    /// was actually there to delete. Only ever touches those paths, so anything else that happens
+    /// to live next to them is safe.
+    pub fn remove_saved(&self) -> driver_util::Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for path in [&self.cache_path, &self.remotes_path] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+                removed.push(path.clone());
+            }
+        }
+        if self.blobs_path.exists() {
+            std::fs::remove_dir_all(&self.blobs_path)?;
+            removed.push(self.blobs_path.clone());
         }
+        Ok(removed)
     }
 }
 
@@ -2,17 +2,18 @@ use http_body_util::BodyExt as _;
 use hyper::Response;
 use hyper::body::Incoming;
 use hyper::header::{
-    CACHE_CONTROL, ETAG, EXPIRES, HOST, IF_MODIFIED_SINCE, IF_NONE_MATCH, USER_AGENT,
+    CACHE_CONTROL, ETAG, EXPIRES, HOST, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT,
 };
 use hyper::{HeaderMap, StatusCode, header::HeaderValue};
+use jiff::fmt::rfc2822;
 use jiff::fmt::temporal::DateTimeParser;
 use jiff::{Span, Timestamp, ToSpan};
 use serde::{Deserialize, Serialize};
 
 use smol_hyper_client::{Client, USER_AGENT as USER_AGENT_VALUE, Uri};
 
-use crate::{Blob, Blobs, Options};
-use driver_util::SerializedMap;
+use crate::{Blob, Blobs, Options, RemoteLock};
+use driver_util::{ErrorKind, SerializedMap};
 
 type EmptyBody = http_body_util::Empty<hyper::body::Bytes>;
 type MyClient = Client<EmptyBody>;
@@ -27,6 +28,9 @@ pub struct RemoteBlobs {
     #[serde(skip, default = "default_client")]
     client: MyClient,
     pub(crate) cache: SerializedMap<Uri, RemoteBlob>,
+    /// If set, every fetch is checked against this lock.
+    #[serde(skip)]
+    pub(crate) lock: Option<RemoteLock>,
 }
 
 impl Default for RemoteBlobs {
@@ -34,6 +38,7 @@ impl Default for RemoteBlobs {
         Self {
             client: default_client(),
             cache: Default::default(),
+            lock: None,
         }
     }
 }
@@ -43,7 +48,7 @@ pub struct RemoteBlob {
     /// The stored blob we fetched.
     pub blob: Blob,
     /// The time at which we fetched the blob.
-    fetched: Timestamp,
+    pub(crate) fetched: Timestamp,
     /// How long after `fetched` can we continue to treat the blob as "fresh" (don't need to
     /// fetch again)? Calculated according to <https://httpwg.org/specs/rfc9111.html#calculating.freshness.lifetime>,
     /// based on the HTTP responose headers.
@@ -51,6 +56,10 @@ pub struct RemoteBlob {
     /// When submitting to the cache server, we provide an ETag header so it can say "not modified"
     /// to short-circuit make us not have to download as much data
     etag: Option<Vec<u8>>,
+    /// When the server says the blob was last changed, if it told us. Used to decide how long to
+    /// cache things the server didn't give explicit freshness for, and sent back as
+    /// `If-Modified-Since`, since it's in the server's clock rather than ours.
+    last_modified: Option<Timestamp>,
 }
 
 impl RemoteBlob {
@@ -68,6 +77,9 @@ impl RemoteBlob {
 impl RemoteBlobs {
     /// Fetches a remote URL and adds it to the local store if not present or too stale.
     /// If the URL is present in the cache and still fresh, uses that instead of fetching.
+    ///
+    /// If we're enforcing a lock, errors if the content doesn't match what's locked. Content that
+    /// doesn't match never makes it into the cache, so it can't end up in the lock either.
     pub async fn fetch<E>(
         &self,
         executor: &E,
@@ -84,7 +96,9 @@ impl RemoteBlobs {
             if let Some(ref remote_blob) = remote_blob
                 && remote_blob.is_fresh()
             {
-                // If there is a fresh blob in the cache, just use that
+                // If there is a fresh blob in the cache, just use that. It might be from before we
+                // were enforcing the lock, so still check it.
+                self.check_lock(&uri, &remote_blob.blob)?;
                 return Ok((*remote_blob).clone());
             }
 
@@ -93,7 +107,8 @@ impl RemoteBlobs {
                 .header(USER_AGENT, USER_AGENT_VALUE)
                 .header(HOST, uri.host().ok_or(driver_util::Error::new("no host"))?);
             if let Some(ref remote_blob) = remote_blob {
-                req = req.header(IF_MODIFIED_SINCE, format_header_date(remote_blob.fetched)?);
+                let since = remote_blob.last_modified.unwrap_or(remote_blob.fetched);
+                req = req.header(IF_MODIFIED_SINCE, format_header_date(since)?);
                 if let Some(etag) = &remote_blob.etag {
                     req = req.header(IF_NONE_MATCH, HeaderValue::from_bytes(etag)?);
                 }
@@ -109,15 +124,21 @@ impl RemoteBlobs {
 
         let req = req.body(http_body_util::Empty::<hyper::body::Bytes>::new())?;
         println!("[{}] get_url(\"{}\")", why, uri);
-        let resp: Response<Incoming> = self.client.request(executor, req).await?;
+        // Network hiccups are worth retrying, so mark them as such
+        let resp: Response<Incoming> = self
+            .client
+            .request(executor, req)
+            .await
+            .map_err(|e| driver_util::Error::from(e).into_kind(ErrorKind::Transient))?;
         let status = resp.status();
         if !status.is_success() {
             if status == StatusCode::NOT_MODIFIED {
                 // Cache thinks the blob we have locally is still fresh, keep it around and
                 // update the headers.
                 let headers = ResponseHeaders::from_headers(resp.headers());
-                return match self.cache.entry_async(uri).await {
+                return match self.cache.entry_async(uri.clone()).await {
                     scc::hash_map::Entry::Occupied(mut entry) => {
+                        self.check_lock(&uri, &entry.blob)?;
                         *entry = headers.with_blob(entry.blob.clone());
                         Ok(entry.clone())
                     }
@@ -126,8 +147,15 @@ impl RemoteBlobs {
                     )),
                 };
             }
-            // Otherwise, the error is unexpected
-            return Err(driver_util::Error::new(
+            // Otherwise, the error is unexpected. Server errors & rate limiting might clear up if
+            // we try again though.
+            let kind = if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                ErrorKind::Transient
+            } else {
+                ErrorKind::Other
+            };
+            return Err(driver_util::Error::with_kind(
+                kind,
                 status.canonical_reason().unwrap_or("unknown response code"),
             ));
         }
@@ -135,8 +163,13 @@ impl RemoteBlobs {
         let headers = ResponseHeaders::from_headers(resp.headers());
 
         let body = resp.into_body();
-        let body = body.collect().await?.to_bytes();
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| driver_util::Error::from(e).into_kind(ErrorKind::Transient))?
+            .to_bytes();
         let blob = blobs.store(options, body.into())?;
+        self.check_lock(&uri, &blob)?;
 
         let remote_blob = headers.with_blob(blob);
         let _ = self.cache.upsert_async(uri, remote_blob.clone()).await;
@@ -145,11 +178,21 @@ impl RemoteBlobs {
     }
 }
 
+impl RemoteBlobs {
+    fn check_lock(&self, uri: &Uri, blob: &Blob) -> driver_util::Result<()> {
+        match &self.lock {
+            Some(lock) => lock.check(&uri.to_string(), blob),
+            None => Ok(()),
+        }
+    }
+}
+
 /// The part of RemoteObject that can be populated from the response headers we get
 struct ResponseHeaders {
     fetched: Timestamp,
     freshness_lifetime: Span,
     etag: Option<Vec<u8>>,
+    last_modified: Option<Timestamp>,
 }
 
 impl ResponseHeaders {
@@ -158,12 +201,14 @@ impl ResponseHeaders {
             fetched,
             freshness_lifetime,
             etag,
+            last_modified,
         } = self;
         RemoteBlob {
             blob,
             fetched,
             freshness_lifetime,
             etag,
+            last_modified,
         }
     }
 
@@ -174,19 +219,29 @@ impl ResponseHeaders {
 
     fn from_headers(headers: &HeaderMap) -> Self {
         let fetched = Timestamp::now();
-        let freshness_lifetime = Self::calculate_freshness_lifetime(headers, fetched)
-            .unwrap_or_else(|e| {
-                // Log the error, then continue with default freshness, since the server _did_ give
-                // us a response after all.
-                tracing::warn!("getting freshness lifetime: {e}");
-                Self::default_freshness()
-            });
+        let last_modified = headers.get(LAST_MODIFIED).and_then(|last_modified| {
+            static PARSER: rfc2822::DateTimeParser = rfc2822::DateTimeParser::new();
+            PARSER
+                .parse_timestamp(last_modified)
+                .inspect_err(|e| tracing::warn!("parsing Last-Modified: {e}"))
+                .ok()
+        });
+        let freshness_lifetime =
+            Self::calculate_freshness_lifetime(headers, fetched, last_modified).unwrap_or_else(
+                |e| {
+                    // Log the error, then continue with default freshness, since the server _did_ give
+                    // us a response after all.
+                    tracing::warn!("getting freshness lifetime: {e}");
+                    Self::default_freshness()
+                },
+            );
         let etag = headers.get(ETAG).map(|header| header.as_bytes().to_owned());
 
         Self {
             fetched,
             freshness_lifetime,
             etag,
+            last_modified,
         }
     }
 
@@ -194,6 +249,7 @@ impl ResponseHeaders {
     fn calculate_freshness_lifetime(
         headers: &HeaderMap,
         fetched: Timestamp,
+        last_modified: Option<Timestamp>,
     ) -> driver_util::Result<Span> {
         if let Some(cache_control) = headers.get(CACHE_CONTROL) {
             let cache_control = cache_control.to_str()?;
@@ -229,6 +285,13 @@ impl ResponseHeaders {
             return Ok(expires - fetched);
         }
 
+        // Heuristic freshness, as suggested by <https://httpwg.org/specs/rfc9111.html#heuristic.freshness>:
+        // things that haven't changed in a while probably won't change soon either.
+        if let Some(last_modified) = last_modified {
+            let unchanged_for = fetched.as_second() - last_modified.as_second();
+            return Ok((unchanged_for.max(0) / 10).seconds());
+        }
+
         // Use a reasonable default if the remote doesn't provide caching headers (or just etags,
         // which I don't support currently because I don't want to make _any_ network requests if
         // the cache is fresh)
@@ -278,3 +341,43 @@ fn format_header_date(timestamp: Timestamp) -> driver_util::Result<HeaderValue>
         format!("{weekday}, {day:0>2} {month} {year:0>4} {hour:0>2}:{minute:0>2}:{second:0>2} GMT");
     Ok(HeaderValue::from_str(&value)?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn heuristic_freshness_from_last_modified() {
+        let fetched: Timestamp = "2026-03-01T00:00:00Z".parse().unwrap();
+        let last_modified = fetched - (20 * 24).hours();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(LAST_MODIFIED, format_header_date(last_modified).unwrap());
+        let parsed = ResponseHeaders::from_headers(&headers);
+        assert_eq!(parsed.last_modified, Some(last_modified));
+
+        // 10% of 20 days is 2 days
+        let lifetime =
+            ResponseHeaders::calculate_freshness_lifetime(&headers, fetched, Some(last_modified))
+                .unwrap();
+        assert_eq!(lifetime.get_seconds(), 2 * 24 * 60 * 60);
+
+        // Explicit freshness still wins
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=60"));
+        let lifetime =
+            ResponseHeaders::calculate_freshness_lifetime(&headers, fetched, Some(last_modified))
+                .unwrap();
+        assert_eq!(lifetime.get_seconds(), 60);
+    }
+
+    #[test]
+    fn default_freshness_without_headers() {
+        let headers = HeaderMap::new();
+        let parsed = ResponseHeaders::from_headers(&headers);
+        assert_eq!(parsed.last_modified, None);
+        let lifetime =
+            ResponseHeaders::calculate_freshness_lifetime(&headers, Timestamp::now(), None)
+                .unwrap();
+        assert_eq!(lifetime.get_days(), 1);
+    }
+}
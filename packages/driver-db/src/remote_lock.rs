@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::Path;
+
+use jiff::Timestamp;
+
+use crate::{Blob, RemoteBlobs, atomic};
+
+/// A lockfile recording which content every remote URL resolved to, so builds on other machines
+/// (or later on this one) can be checked against it.
+///
+/// Meant to be committed, so it's a plain text file with one line per URL, sorted:
+///
+/// ```text
+/// <url> <sha256 as hex> <time fetched>
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RemoteLock {
+    entries: BTreeMap<String, LockEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockEntry {
+    pub hash: String,
+    pub fetched: Timestamp,
+}
+
+impl RemoteLock {
+    /// Snapshots everything currently in the remote cache.
+    pub fn from_remotes(remotes: &RemoteBlobs) -> Self {
+        let mut entries = BTreeMap::new();
+        remotes.cache.iter_sync(|uri, remote_blob| {
+            entries.insert(
+                uri.to_string(),
+                LockEntry {
+                    hash: remote_blob.blob.to_hex(),
+                    fetched: remote_blob.fetched,
+                },
+            );
+            true
+        });
+        Self { entries }
+    }
+
+    pub fn parse(contents: &str) -> driver_util::Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || driver_util::Error::new(&format!("invalid lock line {}", i + 1));
+            let mut parts = line.split_whitespace();
+            let url = parts.next().ok_or_else(invalid)?;
+            let hash = parts.next().ok_or_else(invalid)?;
+            let fetched = parts.next().ok_or_else(invalid)?.parse()?;
+            entries.insert(
+                url.to_string(),
+                LockEntry {
+                    hash: hash.to_string(),
+                    fetched,
+                },
+            );
+        }
+        Ok(Self { entries })
+    }
+
+    /// Reads a lockfile. One not existing yet is the same as an empty one.
+    pub fn read(path: &Path) -> driver_util::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the lockfile atomically, so a build dying partway through can't leave it truncated.
+    pub fn write(&self, path: &Path) -> driver_util::Result<()> {
+        let contents = self.to_string();
+        atomic::write(path, |file| Ok(file.write_all(contents.as_bytes())?))
+    }
+
+    pub fn get(&self, url: &str) -> Option<&LockEntry> {
+        self.entries.get(url)
+    }
+
+    /// Errors if `url` is locked to different content than `blob`. URLs that aren't in the lock
+    /// at all are fine; they'll get added the next time it's written, which is the next build that
+    /// isn't enforcing it.
+    pub fn check(&self, url: &str, blob: &Blob) -> driver_util::Result<()> {
+        let Some(entry) = self.get(url) else {
+            return Ok(());
+        };
+        blob.verify_sha256(&entry.hash)
+            .map_err(|e| driver_util::Error::new(&format!("{url} doesn't match lock: {e}")))
+    }
+}
+
+impl std::fmt::Display for RemoteLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (url, entry) in self.entries.iter() {
+            writeln!(f, "{} {} {}", url, entry.hash, entry.fetched)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sha2::Digest as _;
+
+    use super::*;
+
+    fn blob_of(contents: &[u8]) -> Blob {
+        // SAFETY: it is the hash of the contents
+        unsafe { Blob::from_hash(sha2::Sha256::digest(contents)) }
+    }
+
+    #[test]
+    fn round_trip() {
+        let lock = RemoteLock::parse(&format!(
+            "https://example.com/a.css {} 2026-01-02T03:04:05Z\n",
+            blob_of(b"a").to_hex()
+        ))
+        .unwrap();
+        assert_eq!(RemoteLock::parse(&lock.to_string()).unwrap(), lock);
+    }
+
+    #[test]
+    fn writes_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("remotes.lock");
+        let lock = RemoteLock::parse(&format!(
+            "https://example.com/a.css {} 2026-01-02T03:04:05Z\n",
+            blob_of(b"a").to_hex()
+        ))
+        .unwrap();
+        RemoteLock::default().write(&path).unwrap();
+        lock.write(&path).unwrap();
+
+        assert_eq!(RemoteLock::read(&path).unwrap(), lock);
+        // Nothing left behind from writing it
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Blob, Hashed, Revision, versioned};
+
+/// Everything the database knows (except the contents of blobs), flattened into one place so it
+/// can be attached to a bug report & inspected later. Unlike the cache files, this keeps
+/// revisions around, so you can see exactly what was considered up-to-date.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot<Key, Output> {
+    /// Version of driver that took the snapshot.
+    pub version: String,
+    /// The revision the database was at.
+    pub revision: usize,
+    /// Every materialized entry, sorted by key.
+    pub entries: Vec<SnapshotEntry<Key, Output>>,
+    /// Every fetched URL & what it resolved to, sorted by URL.
+    pub remotes: Vec<(String, Blob)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry<Key, Output> {
+    pub hash: Hashed<Key>,
+    pub key: Key,
+    pub output: Output,
+    pub revision: Revision,
+    pub dependencies: Vec<Hashed<Key>>,
+}
+
+impl<Key: driver_util::Key, Output: driver_util::Output> Snapshot<Key, Output> {
+    pub fn write(&self, path: &Path) -> driver_util::Result<()> {
+        versioned::write(path, &self.version, self)
+    }
+
+    /// Reads a snapshot, as long as it was taken by a version of driver compatible with `version`.
+    pub fn read(path: &Path, version: &str) -> driver_util::Result<Self> {
+        versioned::read(path, version)
+    }
+}
+
+impl<Key: driver_util::Key, Output: driver_util::Output> Display for Snapshot<Key, Output> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "driver {} @ revision {}", self.version, self.revision)?;
+        for entry in self.entries.iter() {
+            writeln!(
+                f,
+                "{:?} {} [changed {}, verified {}]",
+                entry.hash, entry.key, entry.revision.changed_at, entry.revision.verified_at
+            )?;
+            writeln!(f, "\t= {:?}", entry.output)?;
+            for dep in entry.dependencies.iter() {
+                writeln!(f, "\t-> {dep:?}")?;
+            }
+        }
+        for (url, blob) in self.remotes.iter() {
+            writeln!(f, "{url} = {blob:?}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::{Database, Options};
+
+    driver_util::key!(
+        #[input=|_| false]
+        struct TestKey(u32);
+    );
+    driver_util::no_blobs!(TestKey);
+    impl Display for TestKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TestKey({})", self.0)
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let db = Database::<TestKey, u32>::empty();
+        let (parent, ()) = block_on(db.upsert(TestKey(1), async |_, _, entry| entry.insert(1, 10)));
+        let (child, ()) = block_on(db.upsert(TestKey(2), async |_, _, entry| entry.insert(1, 20)));
+        db.add_dependency(parent, child);
+
+        let snapshot = db.snapshot(&Options::default());
+        assert_eq!(snapshot.entries.len(), 2);
+        assert_eq!(snapshot.entries[0].key, TestKey(1));
+        assert_eq!(snapshot.entries[0].dependencies, [child]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot.zst");
+        snapshot.write(&path).unwrap();
+        let restored = Snapshot::read(&path, &snapshot.version).unwrap();
+        assert_eq!(restored, snapshot);
+    }
+}
@@ -0,0 +1,110 @@
+//! Every file we save starts with a small header saying which version of driver wrote it, so we
+//! can throw out caches from incompatible versions instead of trying (and maybe "succeeding") to
+//! deserialize a layout that changed underneath us.
+
+use std::io::Read;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: String,
+}
+
+/// Semver-ish compatibility: major versions must match, and while we're still on 0.x, so must
+/// minor versions.
+fn compatible(a: &str, b: &str) -> bool {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    match (a.next(), b.next()) {
+        (Some("0"), Some("0")) => a.next() == b.next(),
+        (major_a, major_b) => major_a == major_b,
+    }
+}
+
+/// Writes `value` to `path` as zstd-compressed postcard, stamped with `version`.
+pub(crate) fn write<T: Serialize>(
+    path: &Path,
+    version: &str,
+    value: &T,
+) -> driver_util::Result<()> {
+    atomic::write(path, |file| {
+        let file = zstd::Encoder::new(file, 1)?;
+        let header = Header {
+            version: version.to_string(),
+        };
+        let file = postcard::to_io(&header, file)?;
+        let file = postcard::to_io(value, file)?;
+        file.finish()?;
+        Ok(())
+    })
+}
+
+/// Reads a value written by [`write`], erroring if it was written by a version incompatible with
+/// `version`.
+pub(crate) fn read<T: for<'de> Deserialize<'de>>(
+    path: &Path,
+    version: &str,
+) -> driver_util::Result<T> {
+    read_compatible(path, version)?.map_err(|written_by| {
+        driver_util::Error::new(&format!(
+            "{} was written by driver {written_by}, which is incompatible with {version}",
+            path.display()
+        ))
+    })
+}
+
+/// Like [`read`], except a file written by an incompatible version isn't an error, & gives back
+/// the version that wrote it instead. That's expected after upgrading, unlike the file being
+/// unreadable, so callers may want to report it differently.
+pub(crate) fn read_compatible<T: for<'de> Deserialize<'de>>(
+    path: &Path,
+    version: &str,
+) -> driver_util::Result<Result<T, String>> {
+    let file = std::fs::File::open(path)?;
+    let mut file = zstd::Decoder::new(file)?;
+    let mut bytes = Vec::<u8>::new();
+    file.read_to_end(&mut bytes)?;
+
+    let (header, rest) = postcard::take_from_bytes::<Header>(&bytes)?;
+    if !compatible(&header.version, version) {
+        return Ok(Err(header.version));
+    }
+
+    Ok(Ok(postcard::from_bytes(rest)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert!(compatible("1.2.0", "1.9.3"));
+        assert!(!compatible("1.2.0", "2.0.0"));
+        assert!(compatible("0.2.0", "0.2.7"));
+        assert!(!compatible("0.1.0", "0.2.0"));
+    }
+
+    #[test]
+    fn incompatible_falls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.zst");
+        write(&path, "1.0.0", &vec![1u32, 2, 3]).unwrap();
+
+        assert_eq!(read::<Vec<u32>>(&path, "1.4.0").unwrap(), vec![1, 2, 3]);
+        let err = read::<Vec<u32>>(&path, "2.0.0").unwrap_err().to_string();
+        assert!(err.contains("written by driver 1.0.0"), "{err}");
+        assert_eq!(
+            read_compatible::<Vec<u32>>(&path, "2.0.0").unwrap(),
+            Err("1.0.0".to_string())
+        );
+
+        // Which is what `Database::restore` turns into a clean, empty cache
+        let restored: Vec<u32> = read(&path, "2.0.0").unwrap_or_default();
+        assert!(restored.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag for asking a build to stop early. Clones all share the same flag, so one can be handed
+/// off to something like a signal handler while the build keeps checking its own.
+///
+/// Nothing gets interrupted by this on its own: producers that can take a while (like scripts)
+/// are expected to check [`Self::is_cancelled`] every so often & bail out with
+/// [`driver_util::Error::cancelled`]. Whatever gets produced after cancelling is thrown out
+/// instead of saved, so a partial result never sticks around in the cache.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Errors with [`driver_util::ErrorKind::Cancelled`] if cancelled.
+    pub fn check(&self) -> driver_util::Result<()> {
+        if self.is_cancelled() {
+            return Err(driver_util::Error::cancelled("build cancelled"));
+        }
+        Ok(())
+    }
+}
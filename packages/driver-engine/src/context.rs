@@ -1,25 +1,60 @@
-use std::collections::HashSet;
+use std::any::{Any, TypeId};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::hash::Hash;
-use std::sync::Arc;
 use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
+use futures_concurrency::future::Join as _;
 use memmap2::Mmap;
 use tracing::{info, trace, warn};
 
 use async_tpc_executor::Executor;
-use driver_db::{Blob, Database, Entry, Hashed, Options};
+use driver_db::{Blob, CacheStats, Database, Entry, Hashed, HitStats, Options};
 
-use crate::{Producer, ProducerBase};
+use crate::{CancellationToken, Producer, ProducerBase};
 
 struct State<Key: Hash + Ord + Eq, Output> {
     options: Options,
     db: Database<Key, Output>,
     executor: Executor,
     hooks: OptHooks<Key>,
+    /// When the root context was created. Never saved; see [`Context::build_time`].
+    build_time: SystemTime,
+    cancellation: CancellationToken,
+    /// Keys produced after the build was cancelled, which we don't want to save.
+    discarded: Mutex<HashSet<Hashed<Key>>>,
+    /// See [`Context::extension`].
+    extensions: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
 }
 
+/// What one top-level context (& every query started from it) keeps to itself, rather than sharing
+/// with contexts made by [`Context::warm_from`].
+struct Scope<Key> {
+    /// The key from `Options::trace_query` & everything it depends on, as we find them.
+    focus: Mutex<HashSet<Hashed<Key>>>,
+    /// See [`Context::roots`].
+    roots: Mutex<BTreeSet<Hashed<Key>>>,
+}
+
+/// Manual impl to avoid extraneous bounds on `Key`.
+impl<Key> Default for Scope<Key> {
+    fn default() -> Self {
+        Self {
+            focus: Default::default(),
+            roots: Default::default(),
+        }
+    }
+}
+
+/// Tracing target for the output of `Options::trace_query`, so it can be turned on separately.
+pub const TRACE_QUERY: &str = "driver::trace_query";
+
 type OptHooks<Key> = Option<Box<dyn Hooks<Key> + 'static + Send + Sync>>;
+/// Callbacks for watching what the engine is doing. Called from whatever thread is running the
+/// query, so implementations should be quick, and MUST NOT call back into `query()`.
 pub trait Hooks<Key: ProducerBase> {
+    /// Called whenever a key actually gets (re-)computed.
     fn on_compute(
         &self,
         ctx: &Context<Key>,
@@ -27,14 +62,78 @@ pub trait Hooks<Key: ProducerBase> {
         old_deps: HashSet<Hashed<Key>>,
         new_deps: HashSet<Hashed<Key>>,
     );
+
+    /// Called every time `query()` is called, before doing anything else.
+    fn on_query_start(&self, _ctx: &Context<Key>, _key: &Key) {}
+
+    /// Called every time `query()` returns, cached or not.
+    fn on_query_finish(
+        &self,
+        _ctx: &Context<Key>,
+        _key: &Key,
+        _output: &Key::Output,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Called right after [`Hooks::on_query_finish`] whenever the output is an error (see
+    /// [`ProducerBase::error_of`]), cached or not.
+    fn on_query_error(&self, _ctx: &Context<Key>, _key: &Key, _error: &driver_util::Error) {}
+}
+
+/// Lets you use more than one set of hooks at once.
+impl<Key: ProducerBase, A: Hooks<Key>, B: Hooks<Key>> Hooks<Key> for (A, B) {
+    fn on_compute(
+        &self,
+        ctx: &Context<Key>,
+        key: Key,
+        old_deps: HashSet<Hashed<Key>>,
+        new_deps: HashSet<Hashed<Key>>,
+    ) {
+        self.0
+            .on_compute(ctx, key.clone(), old_deps.clone(), new_deps.clone());
+        self.1.on_compute(ctx, key, old_deps, new_deps);
+    }
+
+    fn on_query_start(&self, ctx: &Context<Key>, key: &Key) {
+        self.0.on_query_start(ctx, key);
+        self.1.on_query_start(ctx, key);
+    }
+
+    fn on_query_finish(
+        &self,
+        ctx: &Context<Key>,
+        key: &Key,
+        output: &Key::Output,
+        elapsed: Duration,
+    ) {
+        self.0.on_query_finish(ctx, key, output, elapsed);
+        self.1.on_query_finish(ctx, key, output, elapsed);
+    }
+
+    fn on_query_error(&self, ctx: &Context<Key>, key: &Key, error: &driver_util::Error) {
+        self.0.on_query_error(ctx, key, error);
+        self.1.on_query_error(ctx, key, error);
+    }
 }
 
 #[derive(Clone)]
 pub struct Context<Key: ProducerBase> {
     pub(crate) parent: Option<Hashed<Key>>,
+    /// Every key being produced on the way to this one, innermost first. Used for spotting cycles
+    /// & limiting how deeply queries nest.
+    stack: Option<Arc<Frame<Key>>>,
+    scope: Arc<Scope<Key>>,
     state: Arc<State<Key, Key::Output>>,
 }
 
+struct Frame<Key> {
+    key: Key,
+    parent: Option<Arc<Frame<Key>>>,
+    /// How many frames there are, counting this one, so it doesn't need to be walked every query.
+    depth: usize,
+}
+
 impl<Key: ProducerBase> Context<Key> {
     /// Read the options associated with the context.
     pub fn options(&self) -> &Options {
@@ -46,6 +145,109 @@ impl<Key: ProducerBase> Context<Key> {
         &self.state.db
     }
 
+    /// If `key` is already being produced somewhere up the chain that led here, returns that chain,
+    /// starting & ending with `key`. Querying it would then mean waiting on itself forever.
+    pub fn find_cycle(&self, key: &Key) -> Option<Vec<Key>> {
+        let mut path = vec![key.clone()];
+        let mut frame = self.stack.as_deref();
+        while let Some(current) = frame {
+            path.push(current.key.clone());
+            if current.key == *key {
+                path.reverse();
+                return Some(path);
+            }
+            frame = current.parent.as_deref();
+        }
+        None
+    }
+
+    /// How many queries are being produced up the chain that led here.
+    pub fn depth(&self) -> usize {
+        self.stack.as_ref().map_or(0, |frame| frame.depth)
+    }
+
+    /// Errors if this is already [`Options::max_query_depth`] queries deep.
+    ///
+    /// This is NOT enforced by the engine itself, since a query has no way to fail on its own.
+    /// Producers that can nest arbitrarily deep (currently only `run_js`, for scripts running
+    /// scripts) must call this before querying; everything else only ever goes a few levels
+    /// further.
+    pub fn check_depth(&self) -> driver_util::Result<()> {
+        let max = self.options().max_query_depth;
+        if self.depth() >= max {
+            return Err(driver_util::Error::new(&format!(
+                "queries nested more than {max} deep"
+            )));
+        }
+        Ok(())
+    }
+
+    /// How many queries so far this run were answered from the cache, versus had to be produced.
+    pub fn hit_stats(&self) -> &HitStats {
+        &self.db().hit_stats
+    }
+
+    /// Everything in the cache, including what this run didn't touch. Locks every entry in turn,
+    /// so don't call this while queries are still running.
+    pub fn cache_stats(&self) -> driver_util::Result<CacheStats> {
+        self.db().cache_stats(self.options())
+    }
+
+    /// When this build started. The same for every query in the build, so unlike reading the clock
+    /// directly, using this can't make two parts of one build disagree. Not tracked as a
+    /// dependency, so outputs that were cached keep whatever time they were produced at.
+    ///
+    /// The time itself isn't saved with the cache: every root context (so every run of a build)
+    /// starts with a new one, & only queries that actually re-run see it.
+    pub fn build_time(&self) -> SystemTime {
+        self.state.build_time
+    }
+
+    /// Shared by every context in the build. Clone it to cancel the build from somewhere else.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.state.cancellation
+    }
+
+    /// The keys queried directly from this top-level context, which (having no parent query) don't
+    /// show up in the dependency graph. Everything else they needed can be found from there.
+    pub fn roots(&self) -> BTreeSet<Hashed<Key>> {
+        self.scope.roots.lock().expect("roots poisoned").clone()
+    }
+
+    /// Records `hashed` as one of [`Self::roots`], if this is a top-level context.
+    pub(crate) fn add_root(&self, hashed: Hashed<Key>) {
+        if self.parent.is_none() {
+            self.scope
+                .roots
+                .lock()
+                .expect("roots poisoned")
+                .insert(hashed);
+        }
+    }
+
+    /// Per-build state of type `T`, for things built on top of the engine to keep their own
+    /// settings in (like extra modules scripts can import). Created with `T::default()` the first
+    /// time it's asked for, & shared by every context in the build from then on.
+    pub fn extension<T: Any + Default + Send + Sync>(&self) -> Arc<T> {
+        let mut extensions = self.state.extensions.lock().expect("extensions poisoned");
+        let extension = extensions
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(T::default()))
+            .clone();
+        extension
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("extensions are keyed by their type"))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancellation.is_cancelled()
+    }
+
+    /// Get the hooks associated with the context, if any.
+    pub(crate) fn hooks(&self) -> Option<&(dyn Hooks<Key> + Send + Sync)> {
+        self.state.hooks.as_deref()
+    }
+
     /// Get the executor associated with the context.
     pub fn executor(&self) -> &Executor {
         &self.state.executor
@@ -78,6 +280,11 @@ impl<Key: ProducerBase> Context<Key> {
         Ok(string)
     }
 
+    /// Streams the given blob, without loading it into memory all at once.
+    pub fn open_blob(&self, blob: &Blob) -> driver_util::Result<impl std::io::Read> {
+        self.db().blobs.open(self.options(), blob)
+    }
+
     /// Loads the given blob as an [`Mmap`].
     ///
     /// # Safety
@@ -91,8 +298,17 @@ impl<Key: ProducerBase> Context<Key> {
 impl<Key: Producer<Key>> Context<Key> {
     /// Starts a new root context. Users SHOULD call `.destroy_root()` before dropping it. MUST be
     /// called outside of any async context.
+    ///
+    /// Panics if the database can't be restored; see [`Self::try_create_root`].
     pub fn create_root(options: Options, hooks: OptHooks<Key>) -> Self {
-        let db = Database::restore(&options);
+        Self::try_create_root(options, hooks)
+            .unwrap_or_else(|e| panic!("could not restore database: {e}"))
+    }
+
+    /// Like [`Self::create_root`], but errors instead of panicking if the database can't be
+    /// restored, like when the remotes lock is being enforced but can't be read.
+    pub fn try_create_root(options: Options, hooks: OptHooks<Key>) -> driver_util::Result<Self> {
+        let db = Database::restore(&options)?;
 
         // Bust cache immediately
         // TODO: should only bust the input queries here; right now this busts "everything" which
@@ -101,15 +317,28 @@ impl<Key: Producer<Key>> Context<Key> {
 
         let executor = Executor::start();
 
-        Self {
+        Ok(Self {
             parent: None,
+            stack: None,
+            scope: Default::default(),
             state: Arc::new(State {
                 options,
                 db,
                 executor,
                 hooks,
+                build_time: SystemTime::now(),
+                cancellation: Default::default(),
+                discarded: Default::default(),
+                extensions: Default::default(),
             }),
-        }
+        })
+    }
+
+    /// Starts a new revision, so every input gets checked again the next time something depends on
+    /// it. Anything whose inputs turn out unchanged is reused like usual. For re-running a build in
+    /// the same process, like when watching for changes.
+    pub fn new_revision(&self) {
+        self.db().revision.fetch_add(1, Ordering::SeqCst);
     }
 
     /// Stops a root context. MUST only be called:
@@ -118,12 +347,38 @@ impl<Key: Producer<Key>> Context<Key> {
     ///
     /// TODO: I should probably find a more type-safe way to enforce this API...
     pub fn destroy_root(self) -> driver_util::Result<()> {
-        let Self { parent: _, state } = self;
+        let Self { state, .. } = self;
         let state = Arc::into_inner(state).expect("was still running");
         state.executor.stop();
+        let discarded = state
+            .discarded
+            .into_inner()
+            .expect("discarded set poisoned");
+        if !discarded.is_empty() {
+            warn!(
+                "discarding {} results produced after cancelling",
+                discarded.len()
+            );
+            state.db.remove_keys(&discarded);
+        }
         state.db.save(&state.options)
     }
 
+    /// Creates a new top-level context that shares the database, object store, executor, & hooks
+    /// with `base`, but has its own dependency scope: it isn't attached to any parent query, keeps
+    /// its own [`Self::roots`], & traces `Options::trace_query` separately. Meant for long-lived
+    /// processes that want a cheap, fresh context per request while still hitting the warm cache.
+    ///
+    /// All warmed contexts MUST be dropped before calling `.destroy_root()` on `base`.
+    pub fn warm_from(base: &Self) -> Self {
+        Self {
+            parent: None,
+            stack: None,
+            scope: Default::default(),
+            state: base.state.clone(),
+        }
+    }
+
     /// Creates a root context with an empty database and a single-threaded executor. Only meant
     /// for testing, you probably want to use `Context::create_root()` instead.
     pub fn create_empty_root_for_testing_only() -> Self {
@@ -133,15 +388,64 @@ impl<Key: Producer<Key>> Context<Key> {
 
         Self {
             parent: None,
+            stack: None,
+            scope: Default::default(),
             state: Arc::new(State {
                 options,
                 db,
                 executor,
                 hooks: None,
+                build_time: SystemTime::now(),
+                cancellation: Default::default(),
+                discarded: Default::default(),
+                extensions: Default::default(),
             }),
         }
     }
 
+    /// Whether `hashed` is the key we were asked to trace (via `Options::trace_query`), or one of
+    /// its dependencies. Dependencies are found as we go: anything queried by a focused key
+    /// becomes focused too.
+    pub(crate) fn is_focused(
+        &self,
+        parent: Option<Hashed<Key>>,
+        hashed: &Hashed<Key>,
+        key: &Key,
+    ) -> bool {
+        let Some(target) = &self.options().trace_query else {
+            return false;
+        };
+        let mut focus = self.scope.focus.lock().expect("focus set poisoned");
+        if focus.contains(hashed) {
+            return true;
+        }
+        if parent.is_some_and(|parent| focus.contains(&parent)) || key.to_string() == *target {
+            focus.insert(*hashed);
+            return true;
+        }
+        false
+    }
+
+    /// Fast path for `query(key, ctx)`: if the key has already been verified this revision, there's
+    /// no point in boxing up a task and hopping over to the executor just to clone the value out of
+    /// the cache, so we do it right here instead. Returns `None` if we need to go the slow way.
+    pub(crate) fn query_verified(&self, key: &Key) -> Option<(Hashed<Key>, Key::Output)> {
+        if key.is_volatile() {
+            return None;
+        }
+        let revision = self.db().revision.load(Ordering::SeqCst);
+        let (hashed, value) = self.db().get_verified(key, revision)?;
+        trace!("fast path hit for {hashed:?}");
+        self.db().hit_stats.record_hit();
+        if self.is_focused(self.parent, &hashed, key) {
+            info!(target: TRACE_QUERY, "{key}: already verified this revision");
+        }
+        if let Some(parent) = self.parent {
+            self.db().add_dependency(parent, hashed);
+        }
+        Some((hashed, value))
+    }
+
     /// NOTE: most code that runs inside a query itself should use the `query(key, ctx)` form
     /// instead. This function is meant to be used by the executor itself.
     #[tracing::instrument(level = "debug", skip(self), fields(key=%key))]
@@ -150,17 +454,20 @@ impl<Key: Producer<Key>> Context<Key> {
         self.db()
             .upsert(key, async |hashed, key, entry| {
                 trace!("locked");
-                self.query_entry(hashed, key, entry).await
+                self.query_entry(hashed, key, entry, self.depth()).await
             })
             .await
     }
 
+    /// `depth` is how many queries deep this is, counting both the ones being produced & the ones
+    /// being checked for changes.
     #[tracing::instrument(level = "debug", skip(self, entry), fields(key=%key))]
     async fn query_entry(
         &self,
         hashed: &Hashed<Key>,
         key: &Key,
         entry: &mut Entry<Key::Output>,
+        depth: usize,
     ) -> Key::Output {
         trace!("starting query");
         if let Some(parent) = self.parent {
@@ -171,6 +478,7 @@ impl<Key: Producer<Key>> Context<Key> {
 
         let revision = self.db().revision.load(Ordering::SeqCst);
         let verified_at = entry.revision().map(|rev| rev.verified_at);
+        let focused = self.is_focused(self.parent, hashed, key);
 
         let maybe_changed = match verified_at {
             // If we've never seen it before, it's always "changed"
@@ -180,16 +488,24 @@ impl<Key: Producer<Key>> Context<Key> {
             }
             // If we have seen it before, check it again
             Some(verified_at) => {
-                self.maybe_changed_after(verified_at, hashed, key, revision, entry)
+                self.maybe_changed_after(verified_at, hashed, key, revision, entry, depth)
                     .await
             }
         };
         if !maybe_changed {
+            self.db().hit_stats.record_hit();
+            if focused {
+                info!(target: TRACE_QUERY, "{key}: unchanged since revision {verified_at:?}");
+            }
             return entry
                 .value()
                 .unwrap_or_else(|| panic!("Verified query {key} missing value in cache"));
         }
 
+        self.db().hit_stats.record_miss();
+        if focused {
+            info!(target: TRACE_QUERY, "{key}: computing");
+        }
         trace!("removing dependencies");
         // We're about to run the key again, so remove any dependencies it once had
         let old_deps = self.db().dependencies(hashed).unwrap_or_default();
@@ -199,6 +515,12 @@ impl<Key: Producer<Key>> Context<Key> {
         let value = key
             .produce(&Context {
                 parent: Some(*hashed),
+                stack: Some(Arc::new(Frame {
+                    key: key.clone(),
+                    parent: self.stack.clone(),
+                    depth: self.depth() + 1,
+                })),
+                scope: self.scope.clone(),
                 state: self.state.clone(),
             })
             .await;
@@ -206,8 +528,19 @@ impl<Key: Producer<Key>> Context<Key> {
 
         entry.insert(revision, value.clone());
         trace!("inserted entry");
+        if self.is_cancelled() {
+            // Might have been cut short, so it's not safe to reuse next time
+            self.state
+                .discarded
+                .lock()
+                .expect("discarded set poisoned")
+                .insert(*hashed);
+        }
 
         let new_deps = self.db().dependencies(hashed).unwrap_or_default();
+        if focused {
+            info!(target: TRACE_QUERY, "{key}: computed {} dependencies", new_deps.len());
+        }
         if let Some(hooks) = &self.state.hooks {
             hooks.on_compute(self, key.clone(), old_deps, new_deps);
         }
@@ -223,12 +556,18 @@ impl<Key: Producer<Key>> Context<Key> {
         key: &Key,
         current_revision: usize,
         entry: &mut Entry<Key::Output>,
+        depth: usize,
     ) -> bool {
         let Some(rev) = entry.revision() else {
             trace!("no revision, need to calculate");
             return true;
         };
 
+        if key.is_volatile() {
+            trace!("volatile key, need to calculate");
+            return true;
+        }
+
         if key.is_input() {
             trace!(
                 "checking input: ({} > {}) || ({} > {})?",
@@ -242,6 +581,13 @@ impl<Key: Producer<Key>> Context<Key> {
             return rev.changed_at > verified_at;
         }
 
+        if depth >= self.options().max_query_depth {
+            // Checking goes one level deeper per dependency, all on the same stack. Re-running is
+            // always safe, and whatever the key queries gets checked fresh from there.
+            trace!("too deep to check dependencies, need to calculate");
+            return true;
+        }
+
         trace!("trying to get dependencies");
         let Some(deps) = self.db().dependencies::<Vec<_>>(hashed) else {
             trace!("no dependencies");
@@ -253,46 +599,21 @@ impl<Key: Producer<Key>> Context<Key> {
         };
 
         trace!("got dependencies");
-        for dep in deps {
-            trace!("locking {dep:?}");
-            if self
-                .db()
-                .get_mut(dep, async |dep_entry| {
-                    trace!("locked {dep:?}");
-                    let (dep_hashed, dep_key, dep_entry) = match dep_entry {
-                        None => {
-                            warn!("non-existent dependency: {dep:?}");
-                            return true;
-                        }
-                        Some(dep_entry) => dep_entry,
-                    };
-                    let dep_maybe_changed = Box::pin(self.maybe_changed_after(
-                        verified_at,
-                        dep_hashed,
-                        dep_key,
-                        current_revision,
-                        dep_entry,
-                    ))
-                    .await;
-                    if !dep_maybe_changed {
-                        trace!("dep {dep_hashed:?} definitely hasn't changed");
-                        return false;
-                    }
-
-                    trace!("pre-querying dep {dep_hashed:?}");
-                    let _ = Box::pin(self.query_entry(dep_hashed, dep_key, dep_entry)).await;
-
-                    let dep_rev = dep_entry
-                        .revision()
-                        .expect("revision must be set after query");
-                    trace!(
-                        "checking dep condition: {} > {}?",
-                        dep_rev.changed_at, verified_at
-                    );
-                    dep_rev.changed_at > verified_at
+        // Checking a dependency can mean re-running it, so check a few at once. Order still
+        // matters somewhat: a dependency that changed means the key has to re-run anyways, & the
+        // re-run might not even need the dependencies after it, so we stop at the first batch
+        // with a change instead of checking everything.
+        let limit = self.options().max_concurrent_checks.max(1);
+        for batch in deps.chunks(limit) {
+            let changed = batch
+                .iter()
+                .map(|dep| {
+                    self.dep_maybe_changed(*dep, verified_at, hashed, key, current_revision, depth)
                 })
-                .await
-            {
+                .collect::<Vec<_>>()
+                .join()
+                .await;
+            if changed.into_iter().any(|changed| changed) {
                 return true;
             }
         }
@@ -301,4 +622,612 @@ impl<Key: Producer<Key>> Context<Key> {
         entry.mark_verified(current_revision);
         rev.changed_at > verified_at
     }
+
+    /// Whether `dep`, a dependency of `key`, changed after `verified_at`, re-running it if needed
+    /// to find out.
+    async fn dep_maybe_changed(
+        &self,
+        dep: Hashed<Key>,
+        verified_at: usize,
+        hashed: &Hashed<Key>,
+        key: &Key,
+        current_revision: usize,
+        depth: usize,
+    ) -> bool {
+        trace!("locking {dep:?}");
+        self.db()
+            .get_mut(dep, async |dep_entry| {
+                trace!("locked {dep:?}");
+                let (dep_hashed, dep_key, dep_entry) = match dep_entry {
+                    None => {
+                        warn!("non-existent dependency: {dep:?}");
+                        return true;
+                    }
+                    Some(dep_entry) => dep_entry,
+                };
+                let dep_maybe_changed = Box::pin(self.maybe_changed_after(
+                    verified_at,
+                    dep_hashed,
+                    dep_key,
+                    current_revision,
+                    dep_entry,
+                    depth + 1,
+                ))
+                .await;
+                if self.is_focused(Some(*hashed), dep_hashed, dep_key) {
+                    info!(
+                        target: TRACE_QUERY,
+                        "{key}: dependency {dep_key} maybe changed? {dep_maybe_changed}"
+                    );
+                }
+                if !dep_maybe_changed {
+                    trace!("dep {dep_hashed:?} definitely hasn't changed");
+                    return false;
+                }
+
+                trace!("pre-querying dep {dep_hashed:?}");
+                let _ = Box::pin(self.query_entry(dep_hashed, dep_key, dep_entry, depth + 1)).await;
+
+                let dep_rev = dep_entry
+                    .revision()
+                    .expect("revision must be set after query");
+                trace!(
+                    "checking dep condition: {} > {}?",
+                    dep_rev.changed_at, verified_at
+                );
+                dep_rev.changed_at > verified_at
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, AtomicUsize};
+
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::{NamedBlobs, query, query_with_hash};
+
+    /// Every `Counted` that's been produced, across all tests.
+    static PRODUCED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    fn times_produced(n: u32) -> usize {
+        PRODUCED.lock().unwrap().iter().filter(|m| **m == n).count()
+    }
+
+    crate::key!(
+        #[input=|_| false]
+        struct Counted(u32);
+    );
+    crate::no_blobs!(Counted);
+    crate::producer!(Counted(self, _ctx) -> u32 {
+        PRODUCED.lock().unwrap().push(self.0);
+        self.0 * 2
+    });
+    impl std::fmt::Display for Counted {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Counted({})", self.0)
+        }
+    }
+
+    crate::key!(
+        #[input=|_| false]
+        struct Sum(u32);
+    );
+    crate::no_blobs!(Sum);
+    crate::producer!(Sum(self, ctx) where [Counted] -> u32 {
+        let mut sum = 0;
+        for n in 0..self.0 {
+            sum += query(ctx, Counted(n)).await;
+        }
+        sum
+    });
+    impl std::fmt::Display for Sum {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Sum({})", self.0)
+        }
+    }
+
+    /// Stands in for something like a compiler, which emits code & a sourcemap in one go.
+    crate::key!(
+        #[input=|_| false]
+        struct Compile(String);
+    );
+    crate::no_blobs!(Compile);
+    crate::producer!(Compile(self, ctx) -> driver_util::Result<NamedBlobs> {
+        let mut outputs = NamedBlobs::new();
+        outputs
+            .insert("js", ctx.store(format!("compiled {}", self.0).into_bytes())?)
+            .insert("map", ctx.store(format!("map of {}", self.0).into_bytes())?);
+        Ok(outputs)
+    });
+    impl std::fmt::Display for Compile {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Compile({})", self.0)
+        }
+    }
+
+    /// Every input `Shout` has been produced for, across all tests.
+    static SHOUTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    crate::key!(
+        #[input=|_| false]
+        struct Shout(Blob);
+    );
+    crate::blob_trace!(Shout => (0));
+    crate::producer!(Shout(self, ctx) -> driver_util::Result<String> {
+        let input = ctx.load_string(&self.0)?;
+        SHOUTED.lock().unwrap().push(input.clone());
+        Ok(input.to_uppercase())
+    });
+    impl std::fmt::Display for Shout {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Shout({})", self.0)
+        }
+    }
+
+    /// Stands in for something like reading a file off a slow disk.
+    crate::key!(
+        #[input=|_| true]
+        struct Slow(u32);
+    );
+    crate::no_blobs!(Slow);
+    crate::producer!(Slow(self, _ctx) -> u32 {
+        async_io::Timer::after(Duration::from_millis(100)).await;
+        self.0
+    });
+    impl std::fmt::Display for Slow {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Slow({})", self.0)
+        }
+    }
+
+    crate::key!(
+        #[input=|_| false]
+        struct SlowSum(u32);
+    );
+    crate::no_blobs!(SlowSum);
+    crate::producer!(SlowSum(self, ctx) where [Slow] -> u32 {
+        let mut sum = 0;
+        for n in 0..self.0 {
+            sum += query(ctx, Slow(n)).await;
+        }
+        sum
+    });
+    impl std::fmt::Display for SlowSum {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "SlowSum({})", self.0)
+        }
+    }
+
+    /// How many `Overlap`s are being produced right now, & the most there have been at once.
+    static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Like `Slow`, but counts how many are being produced at once. Only one test uses it, so the
+    /// counts don't get muddled by other tests running alongside.
+    crate::key!(
+        #[input=|_| true]
+        struct Overlap(u32);
+    );
+    crate::no_blobs!(Overlap);
+    crate::producer!(Overlap(self, _ctx) -> u32 {
+        let now = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+        PEAK_IN_FLIGHT.fetch_max(now, Ordering::SeqCst);
+        async_io::Timer::after(Duration::from_millis(20)).await;
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        self.0
+    });
+    impl std::fmt::Display for Overlap {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Overlap({})", self.0)
+        }
+    }
+
+    crate::key!(
+        #[input=|_| false]
+        struct OverlapSum(u32);
+    );
+    crate::no_blobs!(OverlapSum);
+    crate::producer!(OverlapSum(self, ctx) where [Overlap] -> u32 {
+        let mut sum = 0;
+        for n in 0..self.0 {
+            sum += query(ctx, Overlap(n)).await;
+        }
+        sum
+    });
+    impl std::fmt::Display for OverlapSum {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "OverlapSum({})", self.0)
+        }
+    }
+
+    /// Depends on the other of a pair, which depends right back on it.
+    crate::key!(
+        #[input=|_| false]
+        struct Loop(u32);
+    );
+    crate::no_blobs!(Loop);
+    crate::producer!(Loop(self, ctx) where [Loop] -> driver_util::Result<u32> {
+        query(ctx, Loop(1 - self.0)).await
+    });
+    impl std::fmt::Display for Loop {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Loop({})", self.0)
+        }
+    }
+
+    /// Stands in for something like the current time, which is different every time it's asked.
+    static TICKS: AtomicU32 = AtomicU32::new(0);
+
+    crate::key!(
+        #[input=|_| true, volatile=|_| true]
+        struct Tick;
+    );
+    crate::no_blobs!(Tick);
+    crate::producer!(Tick(self, _ctx) -> u32 {
+        TICKS.fetch_add(1, Ordering::SeqCst)
+    });
+    impl std::fmt::Display for Tick {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Tick")
+        }
+    }
+
+    crate::query!(Key {
+        Counted,
+        Sum,
+        Compile,
+        Shout,
+        Slow,
+        SlowSum,
+        Overlap,
+        OverlapSum,
+        Loop,
+        Tick,
+    } with Output);
+
+    #[test]
+    fn trace_query_focus() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = Options::with_base_dir(dir.path());
+        options.trace_query = Some("Sum(3)".to_string());
+        let ctx = Context::<Key>::create_root(options, None);
+
+        let (sum, output) = block_on(query_with_hash(&ctx, Sum(3)));
+        assert_eq!(output, 6);
+        let (unrelated, _) = block_on(query_with_hash(&ctx, Counted(100)));
+        let (dep, _) = block_on(query_with_hash(&ctx, Counted(2)));
+
+        let focus = ctx.scope.focus.lock().unwrap().clone();
+        assert!(focus.contains(&sum));
+        assert!(focus.contains(&dep));
+        // Not part of Sum(3), so nothing gets logged for it
+        assert!(!focus.contains(&unrelated));
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn reports_cycles_as_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+
+        let err = block_on(query(&ctx, Loop(0))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dependency cycle detected: Loop(0) -> Loop(1) -> Loop(0)"
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+
+        let blob = ctx.store(b"hello".to_vec()).unwrap();
+        assert_eq!(ctx.load_bytes(&blob).unwrap(), b"hello");
+        assert_eq!(ctx.load_string(&blob).unwrap(), "hello");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn named_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+
+        let outputs = block_on(query(&ctx, Compile("main.ts".to_string()))).unwrap();
+        let js = outputs.get("js").unwrap();
+        assert_eq!(ctx.load_string(js).unwrap(), "compiled main.ts");
+        let map = outputs.get("map").unwrap();
+        assert_eq!(ctx.load_string(map).unwrap(), "map of main.ts");
+        assert!(outputs.get("css").unwrap_err().is_not_found());
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn identical_inputs_share_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+
+        // Say, the same snippet read out of two different files
+        let first = ctx.store(b"dedupe me".to_vec()).unwrap();
+        let second = ctx.store(b"dedupe me".to_vec()).unwrap();
+        assert_eq!(block_on(query(&ctx, Shout(first))).unwrap(), "DEDUPE ME");
+        assert_eq!(block_on(query(&ctx, Shout(second))).unwrap(), "DEDUPE ME");
+
+        let shouted = SHOUTED.lock().unwrap();
+        assert_eq!(shouted.iter().filter(|s| *s == "dedupe me").count(), 1);
+        drop(shouted);
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[derive(Clone, Default)]
+    struct CountingHooks {
+        started: Arc<AtomicUsize>,
+        finished: Arc<AtomicUsize>,
+        failed: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Hooks<Key> for CountingHooks {
+        fn on_compute(
+            &self,
+            _ctx: &Context<Key>,
+            _key: Key,
+            _old_deps: HashSet<Hashed<Key>>,
+            _new_deps: HashSet<Hashed<Key>>,
+        ) {
+        }
+
+        fn on_query_start(&self, _ctx: &Context<Key>, _key: &Key) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_query_finish(
+            &self,
+            _ctx: &Context<Key>,
+            _key: &Key,
+            _output: &Output,
+            _elapsed: Duration,
+        ) {
+            self.finished.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_query_error(&self, _ctx: &Context<Key>, key: &Key, _error: &driver_util::Error) {
+            self.failed.lock().unwrap().push(key.to_string());
+        }
+    }
+
+    #[test]
+    fn hooks_fire_per_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = CountingHooks::default();
+        let ctx = Context::<Key>::create_root(
+            Options::with_base_dir(dir.path()),
+            Some(Box::new(hooks.clone())),
+        );
+
+        block_on(query(&ctx, Counted(1)));
+        block_on(query(&ctx, Counted(1)));
+        block_on(query(&ctx, Counted(2)));
+        assert_eq!(hooks.started.load(Ordering::SeqCst), 3);
+        assert_eq!(hooks.finished.load(Ordering::SeqCst), 3);
+        assert!(hooks.failed.lock().unwrap().is_empty());
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn hooks_see_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = CountingHooks::default();
+        let ctx = Context::<Key>::create_root(
+            Options::with_base_dir(dir.path()),
+            Some(Box::new(hooks.clone())),
+        );
+
+        // The cycle is caught when `Loop(0)` is queried again, before it starts, so only the two
+        // that actually started fail
+        assert!(block_on(query(&ctx, Loop(0))).is_err());
+        assert_eq!(*hooks.failed.lock().unwrap(), ["Loop(1)", "Loop(0)"]);
+        // Cached errors still count
+        assert!(block_on(query(&ctx, Loop(0))).is_err());
+        assert_eq!(hooks.failed.lock().unwrap().len(), 3);
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn hit_ratio() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let cold = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&cold, Sum(7))), 42);
+        assert_eq!(cold.hit_stats().hits(), 0);
+        assert_eq!(cold.hit_stats().misses(), 8);
+        assert_eq!(cold.hit_stats().ratio(), 0.0);
+        cold.destroy_root().unwrap();
+
+        let warm = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&warm, Sum(7))), 42);
+        assert_eq!(warm.hit_stats().misses(), 0);
+        assert_eq!(warm.hit_stats().ratio(), 1.0);
+        warm.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn checks_dependencies_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let cold = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&cold, OverlapSum(5))), 10);
+        cold.destroy_root().unwrap();
+        // The sum itself queries them one after another
+        assert_eq!(PEAK_IN_FLIGHT.swap(0, Ordering::SeqCst), 1);
+
+        // Every input has to be re-read to see if the sum is still good, but none of them depend
+        // on each other, so that can all happen at once.
+        let warm = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&warm, OverlapSum(5))), 10);
+        assert_eq!(warm.hit_stats().misses(), 5);
+        warm.destroy_root().unwrap();
+        assert_eq!(PEAK_IN_FLIGHT.swap(0, Ordering::SeqCst), 5);
+
+        // Up to the limit, that is
+        let mut options = Options::with_base_dir(dir.path());
+        options.max_concurrent_checks = 2;
+        let limited = Context::<Key>::create_root(options, None);
+        assert_eq!(block_on(query(&limited, OverlapSum(5))), 10);
+        assert_eq!(limited.hit_stats().misses(), 5);
+        limited.destroy_root().unwrap();
+        assert_eq!(PEAK_IN_FLIGHT.swap(0, Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn concurrent_queries_produce_once() {
+        use futures_concurrency::future::Join as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+
+        // Whoever gets to the entry first produces it, and everyone else waits on the entry lock
+        // then finds it already verified.
+        let outputs = (0..8)
+            .map(|_| query(&ctx, Slow(7)))
+            .collect::<Vec<_>>()
+            .join();
+        assert_eq!(block_on(outputs), vec![7; 8]);
+        assert_eq!(ctx.hit_stats().misses(), 1);
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn new_revision_rechecks_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&ctx, SlowSum(2))), 1);
+        let misses = ctx.hit_stats().misses();
+
+        // Same revision, so nothing gets looked at again
+        assert_eq!(block_on(query(&ctx, SlowSum(2))), 1);
+        assert_eq!(ctx.hit_stats().misses(), misses);
+
+        // The inputs get re-read, but the sum is still good
+        ctx.new_revision();
+        assert_eq!(block_on(query(&ctx, SlowSum(2))), 1);
+        assert_eq!(ctx.hit_stats().misses(), misses + 2);
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn repeated_hits_take_the_fast_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert_eq!(block_on(query(&ctx, Counted(41))), 82);
+        let key = Key::from(Counted(41));
+        assert!(ctx.query_verified(&key).is_some());
+
+        let hits = ctx.hit_stats().hits();
+        for _ in 0..100 {
+            assert_eq!(block_on(query(&ctx, Counted(41))), 82);
+        }
+        assert_eq!(ctx.hit_stats().hits(), hits + 100);
+        assert_eq!(times_produced(41), 1);
+
+        // Needs checking again, which the fast path doesn't do
+        ctx.new_revision();
+        assert!(ctx.query_verified(&key).is_none());
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn volatile_keys_always_rerun() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        let first = block_on(query(&ctx, Tick));
+        let second = block_on(query(&ctx, Tick));
+        assert_ne!(first, second);
+        block_on(query(&ctx, Counted(42)));
+        ctx.destroy_root().unwrap();
+
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        // SAFETY: nothing's being computed
+        unsafe {
+            assert!(ctx.db().get_value(&Tick.into()).is_none());
+            assert!(ctx.db().get_value(&Counted(42).into()).is_some());
+        }
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn discards_results_after_cancelling() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        block_on(query(&ctx, Counted(31)));
+        ctx.cancellation().cancel();
+        block_on(query(&ctx, Counted(32)));
+        ctx.destroy_root().unwrap();
+
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        assert!(!ctx.is_cancelled());
+        block_on(query(&ctx, Counted(31)));
+        block_on(query(&ctx, Counted(32)));
+        assert_eq!(times_produced(31), 1);
+        assert_eq!(times_produced(32), 2);
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn stops_producing_after_cancelling() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(dir.path()), None);
+        let input = ctx.store(b"too late".to_vec()).unwrap();
+        ctx.cancellation().cancel();
+
+        let err = block_on(query(&ctx, Shout(input))).unwrap_err();
+        assert!(err.is_cancelled());
+        assert!(!SHOUTED.lock().unwrap().contains(&"too late".to_string()));
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn warm_context_hits_base_cache() {
+        let base = Context::<Key>::create_empty_root_for_testing_only();
+        assert_eq!(block_on(query(&base, Counted(21))), 42);
+        assert_eq!(times_produced(21), 1);
+
+        let warm = Context::warm_from(&base);
+        assert!(warm.parent.is_none());
+        assert_eq!(block_on(query(&warm, Counted(21))), 42);
+        assert_eq!(times_produced(21), 1);
+    }
+
+    #[test]
+    fn warm_contexts_have_their_own_roots() {
+        let base = Context::<Key>::create_empty_root_for_testing_only();
+        let (counted, _) = block_on(query_with_hash(&base, Counted(23)));
+
+        let warm = Context::warm_from(&base);
+        let (sum, _) = block_on(query_with_hash(&warm, Sum(2)));
+        assert_eq!(warm.roots(), BTreeSet::from([sum]));
+        assert_eq!(base.roots(), BTreeSet::from([counted]));
+        assert_eq!(Context::warm_from(&base).roots(), BTreeSet::new());
+        // Dependencies of what the warm context queried are still shared
+        assert_eq!(
+            base.db()
+                .dependencies::<BTreeSet<_>>(&sum)
+                .map(|deps| deps.len()),
+            Some(2)
+        );
+    }
 }
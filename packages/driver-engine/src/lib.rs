@@ -6,6 +6,11 @@
 //! - `query`, which allows producers/external code to run an incremental computation.
 //! - A whole host of macros (`key!`, `producer!`, `query_key!`) to make writing these easier.
 //!
+//! Transforms over file contents should take the [`Blob`] as their key, not the path it came from.
+//! Blobs are content-addressed, so two callers that end up with byte-identical inputs (say, the
+//! same markdown included from two different pages) ask for the exact same key, and the work only
+//! gets done once, no matter where the bytes came from.
+//!
 //! Example usage:
 //!
 //! ```rust
@@ -41,9 +46,13 @@
 //! }
 //! ```
 
+mod cancel;
+pub use cancel::CancellationToken;
+
 mod context;
 pub use context::Context;
 pub use context::Hooks;
+pub use context::TRACE_QUERY;
 
 mod producer;
 pub use producer::Downcastable;
@@ -52,11 +61,20 @@ pub use producer::ProducerBase;
 pub use producer::query;
 pub use producer::query_with_hash;
 
+mod retry;
+pub use retry::RetryPolicy;
+pub use retry::retry;
+
 /// Re-export for convenience
+pub use driver_db::CacheStats;
+pub use driver_db::HitStats;
 pub use driver_db::Options;
+pub use driver_db::PostProcessRule;
+pub use driver_db::Snapshot;
 pub use driver_db::Uri;
 pub use driver_util::Blob;
 pub use driver_util::BlobTrace;
+pub use driver_util::NamedBlobs;
 pub use driver_util::blob_trace;
 pub use driver_util::key;
 pub use driver_util::no_blobs;
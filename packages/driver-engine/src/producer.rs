@@ -1,9 +1,27 @@
+use std::time::Instant;
+
 use crate::Context;
 
 /// A trait that registers a key with the potential to produce exactly **one** output type. Not
 /// part of `Producer` in order to resolve some nasty trait recursion things.
 pub trait ProducerBase: driver_util::Key {
     type Output: driver_util::Output;
+
+    /// What to give back instead of producing this key, when it can't be: either producing it
+    /// would mean waiting on itself, or the build's been cancelled. `None` (the default) means
+    /// there's nothing sensible, so a cycle panics & a cancelled build produces it anyways.
+    /// [`producer!`] fills this in with the error for every producer that returns a
+    /// [`driver_util::Result`].
+    fn on_error(_error: driver_util::Error) -> Option<Self::Output> {
+        None
+    }
+
+    /// The error `output` holds, if producing this key failed. `None` (the default) means it
+    /// didn't, as far as [`Hooks::on_query_error`](crate::Hooks::on_query_error) is concerned.
+    /// [`producer!`] fills this in for every producer that returns a [`driver_util::Result`].
+    fn error_of(_output: &Self::Output) -> Option<&driver_util::Error> {
+        None
+    }
 }
 
 /// The main trait that library authors should implement to support incremental compilation. The
@@ -20,12 +38,64 @@ pub trait Producer<Key: ProducerBase>: ProducerBase {
 /// Formatted mostly like a normal function declaration, except in the brackets we put all the
 /// subqueries we use inside this function, in order to generate the appropriate trait bounds. See
 /// module documentation for an example.
+///
+/// Producers returning a [`driver_util::Result`] give back an error when they'd depend on
+/// themselves, or the build's been cancelled. Others can say what to return instead with
+/// `-> Output, on_error(error) { ... }`. See [`ProducerBase::on_error`].
 #[macro_export]
 macro_rules! producer {
-    ($name:ident ($self:ident, $ctx:ident) $(where [ $( $subkey:ident ),* ])? -> $output:ty { $($tt:tt)* }) => {
+    // Producers that can fail report cycles & cancellation as an error
+    ($name:ident ($self:ident, $ctx:ident) $(where [ $( $subkey:ident ),* ])? -> driver_util::Result<$inner:ty> { $($tt:tt)* }) => {
+        $crate::producer!(@result_error $name, $inner);
+        $crate::producer!(@produce $name ($self, $ctx) $(where [ $( $subkey ),* ])? -> driver_util::Result<$inner> { $($tt)* });
+    };
+
+    ($name:ident ($self:ident, $ctx:ident) as ($query_key:ty) -> driver_util::Result<$inner:ty> { $($tt:tt)* }) => {
+        $crate::producer!(@result_error $name, $inner);
+        $crate::producer!(@produce $name ($self, $ctx) as ($query_key) -> driver_util::Result<$inner> { $($tt)* });
+    };
+
+    ($name:ident ($self:ident, $ctx:ident) $(where [ $( $subkey:ident ),* ])? -> $output:ty $(, on_error($err:ident) $on_error:block)? { $($tt:tt)* }) => {
+        $crate::producer!(@base $name, $output $(, $err $on_error)?);
+        $crate::producer!(@produce $name ($self, $ctx) $(where [ $( $subkey ),* ])? -> $output { $($tt)* });
+    };
+
+    ($name:ident ($self:ident, $ctx:ident) as ($query_key:ty) -> $output:ty $(, on_error($err:ident) $on_error:block)? { $($tt:tt)* }) => {
+        $crate::producer!(@base $name, $output $(, $err $on_error)?);
+        $crate::producer!(@produce $name ($self, $ctx) as ($query_key) -> $output { $($tt)* });
+    };
+
+    (@result_error $name:ident, $inner:ty) => {
+        impl $crate::ProducerBase for $name {
+            type Output = driver_util::Result<$inner>;
+
+            fn on_error(error: driver_util::Error) -> Option<Self::Output> {
+                Some(Err(error))
+            }
+
+            fn error_of(output: &Self::Output) -> Option<&driver_util::Error> {
+                output.as_ref().err()
+            }
+        }
+    };
+
+    (@base $name:ident, $output:ty) => {
         impl $crate::ProducerBase for $name {
             type Output = $output;
         }
+    };
+
+    (@base $name:ident, $output:ty, $err:ident $on_error:block) => {
+        impl $crate::ProducerBase for $name {
+            type Output = $output;
+
+            fn on_error($err: driver_util::Error) -> Option<Self::Output> {
+                Some($on_error)
+            }
+        }
+    };
+
+    (@produce $name:ident ($self:ident, $ctx:ident) $(where [ $( $subkey:ident ),* ])? -> $output:ty { $($tt:tt)* }) => {
         impl<Key> $crate::Producer<Key> for $name
         where
             Key: $crate::Producer<Key>,
@@ -38,10 +108,7 @@ macro_rules! producer {
         }
     };
 
-    ($name:ident ($self:ident, $ctx:ident) as ($query_key:ty) -> $output:ty { $($tt:tt)* }) => {
-        impl $crate::ProducerBase for $name {
-            type Output = $output;
-        }
+    (@produce $name:ident ($self:ident, $ctx:ident) as ($query_key:ty) -> $output:ty { $($tt:tt)* }) => {
         impl $crate::Producer<$query_key> for $name {
             async fn produce(&$self, $ctx: &$crate::Context<$query_key>) -> $output { $($tt)* }
         }
@@ -73,14 +140,49 @@ where
     KLarge: Producer<KLarge>,
     KLarge::Output: Downcastable,
 {
-    let (hash, output) = ctx
-        .executor()
-        .execute_pinned({
-            let ctx = ctx.clone();
-            let key = key.into();
-            move || ctx.query_internal(key)
-        })
-        .await;
+    let key = key.into();
+    if let Some(cycle) = ctx.find_cycle(&key) {
+        let cycle: Vec<String> = cycle.iter().map(|key| key.to_string()).collect();
+        let message = format!("dependency cycle detected: {}", cycle.join(" -> "));
+        // Producers that can't fail have nothing to return instead, but panicking still beats
+        // deadlocking. Callers with their own way of reporting errors (like scripts running other
+        // scripts) should check for this themselves first.
+        match KSmall::on_error(driver_util::Error::new(&message)) {
+            Some(output) => return (driver_db::Hashed::new(&key), output),
+            None => panic!("{message}"),
+        }
+    }
+    // No point starting anything new, since none of it will be kept
+    if let Err(error) = ctx.cancellation().check()
+        && let Some(output) = KSmall::on_error(error)
+    {
+        return (driver_db::Hashed::new(&key), output);
+    }
+    let start = Instant::now();
+    if let Some(hooks) = ctx.hooks() {
+        hooks.on_query_start(ctx, &key);
+    }
+
+    let (hash, output) = match ctx.query_verified(&key) {
+        Some(found) => found,
+        None => {
+            ctx.executor()
+                .execute_pinned({
+                    let ctx = ctx.clone();
+                    let key = key.clone();
+                    move || ctx.query_internal(key)
+                })
+                .await
+        }
+    };
+    ctx.add_root(hash);
+
+    if let Some(hooks) = ctx.hooks() {
+        hooks.on_query_finish(ctx, &key, &output, start.elapsed());
+        if let Some(error) = KLarge::error_of(&output) {
+            hooks.on_query_error(ctx, &key, error);
+        }
+    }
 
     (
         hash,
@@ -120,6 +222,12 @@ macro_rules! query {
 
         impl $crate::ProducerBase for $name {
             type Output = $output;
+
+            fn error_of(output: &$output) -> Option<&driver_util::Error> {
+                match output { $(
+                    $output::$key(output) => <$key as $crate::ProducerBase>::error_of(output),
+                )* }
+            }
         }
 
         impl $crate::Producer<$name> for $name {
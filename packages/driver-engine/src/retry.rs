@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+/// How many times to retry something flaky, and how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of tries, including the first one.
+    pub max_attempts: usize,
+    /// How long to wait after the first failure. Doubles after every failure after that.
+    pub initial_backoff: Duration,
+    /// Backoff never goes above this.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Runs `f` until it succeeds, fails with a non-transient error, or runs out of attempts. Only
+/// errors marked [`driver_util::ErrorKind::Transient`] get retried, so deterministic failures
+/// (404s, parse errors, etc.) still fail right away.
+///
+/// Meant to be used _inside_ a producer, so the query itself only ever sees the final result.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, mut f: F) -> driver_util::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = driver_util::Result<T>>,
+{
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if e.is_transient() && attempt < policy.max_attempts => {
+                warn!("attempt {attempt} failed, retrying in {backoff:?}: {e}");
+                async_io::Timer::after(backoff).await;
+                backoff = std::cmp::min(backoff * 2, policy.max_backoff);
+                attempt += 1;
+            }
+            out => return out,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    const FAST: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+    };
+
+    #[test]
+    fn fails_twice_then_succeeds() {
+        let calls = Cell::new(0);
+        let out = block_on(retry(&FAST, || async {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(driver_util::Error::transient("try again"))
+            } else {
+                Ok(calls.get())
+            }
+        }));
+        assert_eq!(out, Ok(3));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn gives_up() {
+        let calls = Cell::new(0);
+        let out: driver_util::Result<()> = block_on(retry(&FAST, || async {
+            calls.set(calls.get() + 1);
+            Err(driver_util::Error::transient("try again"))
+        }));
+        assert!(out.is_err());
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn deterministic_errors_not_retried() {
+        let calls = Cell::new(0);
+        let out: driver_util::Result<()> = block_on(retry(&FAST, || async {
+            calls.set(calls.get() + 1);
+            Err(driver_util::Error::new("nope"))
+        }));
+        assert!(out.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -0,0 +1,334 @@
+//! Hashing a whole directory tree down to a single digest, so that something can depend on "this
+//! entire directory" without having to list & read every file inside it.
+//!
+//! Every directory's digest is made by folding in each of its children, sorted by name, as
+//! `<kind> <name> NUL <child digest>`. That way, renaming or moving a file changes the hash just as
+//! much as editing it does. Symlinks that aren't followed are hashed by the path they point to.
+//!
+//! SHA-256 is used unless asked otherwise (see [`walk_with_digest`]). Other digests only swap out
+//! the primitive; everything gets folded together exactly the same way.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use driver_util::Hash;
+use sha2::Digest;
+use sha2::digest::Output;
+
+use crate::WalkFilter;
+
+/// Hashes everything under `dir`, skipping whatever the default [`WalkFilter`] would.
+pub fn walk(dir: &Path) -> driver_util::Result<Hash> {
+    walk_with_ignores(dir, &WalkFilter::default())
+}
+
+/// Hashes everything under `dir` that isn't left out by `filter`. Symlinks are followed, so
+/// they hash exactly the same as whatever they point to.
+pub fn walk_with_ignores(dir: &Path, filter: &WalkFilter) -> driver_util::Result<Hash> {
+    walk_with_options(
+        dir,
+        &WalkOptions {
+            filter: filter.clone(),
+            follow_symlinks: true,
+        },
+    )
+}
+
+/// How [`walk_with_options`] treats what it finds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    pub filter: WalkFilter,
+    /// If set, symlinks are hashed as whatever they point to, & symlinks back up the tree are an
+    /// error. Otherwise, they're hashed as the path they point to, which is never read, so the
+    /// hash can't depend on anything outside `dir`.
+    pub follow_symlinks: bool,
+}
+
+/// Hashes everything under `dir`, as configured by `options`.
+pub fn walk_with_options(dir: &Path, options: &WalkOptions) -> driver_util::Result<Hash> {
+    walk_with_digest::<sha2::Sha256>(dir, options)
+}
+
+/// Same as [`walk_with_options`], but hashing with `D` instead of SHA-256.
+pub fn walk_with_digest<D: Digest>(
+    dir: &Path,
+    options: &WalkOptions,
+) -> driver_util::Result<Output<D>>
+where
+    Output<D>: Send,
+{
+    let (root, files) = tree(dir, options)?;
+    // Reading files is most of the work, & each one can be hashed on its own, so do that all at
+    // once up front. Folding everything together still happens in order, so the result is the
+    // same as if we'd read each file as we got to it.
+    let files = hash_files::<D>(files)?;
+    root.hash::<D>(options.follow_symlinks, &files)
+}
+
+/// Everything under `dir`, along with which of those things are files.
+fn tree(dir: &Path, options: &WalkOptions) -> driver_util::Result<(Node, Vec<PathBuf>)> {
+    // Let the walker do all the ignore matching for the whole tree at once (so that globs stay
+    // relative to `dir`), then rebuild the tree structure from the paths it gives back. The walker
+    // also errors on symlink cycles for us.
+    let walk = options
+        .filter
+        .walk_builder(dir, dir)?
+        .follow_links(options.follow_symlinks)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    let mut root = Node {
+        children: Some(BTreeMap::new()),
+        path: dir.to_path_buf(),
+    };
+    let mut files = Vec::new();
+    for entry in walk {
+        let entry = entry?;
+        let Ok(relative) = entry.path().strip_prefix(dir) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        root.insert(relative, entry.path());
+        if let Kind::File = Kind::of(entry.path(), options.follow_symlinks)? {
+            files.push(entry.path().to_path_buf());
+        }
+    }
+    Ok((root, files))
+}
+
+/// Reads & hashes every one of `paths`, spread out over as many threads as we have cores.
+fn hash_files<D: Digest>(paths: Vec<PathBuf>) -> driver_util::Result<HashMap<PathBuf, Output<D>>>
+where
+    Output<D>: Send,
+{
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(paths.len());
+    let next = AtomicUsize::new(0);
+    let hashed: Vec<_> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut hashed = Vec::new();
+                    while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let hash = std::fs::read(path).map(D::digest);
+                        hashed.push((path.clone(), hash));
+                    }
+                    hashed
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("hashing thread panicked"))
+            .collect()
+    });
+    hashed
+        .into_iter()
+        .map(|(path, hash)| -> driver_util::Result<_> { Ok((path, hash?)) })
+        .collect()
+}
+
+enum Kind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl Kind {
+    fn of(path: &Path, follow_symlinks: bool) -> driver_util::Result<Self> {
+        let metadata = if follow_symlinks {
+            std::fs::metadata(path)?
+        } else {
+            std::fs::symlink_metadata(path)?
+        };
+        Ok(if metadata.is_symlink() {
+            Self::Symlink
+        } else if metadata.is_dir() {
+            Self::Directory
+        } else {
+            Self::File
+        })
+    }
+
+    fn tag(&self) -> &'static [u8] {
+        match self {
+            Self::File => b"f ",
+            Self::Directory => b"d ",
+            Self::Symlink => b"l ",
+        }
+    }
+}
+
+/// A directory, as far as the walk could see it.
+#[derive(Default)]
+struct Node {
+    /// `None` for files, `Some` for directories.
+    children: Option<BTreeMap<OsString, Node>>,
+    path: PathBuf,
+}
+
+impl Node {
+    fn insert(&mut self, relative: &Path, full: &Path) {
+        let mut node = self;
+        for component in relative.iter() {
+            node = node
+                .children
+                .get_or_insert_default()
+                .entry(component.to_os_string())
+                .or_default();
+        }
+        node.path = full.to_path_buf();
+    }
+
+    /// Files already hashed can be passed in as `files`; anything else is read as we go.
+    fn hash<D: Digest>(
+        &self,
+        follow_symlinks: bool,
+        files: &HashMap<PathBuf, Output<D>>,
+    ) -> driver_util::Result<Output<D>> {
+        let mut hasher = D::new();
+        match Kind::of(&self.path, follow_symlinks)? {
+            Kind::File => {
+                if let Some(hash) = files.get(&self.path) {
+                    return Ok(hash.clone());
+                }
+                hasher.update(std::fs::read(&self.path)?);
+                return Ok(hasher.finalize());
+            }
+            Kind::Symlink => {
+                let target = std::fs::read_link(&self.path)?;
+                hasher.update(target.as_os_str().as_encoded_bytes());
+                return Ok(hasher.finalize());
+            }
+            Kind::Directory => {}
+        }
+        for (name, child) in self.children.iter().flatten() {
+            hasher.update(Kind::of(&child.path, follow_symlinks)?.tag());
+            hasher.update(name.as_encoded_bytes());
+            hasher.update(b"\0");
+            hasher.update(child.hash::<D>(follow_symlinks, files)?);
+        }
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sensitive_to_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/c.txt"), "c").unwrap();
+        std::fs::write(root.join("d.txt"), "d").unwrap();
+
+        let original = walk(root).unwrap();
+        assert_eq!(walk(root).unwrap(), original);
+
+        std::fs::write(root.join("a/b/c.txt"), "changed").unwrap();
+        let edited = walk(root).unwrap();
+        assert_ne!(edited, original);
+
+        std::fs::rename(root.join("a/b/c.txt"), root.join("a/b/e.txt")).unwrap();
+        assert_ne!(walk(root).unwrap(), edited);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_only_followed_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("outside.txt");
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(&outside, "outside").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link.txt")).unwrap();
+
+        let follow = WalkOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let followed = walk_with_options(&root, &follow).unwrap();
+        let not_followed = walk_with_options(&root, &WalkOptions::default()).unwrap();
+        assert_eq!(followed, walk(&root).unwrap());
+
+        std::fs::write(&outside, "changed").unwrap();
+        assert_ne!(walk_with_options(&root, &follow).unwrap(), followed);
+        assert_eq!(
+            walk_with_options(&root, &WalkOptions::default()).unwrap(),
+            not_followed
+        );
+
+        // A link back up the tree only loops if it's followed
+        std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+        assert!(walk_with_options(&root, &follow).is_err());
+        assert!(walk_with_options(&root, &WalkOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for d in 0..20 {
+            let sub = root.join(format!("dir{d}/nested"));
+            std::fs::create_dir_all(&sub).unwrap();
+            for f in 0..20 {
+                std::fs::write(sub.join(format!("{f}.txt")), format!("{d} {f}")).unwrap();
+            }
+        }
+
+        let options = WalkOptions::default();
+        let (node, files) = tree(root, &options).unwrap();
+        assert_eq!(files.len(), 400);
+        let sequential = node.hash::<sha2::Sha256>(false, &HashMap::new()).unwrap();
+        assert_eq!(walk_with_options(root, &options).unwrap(), sequential);
+    }
+
+    /// What hashing a directory with just `a/b.txt` (containing `b`) should give, built up by hand.
+    fn framed<D: Digest>() -> Output<D> {
+        let file = D::digest("b");
+        let mut a = D::new();
+        a.update(b"f b.txt\0");
+        a.update(file);
+        let mut expected = D::new();
+        expected.update(b"d a\0");
+        expected.update(a.finalize());
+        expected.finalize()
+    }
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn other_digests() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(root.join("a/b.txt"), "b").unwrap();
+
+        let options = WalkOptions::default();
+        let sha256 = walk_with_digest::<sha2::Sha256>(root, &options).unwrap();
+        let blake3 = walk_with_digest::<blake3::Hasher>(root, &options).unwrap();
+        assert_eq!(sha256, walk_with_options(root, &options).unwrap());
+
+        // Same framing, just a different primitive
+        assert_eq!(sha256, framed::<sha2::Sha256>());
+        assert_eq!(blake3, framed::<blake3::Hasher>());
+
+        // And pinned, so that neither can change without anyone noticing
+        assert_eq!(
+            hex(&sha256),
+            "96f7591788f4785a3cb9ace5c0607b4f717bbc77a3f6b2a6b9ae04d4cec2eda3"
+        );
+        assert_eq!(
+            hex(&blake3),
+            "fb1fa700f848f8d258749ccfc797cc85ec9fba88471ae412e4c6f477fa843ae7"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use driver_engine::Options;
+use driver_util::Hash;
+
+use crate::{WalkFilter, dirhash};
+
+driver_engine::key!(
+    #[input=|_| true]
+    struct HashDirectory {
+        pub path: PathBuf,
+        pub filter: WalkFilter,
+    }
+);
+driver_engine::no_blobs!(HashDirectory);
+
+impl HashDirectory {
+    /// Hashes `path` using whatever filter is configured in the options.
+    pub fn new(path: PathBuf, options: &Options) -> Self {
+        Self {
+            path,
+            filter: WalkFilter::from_options(options),
+        }
+    }
+}
+
+driver_engine::producer!(HashDirectory(self, ctx) -> driver_util::Result<Hash> {
+    crate::check_project_root(ctx.options(), &self.path)?;
+    dirhash::walk_with_ignores(&self.path, &self.filter)
+});
+
+impl std::fmt::Display for HashDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hash_directory(\"{}\"", self.path.display())?;
+        if self.filter != WalkFilter::default() {
+            write!(
+                f,
+                ", gitignore={}, ignore={:?}",
+                self.filter.respect_gitignore, self.filter.ignore
+            )?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::{Context, query};
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    driver_engine::query!(Key { HashDirectory } with Output);
+
+    #[test]
+    fn changes_with_any_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir_all(site.join("posts")).unwrap();
+        std::fs::write(site.join("index.md"), "hi").unwrap();
+        std::fs::write(site.join("posts/first.md"), "first").unwrap();
+
+        let hash_of = |site: &PathBuf| {
+            // A fresh root is a fresh revision, so the input gets re-checked
+            let options = Options::with_base_dir(&dir.path().join(".driver"));
+            let key = HashDirectory::new(site.clone(), &options);
+            let ctx = Context::<Key>::create_root(options, None);
+            let hash = block_on(query(&ctx, key)).unwrap();
+            ctx.destroy_root().unwrap();
+            hash
+        };
+
+        let original = hash_of(&site);
+        assert_eq!(hash_of(&site), original);
+
+        std::fs::write(site.join("posts/first.md"), "edited").unwrap();
+        let edited = hash_of(&site);
+        assert_ne!(edited, original);
+
+        std::fs::write(site.join("posts/second.md"), "second").unwrap();
+        assert_ne!(hash_of(&site), edited);
+    }
+}
@@ -1,4 +1,28 @@
+pub mod dirhash;
+mod hash_directory;
 mod list_directory;
 mod read_file;
+mod walk_directory;
+pub use hash_directory::HashDirectory;
 pub use list_directory::ListDirectory;
+pub use list_directory::WalkFilter;
 pub use read_file::ReadFile;
+pub use walk_directory::WalkDirectory;
+
+/// Errors if `path` is outside [`driver_engine::Options::project_root`].
+fn check_project_root(
+    options: &driver_engine::Options,
+    path: &std::path::Path,
+) -> driver_util::Result<()> {
+    let Some(root) = &options.project_root else {
+        return Ok(());
+    };
+    if !driver_util::is_within(root, path)? {
+        return Err(driver_util::Error::new(&format!(
+            "{} is outside the project root {}",
+            path.display(),
+            root.display()
+        )));
+    }
+    Ok(())
+}
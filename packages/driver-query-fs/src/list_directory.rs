@@ -1,32 +1,214 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use driver_engine::Options;
+
+/// Which entries a directory listing should leave out. Lives in the key (rather than being read off
+/// the options at produce-time) so that changing it invalidates the listings that used it.
+#[derive(
+    Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize,
+)]
+pub struct WalkFilter {
+    /// Whether .gitignore (and friends) should be respected.
+    pub respect_gitignore: bool,
+    /// Extra globs to leave out, relative to the directory being walked (see
+    /// [`WalkFilter::walk_builder`]).
+    pub ignore: Vec<String>,
+}
+
+impl Default for WalkFilter {
+    fn default() -> Self {
+        Self {
+            respect_gitignore: true,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+impl WalkFilter {
+    pub fn from_options(options: &Options) -> Self {
+        Self {
+            respect_gitignore: options.respect_gitignore,
+            ignore: options.ignore_globs.clone(),
+        }
+    }
+
+    /// Builds a walker over `path` that applies this filter, with the ignore globs relative to
+    /// `root`. That's `path` itself, unless `path` is just one part of a bigger walk starting at
+    /// `root`, so that `posts/drafts` means the same thing no matter which directory is listed.
+    pub fn walk_builder(
+        &self,
+        path: &Path,
+        root: &Path,
+    ) -> driver_util::Result<ignore::WalkBuilder> {
+        let mut walk = ignore::WalkBuilder::new(path);
+        walk.git_ignore(self.respect_gitignore)
+            .git_global(self.respect_gitignore)
+            .git_exclude(self.respect_gitignore);
+        if !self.ignore.is_empty() {
+            let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+            for glob in self.ignore.iter() {
+                // A leading ! means "ignore" for overrides, which is backwards from gitignore
+                overrides.add(&format!("!{glob}"))?;
+            }
+            walk.overrides(overrides.build()?);
+        }
+        Ok(walk)
+    }
+}
 
 driver_engine::key!(
     #[input=|_| true]
-    struct ListDirectory(pub PathBuf);
+    struct ListDirectory {
+        pub path: PathBuf,
+        pub filter: WalkFilter,
+        /// Where the walk this listing is part of started, if not at `path`. The ignore globs are
+        /// relative to this.
+        pub walk_root: Option<PathBuf>,
+    }
 );
 driver_engine::no_blobs!(ListDirectory);
 
-driver_engine::producer!(ListDirectory(self, ctx) -> driver_util::Result<Vec<PathBuf>> {
-    // TODO: make this async? Unclear if worth it, investigate later
-    let walk = ignore::WalkBuilder::new(&self.0)
-        .max_depth(Some(1))
-        .sort_by_file_name(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-        .build();
-    let entries = walk
-        .into_iter()
-        .map(|e| e.map(|entry| entry.into_path()))
-        .filter(|e| match e {
+impl ListDirectory {
+    /// Lists `path` using whatever filter is configured in the options.
+    pub fn new(path: PathBuf, options: &Options) -> Self {
+        Self {
+            path,
+            filter: WalkFilter::from_options(options),
+            walk_root: None,
+        }
+    }
+
+    /// Lists `path` as one part of a walk that started at `root`, using `filter`.
+    pub fn in_walk(path: PathBuf, root: &Path, filter: WalkFilter) -> Self {
+        Self {
+            walk_root: (path != root).then(|| root.to_path_buf()),
+            path,
+            filter,
+        }
+    }
+}
+
+impl ListDirectory {
+    /// Lists `path` lazily, in the same order as the producer, without going through the cache.
+    /// For handing entries of huge directories out one at a time; whatever calls this should still
+    /// `query` the key itself afterwards, so that the directory is tracked as a dependency.
+    pub fn entries(
+        &self,
+        options: &Options,
+    ) -> driver_util::Result<impl Iterator<Item = driver_util::Result<PathBuf>> + use<>> {
+        crate::check_project_root(options, &self.path)?;
+        let walk = self
+            .filter
+            .walk_builder(&self.path, self.walk_root.as_ref().unwrap_or(&self.path))?
+            .max_depth(Some(1))
+            // Plain byte order of the names (UTF-8 for any valid name, on every platform), so
+            // listings & anything hashed from them come out the same everywhere. Capitals sort
+            // before lowercase, & anything non-ASCII sorts after both.
+            .sort_by_file_name(|a, b| a.as_encoded_bytes().cmp(b.as_encoded_bytes()))
+            .build();
+        let path = self.path.clone();
+        Ok(walk.filter_map(move |e| match e {
             // Pass thru all errors
-            Err(_) => true,
+            Err(e) => Some(Err(e.into())),
             // Exclude the target directory from the returned list
-            Ok(entry) => entry != &self.0,
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(entries)
+            Ok(entry) if entry.path() == path => None,
+            Ok(entry) => Some(Ok(entry.into_path())),
+        }))
+    }
+}
+
+driver_engine::producer!(ListDirectory(self, ctx) -> driver_util::Result<Vec<PathBuf>> {
+    // TODO: make this async? Unclear if worth it, investigate later
+    self.entries(ctx.options())?.collect()
 });
 
 impl std::fmt::Display for ListDirectory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "list_directory(\"{}\")", self.0.display())
+        write!(f, "list_directory(\"{}\"", self.path.display())?;
+        if self.filter != WalkFilter::default() {
+            write!(
+                f,
+                ", gitignore={}, ignore={:?}",
+                self.filter.respect_gitignore, self.filter.ignore
+            )?;
+        }
+        if let Some(root) = &self.walk_root {
+            write!(f, ", walk_root=\"{}\"", root.display())?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::{Context, query};
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    driver_engine::query!(Key { ListDirectory } with Output);
+
+    #[test]
+    fn sorts_by_bytes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let listed = dir.join("listed");
+        std::fs::create_dir_all(&listed).unwrap();
+        for name in ["é", "b", "ä", "B", "a"] {
+            std::fs::write(listed.join(name), b"").unwrap();
+        }
+
+        let options = Options::with_base_dir(&dir.join(".driver"));
+        let key = ListDirectory::new(listed.clone(), &options);
+        let ctx = Context::<Key>::create_root(options, None);
+        let entries = block_on(query(&ctx, key)).unwrap();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.strip_prefix(&listed).unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["B", "a", "b", "ä", "é"]);
+
+        ctx.destroy_root().unwrap();
+    }
+
+    /// Lists a directory with `a.md`, `b.draft`, and a gitignored `c.md`, returning the names that
+    /// made it through.
+    fn list_filtered(configure: impl FnOnce(&mut Options)) -> Vec<String> {
+        let tmp = tempfile::tempdir().unwrap();
+        let listed = tmp.path().join("listed");
+        // Gitignores are only respected inside a repository
+        std::fs::create_dir_all(listed.join(".git")).unwrap();
+        std::fs::write(listed.join(".gitignore"), "c.md\n").unwrap();
+        for name in ["a.md", "b.draft", "c.md"] {
+            std::fs::write(listed.join(name), b"").unwrap();
+        }
+
+        let mut options = Options::with_base_dir(&tmp.path().join(".driver"));
+        configure(&mut options);
+        let key = ListDirectory::new(listed.clone(), &options);
+        let ctx = Context::<Key>::create_root(options, None);
+        let entries = block_on(query(&ctx, key)).unwrap();
+        ctx.destroy_root().unwrap();
+        entries
+            .iter()
+            .map(|entry| entry.strip_prefix(&listed).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn respects_gitignore_by_default() {
+        assert_eq!(list_filtered(|_| {}), ["a.md", "b.draft"]);
+    }
+
+    #[test]
+    fn skips_extra_ignore_globs() {
+        let names = list_filtered(|options| options.ignore_globs = vec!["*.draft".to_string()]);
+        assert_eq!(names, ["a.md"]);
+    }
+
+    #[test]
+    fn can_include_gitignored_files() {
+        let names = list_filtered(|options| options.respect_gitignore = false);
+        assert_eq!(names, ["a.md", "b.draft", "c.md"]);
     }
 }
@@ -9,7 +9,19 @@ driver_engine::key!(
 driver_engine::no_blobs!(ReadFile);
 
 driver_engine::producer!(ReadFile(self, ctx) -> driver_util::Result<Blob> {
-    let content = async_fs::read(&self.0).await?;
+    crate::check_project_root(ctx.options(), &self.0)?;
+    // An empty file is a perfectly fine (empty) blob; a missing one gets its own error kind so
+    // callers can tell the two apart.
+    let content = match async_fs::read(&self.0).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(driver_util::Error::not_found(&format!(
+                "{} not found",
+                self.0.display()
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
     let blob = ctx.store(content)?;
     Ok(blob)
 });
@@ -19,3 +31,53 @@ impl std::fmt::Display for ReadFile {
         write!(f, "read_file(\"{}\")", self.0.display())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use driver_engine::{Context, Options, query};
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    driver_engine::query!(Key { ReadFile } with Output);
+
+    #[test]
+    fn empty_vs_missing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let empty = dir.join("empty.txt");
+        std::fs::write(&empty, b"").unwrap();
+
+        let ctx = Context::<Key>::create_root(Options::with_base_dir(&dir.join(".driver")), None);
+
+        let blob = block_on(query(&ctx, ReadFile(empty))).unwrap();
+        assert_eq!(ctx.load_bytes(&blob).unwrap(), b"");
+
+        let err = block_on(query(&ctx, ReadFile(dir.join("missing.txt")))).unwrap_err();
+        assert!(err.is_not_found());
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn stays_in_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        std::fs::create_dir_all(dir.join("site")).unwrap();
+        std::fs::write(dir.join("site/index.md"), b"hi").unwrap();
+        std::fs::write(dir.join("secret.txt"), b"shh").unwrap();
+
+        let mut options = Options::with_base_dir(&dir.join(".driver"));
+        options.project_root = Some(dir.join("site"));
+        let ctx = Context::<Key>::create_root(options, None);
+
+        assert!(block_on(query(&ctx, ReadFile(dir.join("site/index.md")))).is_ok());
+        let err = block_on(query(&ctx, ReadFile(dir.join("site/../secret.txt")))).unwrap_err();
+        assert!(
+            err.to_string().contains("outside the project root"),
+            "{err}"
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use driver_engine::{Options, query};
+
+use crate::{ListDirectory, WalkFilter};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct WalkDirectory {
+        pub path: PathBuf,
+        pub filter: WalkFilter,
+    }
+);
+driver_engine::no_blobs!(WalkDirectory);
+
+impl WalkDirectory {
+    /// Walks `path` using whatever filter is configured in the options.
+    pub fn new(path: PathBuf, options: &Options) -> Self {
+        Self {
+            path,
+            filter: WalkFilter::from_options(options),
+        }
+    }
+}
+
+// Goes through `ListDirectory` one directory at a time (instead of walking everything in one go)
+// so that a change anywhere in the tree invalidates this.
+driver_engine::producer!(WalkDirectory(self, ctx) where [ListDirectory] -> driver_util::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![self.path.clone()];
+    // Listings include symlinks, which can point back up the tree, so only walk each actual
+    // directory once
+    let mut seen = HashSet::new();
+    while let Some(dir) = dirs.pop() {
+        if !seen.insert(std::fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        let list_directory = ListDirectory::in_walk(dir, &self.path, self.filter.clone());
+        for entry in query(ctx, list_directory).await? {
+            if entry.is_dir() {
+                dirs.push(entry);
+            } else {
+                files.push(entry);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+});
+
+impl std::fmt::Display for WalkDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "walk_directory(\"{}\"", self.path.display())?;
+        if self.filter != WalkFilter::default() {
+            write!(
+                f,
+                ", gitignore={}, ignore={:?}",
+                self.filter.respect_gitignore, self.filter.ignore
+            )?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::Context;
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    driver_engine::query!(Key { ListDirectory, WalkDirectory } with Output);
+
+    #[test]
+    fn sees_nested_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir_all(site.join("posts/2026")).unwrap();
+        std::fs::write(site.join("index.md"), "").unwrap();
+        std::fs::write(site.join("posts/2026/first.md"), "").unwrap();
+
+        let walk = || {
+            // A fresh root is a fresh revision, so the listings get re-checked
+            let options = Options::with_base_dir(&dir.path().join(".driver"));
+            let key = WalkDirectory::new(site.clone(), &options);
+            let ctx = Context::<Key>::create_root(options, None);
+            let files = block_on(query(&ctx, key)).unwrap();
+            ctx.destroy_root().unwrap();
+            files
+        };
+
+        assert_eq!(
+            walk(),
+            [site.join("index.md"), site.join("posts/2026/first.md")]
+        );
+
+        std::fs::write(site.join("posts/2026/second.md"), "").unwrap();
+        assert_eq!(
+            walk(),
+            [
+                site.join("index.md"),
+                site.join("posts/2026/first.md"),
+                site.join("posts/2026/second.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_relative_to_the_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir_all(site.join("posts/drafts")).unwrap();
+        std::fs::write(site.join("posts/first.md"), "").unwrap();
+        std::fs::write(site.join("posts/drafts/second.md"), "").unwrap();
+
+        let mut options = Options::with_base_dir(&dir.path().join(".driver"));
+        options.ignore_globs = vec!["posts/drafts".to_string()];
+        let key = WalkDirectory::new(site.clone(), &options);
+        let ctx = Context::<Key>::create_root(options, None);
+        assert_eq!(
+            block_on(query(&ctx, key)).unwrap(),
+            [site.join("posts/first.md")]
+        );
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn stops_at_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir_all(site.join("posts")).unwrap();
+        std::fs::write(site.join("posts/first.md"), "").unwrap();
+        std::os::unix::fs::symlink(&site, site.join("posts/up")).unwrap();
+
+        let options = Options::with_base_dir(&dir.path().join(".driver"));
+        let key = WalkDirectory::new(site.clone(), &options);
+        let ctx = Context::<Key>::create_root(options, None);
+        assert_eq!(
+            block_on(query(&ctx, key)).unwrap(),
+            [site.join("posts/first.md")]
+        );
+        ctx.destroy_root().unwrap();
+    }
+}
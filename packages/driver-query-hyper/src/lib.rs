@@ -1,4 +1,4 @@
-use driver_engine::{Blob, Uri};
+use driver_engine::{Blob, RetryPolicy, Uri, retry};
 
 driver_engine::key!(
     #[input=|_| true]
@@ -7,7 +7,7 @@ driver_engine::key!(
 driver_engine::no_blobs!(GetUrl);
 
 driver_engine::producer!(GetUrl(self, ctx) -> driver_util::Result<Blob> {
-    ctx.fetch(self.0.clone()).await
+    retry(&RetryPolicy::default(), || ctx.fetch(self.0.clone())).await
 });
 
 impl std::fmt::Display for GetUrl {
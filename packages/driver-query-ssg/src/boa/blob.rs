@@ -29,9 +29,8 @@ pub struct JsBlob {
 driver_engine::blob_trace!(JsBlob => { blob });
 
 impl JsBlob {
-    /// # Safety
-    /// Only safe to call when in a javascript context
-    pub unsafe fn contents_as_bytes(self) -> JsResult<Vec<u8>> {
+    /// Errors if not called from inside a javascript context.
+    pub fn contents_as_bytes(self) -> JsResult<Vec<u8>> {
         let ctx = &get_context()?;
         ctx.load_bytes(&self.blob).map_err(|e| {
             JsNativeError::eval()
@@ -40,9 +39,9 @@ impl JsBlob {
         })
     }
 
-    /// # Safety
-    /// Only safe to call when in a javascript context
-    pub unsafe fn contents_as_string(self) -> JsResult<String> {
+    /// Errors if not called from inside a javascript context, or if the contents aren't UTF-8.
+    /// Nothing else about a blob cares what's in it, so this is the only place that gets checked.
+    pub fn contents_as_string(self) -> JsResult<String> {
         let ctx = &get_context()?;
         ctx.load_string(&self.blob).map_err(|e| {
             JsNativeError::eval()
@@ -56,16 +55,15 @@ class_wrap!(class JsBlob {
     length 0,
     methods {
         data: (0) |this: GcRef<'_, JsBlob>, _args, js_ctx| {
-            // SAFETY: we are in a javascript context
-            let src = unsafe { this.clone().contents_as_bytes()? };
+            let src = this.clone().contents_as_bytes()?;
             JsUint8Array::from_iter(src, js_ctx)
         },
+        // Just the hex, since the point is to put it in things like filenames
         hash: (0) |this: GcRef<'_, JsBlob>, _args, _js_ctx| {
-            JsResult::Ok(this.blob.to_string())
+            JsResult::Ok(this.blob.to_hex())
         },
         toString: (0) |this: GcRef<'_, JsBlob>, _args, _js_ctx| {
-            // SAFETY: we are in a javascript context
-            unsafe { this.clone().contents_as_string() }
+            this.clone().contents_as_string()
         },
     },
 });
@@ -0,0 +1,163 @@
+//! Turns the "at line N, col M" that boa tacks onto its errors into an actual snippet of the
+//! offending source, so you don't have to go counting lines yourself. Also makes sure errors
+//! thrown at runtime say where they came from.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use boa_engine::{Context, JsError, js_str};
+
+/// Finds the last "line N, col M" in an error message. Both are 1-indexed.
+fn find_location(message: &str) -> Option<(usize, usize)> {
+    let start = message.rfind("line ")?;
+    let rest = &message[start + "line ".len()..];
+    let (line, rest) = rest.split_once(", col ")?;
+    let col_len = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let line = line.parse().ok()?;
+    let col = rest[..col_len].parse().ok()?;
+    Some((line, col))
+}
+
+/// Renders a rustc-style snippet pointing at `line`/`col` in `source`. Returns `None` if the
+/// location doesn't exist in the source.
+fn render(source: &str, path: &Path, line: usize, col: usize, message: &str) -> Option<String> {
+    let text = source.lines().nth(line.checked_sub(1)?)?;
+    let gutter = " ".repeat(line.to_string().len());
+
+    // Keep tabs as tabs so the caret still lines up in the terminal
+    let padding: String = text
+        .chars()
+        .take(col.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "error: {message}");
+    let _ = writeln!(out, "{gutter}--> {}:{line}:{col}", path.display());
+    let _ = writeln!(out, "{gutter} |");
+    let _ = writeln!(out, "{line} | {text}");
+    let _ = write!(out, "{gutter} | {padding}^");
+    Some(out)
+}
+
+/// If `err` points somewhere in `source`, replaces its message with a rendered snippet. Otherwise
+/// hands it back unchanged.
+pub(crate) fn annotate(source: &[u8], path: &Path, err: driver_util::Error) -> driver_util::Error {
+    let message = err.to_string();
+    let Some((line, col)) = find_location(&message) else {
+        return err;
+    };
+    let source = String::from_utf8_lossy(source);
+    match render(&source, path, line, col, &message) {
+        Some(rendered) => driver_util::Error::with_kind(err.kind(), &rendered),
+        None => err,
+    }
+}
+
+/// The frames of a JS stack trace, innermost first, without the message some engines put on top.
+fn frames(stack: &str) -> Vec<&str> {
+    stack
+        .lines()
+        .map(str::trim)
+        .filter_map(|line| line.strip_prefix("at "))
+        .collect()
+}
+
+/// Where a frame is in the source, like `lib.js:2:9`.
+fn frame_location(frame: &str) -> &str {
+    match frame.rsplit_once('(') {
+        Some((_, location)) => location.trim_end_matches(')'),
+        None => frame,
+    }
+}
+
+/// Turns a value a script threw into an error that says where it was thrown from. Errors get
+/// their innermost frame up front & the rest of their stack trace after; anything else that got
+/// thrown only knows `module`, the script that was being run.
+pub(crate) fn uncaught(
+    value: boa_engine::JsValue,
+    module: &Path,
+    js_ctx: &mut Context,
+) -> driver_util::Error {
+    let stack = value
+        .as_object()
+        .and_then(|object| object.get(js_str!("stack"), js_ctx).ok())
+        .and_then(|stack| stack.as_string().map(|s| s.to_std_string_escaped()))
+        .unwrap_or_default();
+
+    let native = match JsError::from_opaque(value.clone()).try_native(js_ctx) {
+        Ok(native) => native,
+        Err(_) => {
+            return driver_util::Error::new(&format!(
+                "{}: uncaught {}",
+                module.display(),
+                value.display()
+            ));
+        }
+    };
+    let frames = frames(&stack);
+    let Some(top) = frames.first() else {
+        return native.into();
+    };
+
+    let mut out = format!("{}: {native}", frame_location(top));
+    for frame in frames {
+        let _ = write!(out, "\n    at {frame}");
+    }
+    driver_util::Error::new(&out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_location() {
+        assert_eq!(
+            find_location("SyntaxError: unexpected token ';' at line 12, col 3"),
+            Some((12, 3))
+        );
+        assert_eq!(find_location("TypeError: not a function"), None);
+    }
+
+    #[test]
+    fn finds_top_frame() {
+        let stack = "Error: boom\n    at boom (lib.js:2:9)\n    at main.js:3:16\n";
+        let frames = frames(stack);
+        assert_eq!(frames, ["boom (lib.js:2:9)", "main.js:3:16"]);
+        assert_eq!(frame_location(frames[0]), "lib.js:2:9");
+        assert_eq!(frame_location(frames[1]), "main.js:3:16");
+    }
+
+    #[test]
+    fn renders_caret_under_column() {
+        let source = "const a = 1;\nconst b = ;\nconst c = 3;\n";
+        let err = driver_util::Error::new("SyntaxError: unexpected token ';' at line 2, col 11");
+        let rendered = annotate(source.as_bytes(), Path::new("index.js"), err).to_string();
+
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[1], " --> index.js:2:11");
+        assert_eq!(lines[3], "2 | const b = ;");
+        assert_eq!(lines[4], "  |           ^");
+        // The caret is directly below the offending `;`
+        assert_eq!(lines[4].find('^'), lines[3].find(';'));
+    }
+
+    #[test]
+    fn syntax_error_from_boa() {
+        let source = "let x = 1;\nlet y = );\n";
+        let js_ctx = &mut boa_engine::Context::default();
+        let err = boa_engine::Module::parse(
+            boa_engine::Source::from_bytes(source.as_bytes()),
+            None,
+            js_ctx,
+        )
+        .expect_err("source is invalid");
+
+        let rendered = annotate(source.as_bytes(), Path::new("bad.js"), err.into()).to_string();
+        assert!(rendered.contains("2 | let y = );"), "{rendered}");
+        assert!(rendered.contains('^'), "{rendered}");
+    }
+}
@@ -7,7 +7,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::boa::JsBlob;
 use crate::boa::macros::class_wrap;
-use crate::zune::{EncoderOptions, ImageFit, ImageFormat, ImageObject, ImageSize, ResizeMethod};
+use crate::zune::{
+    EncoderOptions, ImageFit, ImageFormat, ImageObject, ImageSize, ResizeMethod, TargetSize,
+};
 
 impl TryIntoJs for ImageFormat {
     fn try_into_js(&self, context: &mut Context) -> JsResult<JsValue> {
@@ -60,6 +62,24 @@ impl TryFromJs for ImageSize {
     }
 }
 
+impl TryFromJs for TargetSize {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
+        let obj = value
+            .as_object()
+            .ok_or_else(|| JsNativeError::typ().with_message("ImageSize must be object"))?;
+
+        let width = Option::<usize>::try_from_js(&obj.get(js_str!("width"), context)?, context)?;
+        let height = Option::<usize>::try_from_js(&obj.get(js_str!("height"), context)?, context)?;
+        if width.is_none() && height.is_none() {
+            return Err(JsNativeError::typ()
+                .with_message("ImageSize must have a width or a height")
+                .into());
+        }
+
+        Ok(TargetSize { width, height })
+    }
+}
+
 impl TryFromJs for ImageFit {
     fn try_from_js(value: &JsValue, _js_ctx: &mut Context) -> JsResult<Self> {
         match value
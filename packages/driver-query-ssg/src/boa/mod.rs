@@ -4,15 +4,17 @@ use std::{
     ops::DerefMut,
     path::PathBuf,
     rc::Rc,
+    sync::RwLock,
 };
 
 use boa_engine::{
-    Context, JsError, JsNativeError, JsResult, JsString, Module, NativeFunction,
+    Context, JsNativeError, JsResult, JsString, Module, NativeFunction,
     builtins::promise::PromiseState,
     context::{ContextBuilder, time::JsInstant},
     job::{GenericJob, Job, JobExecutor, NativeAsyncJob, PromiseJob, TimeoutJob},
     js_str,
     module::{ModuleLoader, resolve_module_specifier},
+    object::builtins::JsPromise,
     property::Attribute,
     value::{TryFromJs, TryIntoJs},
 };
@@ -28,12 +30,42 @@ use driver_query_fs::ReadFile;
 use crate::{QueryContext, WriteOutput, WriteOutputBuilder};
 
 mod blob;
+mod diagnostic;
 mod image;
 mod macros;
 mod path;
 mod value;
 
-pub use self::{blob::JsBlob, image::JsImage, path::JsPath, value::JsValue};
+pub use self::{blob::JsBlob, image::JsImage, path::JsPath, value::Float, value::JsValue};
+
+/// Builds a native module for a given JS context. Needs to be a plain function (rather than an
+/// already-built [`Module`]) because we make a fresh JS context for every file we run.
+pub type NativeModuleBuilder = fn(&mut Context) -> JsResult<Module>;
+
+/// Every module registered with [`register_native_module`] for a build.
+#[derive(Default)]
+struct NativeModules(RwLock<Vec<(String, NativeModuleBuilder)>>);
+
+/// Registers an extra native module that every script run by `ctx`'s build can `import` by `name`,
+/// right alongside the built-in `driver` one. SHOULD be called before running any scripts.
+///
+/// NOTE: the module's code isn't part of any cache key, so changing what a registered module does
+/// won't invalidate scripts that already ran with it. Clear the cache if you do that.
+pub fn register_native_module(
+    ctx: &QueryContext,
+    name: &str,
+    builder: NativeModuleBuilder,
+) -> driver_util::Result<()> {
+    let registry = ctx.extension::<NativeModules>();
+    let mut modules = registry.0.write().expect("native module registry poisoned");
+    if name == "driver" || modules.iter().any(|(existing, _)| existing == name) {
+        return Err(driver_util::Error::new(&format!(
+            "native module {name:?} already registered"
+        )));
+    }
+    modules.push((name.to_string(), builder));
+    Ok(())
+}
 
 /// Turns command-line arguments into a javascript-compatible list.
 /// TODO: better types than `&str`.
@@ -114,7 +146,8 @@ impl Executor {
         }
     }
 
-    fn drain_timeout_jobs(&self, js_ctx: &mut Context) {
+    /// Errors are what the script left uncaught, so they end the run with that as the error.
+    fn drain_timeout_jobs(&self, js_ctx: &mut Context) -> JsResult<()> {
         let now = js_ctx.clock().now();
 
         let timed_out_jobs = {
@@ -125,30 +158,28 @@ impl Executor {
         };
 
         for timeout_job in timed_out_jobs.into_values() {
-            if let Err(e) = timeout_job.call(js_ctx) {
-                eprintln!("Uncaught {e}");
-            }
+            timeout_job.call(js_ctx)?;
         }
+        Ok(())
     }
 
-    fn drain_jobs(&self, js_ctx: &mut Context) {
+    /// Like [`Self::drain_timeout_jobs`], stops at the first uncaught error.
+    fn drain_jobs(&self, js_ctx: &mut Context) -> JsResult<()> {
         // Run the timeout jobs first.
-        self.drain_timeout_jobs(js_ctx);
+        self.drain_timeout_jobs(js_ctx)?;
 
-        if let Some(generic_job) = self.generic_jobs.borrow_mut().pop_front()
-            && let Err(err) = generic_job.call(js_ctx)
-        {
-            eprintln!("Uncaught {err}");
+        let generic_job = self.generic_jobs.borrow_mut().pop_front();
+        if let Some(generic_job) = generic_job {
+            generic_job.call(js_ctx)?;
         }
 
         let promise_jobs = std::mem::take(self.promise_jobs.borrow_mut().deref_mut());
         for promise_job in promise_jobs {
-            if let Err(e) = promise_job.call(js_ctx) {
-                eprintln!("Uncaught {e}");
-            }
+            promise_job.call(js_ctx)?;
         }
 
         js_ctx.clear_kept_objects();
+        Ok(())
     }
 }
 
@@ -178,6 +209,12 @@ impl JobExecutor for Executor {
     async fn run_jobs_async(self: Rc<Self>, js_ctx: &RefCell<&mut Context>) -> JsResult<()> {
         let mut group = FutureGroup::new();
         loop {
+            if get_context().is_ok_and(|ctx| ctx.is_cancelled()) {
+                return Err(JsNativeError::error()
+                    .with_message("build cancelled")
+                    .into());
+            }
+
             for async_job in std::mem::take(self.async_jobs.borrow_mut().deref_mut()) {
                 trace!("inserting another async job");
                 group.insert(async_job.call(js_ctx));
@@ -197,10 +234,10 @@ impl JobExecutor for Executor {
             // instead of awaiting one at a time. Also also, it's an incorrect strategy to poll all
             // the pending futures at once.
             if let Some(Err(err)) = group.next().await {
-                eprintln!("Uncaught {err}");
+                return Err(err);
             }
 
-            self.drain_jobs(&mut js_ctx.borrow_mut());
+            self.drain_jobs(&mut js_ctx.borrow_mut())?;
             // TOOD: is it better or worse to yield here? Seems like worse but idk
         }
     }
@@ -242,6 +279,28 @@ impl ModuleLoader for MemoizedModuleLoader {
         // TODO: specify a base directory to sandbox the module imports
         let path =
             resolve_module_specifier(None, &specifier, referrer.path(), &mut js_ctx.borrow_mut())?;
+        let path = driver_util::normalize_path(&path);
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        if !self
+            .ctx
+            .options()
+            .module_extensions
+            .iter()
+            .any(|allowed| allowed == extension)
+        {
+            let what = match extension {
+                "" => "files without an extension aren't".to_string(),
+                _ => format!(".{extension} isn't one of the"),
+            };
+            return Err(JsNativeError::typ()
+                .with_message(format!(
+                    "can't import '{short_path}': {what} allowed module extensions"
+                ))
+                .into());
+        }
 
         // Shortcutting here is OK because we create a new loader for each file we execute, so we
         // do exactly one ReadFile for each import dependency we have.
@@ -259,12 +318,23 @@ impl ModuleLoader for MemoizedModuleLoader {
             JsNativeError::eval().with_message(format!("loading {} for {}", e, path.display()))
         })?;
 
+        if extension == "json" {
+            let json = std::str::from_utf8(&source_bytes).map_err(|e| {
+                JsNativeError::syntax().with_message(format!("'{short_path}' isn't UTF-8: {e}"))
+            })?;
+            let module = Module::parse_json(JsString::from(json), &mut js_ctx.borrow_mut())?;
+            let _ = self.js_module_map.insert_async(path, module.clone()).await;
+            return Ok(module);
+        }
+
         let source = boa_engine::Source::from_bytes(&source_bytes).with_path(&path);
         let module =
             boa_engine::Module::parse(source, None, &mut js_ctx.borrow_mut()).map_err(|err| {
-                eprintln!("error in module: {err}");
+                let rendered = diagnostic::annotate(&source_bytes, &path, err.clone().into());
                 JsNativeError::syntax()
-                    .with_message(format!("could not parse module '{short_path}'"))
+                    .with_message(format!(
+                        "could not parse module '{short_path}':\n{rendered}"
+                    ))
                     .with_cause(err)
             })?;
         let _ = self.js_module_map.insert_async(path, module.clone()).await;
@@ -309,6 +379,18 @@ where
     let driver_module = make_driver_module(js_ctx)?;
     loader.set_builtin_module("driver".to_string(), driver_module);
 
+    let native_modules = loader
+        .ctx
+        .extension::<NativeModules>()
+        .0
+        .read()
+        .expect("native module registry poisoned")
+        .clone();
+    for (name, builder) in native_modules {
+        let module = builder(js_ctx)?;
+        loader.set_builtin_module(name, module);
+    }
+
     f(js_ctx).await
 }
 
@@ -320,14 +402,31 @@ fn make_driver_module(js_ctx: &mut Context) -> JsResult<Module> {
         fn store(value: String) -> JsResult<JsObject>;
         fn slugify(value: String) -> JsResult<String>;
 
-        async fn read_file(path: JsPath) -> JsResult<JsObject>;
+        async fn read_file(path: JsPath, [js_ctx: &mut Context]) -> JsResult<JsObject>;
         async fn list_directory(dirname: JsPath) -> JsResult<Vec<String>>;
+        async fn walk_directory(dirname: JsPath) -> JsResult<Vec<String>>;
+        async fn for_each_in_directory(
+            dirname: JsPath,
+            callback: boa_engine::JsObject,
+            [js_ctx: &mut Context],
+        ) -> JsResult<()>;
+        async fn glob(pattern: String) -> JsResult<Vec<String>>;
         fn file_type(entry_name: String) -> JsResult<String>;
+        async fn env(name: String) -> JsResult<JsValue>;
+        fn build_time() -> JsResult<String>;
 
         async fn get_url(url: String) -> JsResult<JsObject>;
-
-        async fn markdown_to_html(contents: JsBlob) -> JsResult<JsObject>;
-        async fn minify_html(contents: JsBlob) -> JsResult<JsObject>;
+        async fn get_url_verified(url: String, sha256: String) -> JsResult<JsObject>;
+
+        async fn markdown_to_html(contents: JsBlob, options: JsValue) -> JsResult<JsObject>;
+        async fn minify_html(contents: JsBlob, options: JsValue) -> JsResult<JsObject>;
+        async fn compile_scss(contents: JsBlob) -> JsResult<JsObject>;
+        async fn concat(objects: Vec<JsBlob>) -> JsResult<JsObject>;
+        async fn optimize_wasm(contents: JsBlob) -> JsResult<JsObject>;
+        async fn reading_time(contents: JsBlob) -> JsResult<JsValue>;
+        async fn parse_json(contents: JsBlob) -> JsResult<JsValue>;
+        fn to_json(value: JsValue, options: JsValue) -> JsResult<JsObject>;
+        async fn parse_front_matter(contents: JsBlob) -> JsResult<JsValue>;
 
         async fn parse_image(blob: JsBlob) -> JsResult<JsImage>;
         async fn convert_image(
@@ -338,7 +437,9 @@ fn make_driver_module(js_ctx: &mut Context) -> JsResult<Module> {
 
         async fn run_js(filename: JsPath, args: JsValue) -> JsResult<JsValue>;
         async fn run_tera(filename: JsPath, args: JsValue) -> JsResult<JsValue>;
+        async fn render_tera(template: JsBlob, args: JsValue) -> JsResult<JsValue>;
         fn write_output(name: String, contents: JsBlob) -> JsResult<()>;
+        fn pick_output(outputs: JsValue, name: String) -> JsResult<JsObject>;
     ))
 }
 
@@ -346,33 +447,59 @@ mod driver_module {
     use super::*;
 
     use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::hash::{DefaultHasher, Hash as _, Hasher as _};
     use std::ops::DerefMut;
-    use std::path::{Component, PathBuf};
+    use std::path::{Component, Path, PathBuf};
 
     use boa_engine::value::TryFromJs;
     use boa_engine::{Context, js_str};
     use boa_engine::{JsError, JsNativeError, JsResult};
 
     use driver_engine::Uri;
-    use driver_query_fs::{ListDirectory, ReadFile};
+    use driver_query_fs::{ListDirectory, ReadFile, WalkDirectory, WalkFilter};
     use driver_query_hyper::GetUrl;
+    use driver_util::Secret;
 
+    use crate::QueryKey;
     use crate::comrak::MarkdownToHtml;
+    use crate::concat::Concat;
+    use crate::env::{DotEnv, EnvVar};
+    use crate::front_matter::ParseFrontMatter;
+    use crate::json::{NonFinite, ParseJson};
     use crate::minify_html::MinifyHtml;
-    use crate::tera::RunTera;
+    use crate::reading_time::ReadingTime;
+    use crate::scss::CompileScss;
+    use crate::tera::{RenderTera, RunTera};
+    use crate::wasm::OptimizeWasm;
     use crate::zune::{ConvertImage, ParseImage};
 
     pub fn slugify(value: String) -> JsResult<String> {
         Ok(slug::slugify(value))
     }
 
-    pub async fn read_file(path: JsPath) -> JsResult<JsBlob> {
+    /// A missing file throws an error with `code: "ENOENT"`, like Node does, so scripts can catch
+    /// that specifically.
+    pub async fn read_file(path: JsPath, js_ctx: &RefCell<&mut Context>) -> JsResult<JsBlob> {
         let ctx = &get_context()?;
 
         let read_file = ReadFile(path.0);
-        let blob = query(ctx, read_file.clone())
-            .await
-            .map_err(|e| JsNativeError::eval().with_message(format!("{read_file}: {e}")))?;
+        let blob = match query(ctx, read_file.clone()).await {
+            Ok(blob) => blob,
+            Err(e) if e.is_not_found() => {
+                let js_ctx = &mut js_ctx.borrow_mut();
+                let error = JsNativeError::error()
+                    .with_message(format!("{read_file}: {e}"))
+                    .to_opaque(js_ctx);
+                error.set(js_str!("code"), JsString::from("ENOENT"), false, js_ctx)?;
+                return Err(JsError::from_opaque(error.into()));
+            }
+            Err(e) => {
+                return Err(JsNativeError::eval()
+                    .with_message(format!("{read_file}: {e}"))
+                    .into());
+            }
+        };
 
         Ok(JsBlob { blob })
     }
@@ -380,7 +507,7 @@ mod driver_module {
     pub async fn list_directory(dirname: JsPath) -> JsResult<Vec<String>> {
         let ctx = &get_context()?;
 
-        let list_directory = ListDirectory(dirname.0);
+        let list_directory = ListDirectory::new(dirname.0, ctx.options());
         let contents = query(ctx, list_directory.clone())
             .await
             .map_err(|e| JsNativeError::eval().with_message(format!("{list_directory}: {e}")))?
@@ -391,6 +518,173 @@ mod driver_module {
         Ok(contents)
     }
 
+    pub async fn walk_directory(dirname: JsPath) -> JsResult<Vec<String>> {
+        let ctx = &get_context()?;
+
+        let walk_directory = WalkDirectory::new(dirname.0, ctx.options());
+        let contents = query(ctx, walk_directory.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{walk_directory}: {e}")))?
+            .into_iter()
+            .map(|entry| entry.display().to_string())
+            .collect();
+
+        Ok(contents)
+    }
+
+    /// Like `list_directory`, but calls `callback` with each entry instead of building one big
+    /// array, which gets slow in JS land for huge directories. Returning `false` from the callback
+    /// stops early.
+    pub async fn for_each_in_directory(
+        dirname: JsPath,
+        callback: boa_engine::JsObject,
+        js_ctx: &RefCell<&mut Context>,
+    ) -> JsResult<()> {
+        let ctx = &get_context()?;
+
+        if !callback.is_callable() {
+            return Err(JsNativeError::typ()
+                .with_message("callback must be a function")
+                .into());
+        }
+
+        let list_directory = ListDirectory::new(dirname.0, ctx.options());
+        let error = |e: driver_util::Error| {
+            JsNativeError::eval().with_message(format!("{list_directory}: {e}"))
+        };
+
+        // Straight off the disk, so the callback can get started before the whole directory's
+        // been read. Only a hash of what was handed out is kept, since the directory is huge.
+        let mut streamed = DefaultHasher::new();
+        let mut count = 0;
+        let mut stopped_early = false;
+        for entry in list_directory.entries(ctx.options()).map_err(error)? {
+            let entry = entry.map_err(error)?;
+            entry.hash(&mut streamed);
+            count += 1;
+            let entry = boa_engine::JsValue::from(JsString::from(entry.display().to_string()));
+            let keep_going = callback.call(
+                &boa_engine::JsValue::undefined(),
+                &[entry],
+                &mut js_ctx.borrow_mut(),
+            )?;
+            if keep_going.as_boolean() == Some(false) {
+                stopped_early = true;
+                break;
+            }
+        }
+
+        // Only for tracking the dependency, so this is the second time the directory gets listed,
+        // unless something else already listed it this build. If the directory changed in
+        // between, the callback saw something other than what was recorded, and a later build
+        // could wrongly skip re-running.
+        let listed = query(ctx, list_directory.clone()).await.map_err(error)?;
+        let matches = match listed.get(..count) {
+            Some(seen) if stopped_early || seen.len() == listed.len() => {
+                let mut recorded = DefaultHasher::new();
+                for entry in seen {
+                    entry.hash(&mut recorded);
+                }
+                recorded.finish() == streamed.finish()
+            }
+            _ => false,
+        };
+        if !matches {
+            return Err(error(driver_util::Error::new(
+                "directory changed while it was being listed",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Finds every path matching `pattern`, going through `ListDirectory` for each directory it
+    /// looks in, so that adding/removing a matching file reruns whatever called this.
+    pub async fn glob(pattern: String) -> JsResult<Vec<String>> {
+        let ctx = &get_context()?;
+
+        let (base, max_depth) = split_glob(&pattern)?;
+        let normalized = driver_util::normalize_path(Path::new(&pattern));
+        let matcher = globset::GlobBuilder::new(&normalized.to_string_lossy())
+            .literal_separator(true)
+            .build()
+            .map_err(|e| JsNativeError::eval().with_message(format!("glob({pattern:?}): {e}")))?
+            .compile_matcher();
+
+        let mut matches = Vec::new();
+        let filter = WalkFilter::from_options(ctx.options());
+        let mut dirs = vec![(base.clone(), 1)];
+        // Same as `WalkDirectory`, symlinks can point back up the tree
+        let mut seen = HashSet::new();
+        while let Some((dir, depth)) = dirs.pop() {
+            if !seen.insert(std::fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+                continue;
+            }
+            let list_directory = ListDirectory::in_walk(dir.clone(), &base, filter.clone());
+            let entries = match query(ctx, list_directory.clone()).await {
+                Ok(entries) => entries,
+                // Still depends on the listing, so the directory showing up later is noticed
+                Err(_) if !dir.exists() => continue,
+                Err(e) => {
+                    return Err(JsNativeError::eval()
+                        .with_message(format!("{list_directory}: {e}"))
+                        .into());
+                }
+            };
+            for entry in entries {
+                let entry = driver_util::normalize_path(&entry);
+                if matcher.is_match(&entry) {
+                    matches.push(entry.display().to_string());
+                }
+                if max_depth.is_none_or(|max| depth < max) && entry.is_dir() {
+                    dirs.push((entry, depth + 1));
+                }
+            }
+        }
+        matches.sort();
+        Ok(matches)
+    }
+
+    /// Splits a glob into the directory to start looking in (everything before the first
+    /// wildcard), and how many levels below it matches can be, if that's limited.
+    pub(super) fn split_glob(pattern: &str) -> JsResult<(PathBuf, Option<usize>)> {
+        let path = driver_util::normalize_path(Path::new(pattern));
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::CurDir | Component::Normal(_)))
+        {
+            // Same as `write_output`, don't look outside the project
+            return Err(JsNativeError::eval()
+                .with_message(format!("directory traversal {pattern}"))
+                .into());
+        }
+
+        let is_wild = |component: &Component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', '{'])
+        };
+        let components = path.components().collect::<Vec<_>>();
+        let split = components
+            .iter()
+            .position(is_wild)
+            // No wildcards at all, so it can only match itself
+            .unwrap_or(components.len().saturating_sub(1));
+        let (base, rest) = components.split_at(split);
+
+        let base = if base.is_empty() {
+            PathBuf::from(".")
+        } else {
+            base.iter().collect()
+        };
+        let max_depth = if rest.iter().any(|c| c.as_os_str() == "**") {
+            None
+        } else {
+            Some(rest.len())
+        };
+        Ok((base, max_depth))
+    }
+
     pub async fn run_js(filename: JsPath, arg: JsValue) -> JsResult<JsValue> {
         let ctx = &get_context()?;
 
@@ -400,6 +694,21 @@ mod driver_module {
             arg: arg.clone(),
         };
 
+        if let Some(cycle) = ctx.find_cycle(&task.clone().into()) {
+            let cycle: Vec<String> = cycle
+                .iter()
+                .map(|key| match key {
+                    QueryKey::RunJs(run_js) => run_js.file.display().to_string(),
+                    other => other.to_string(),
+                })
+                .collect();
+            return Err(JsNativeError::eval()
+                .with_message(format!("dependency cycle detected: {}", cycle.join(" -> ")))
+                .into());
+        }
+        ctx.check_depth()
+            .map_err(|e| JsNativeError::eval().with_message(format!("{task}: {e}")))?;
+
         let (hash, output) = query_with_hash(ctx, task.clone()).await;
 
         unsafe { with_outputs(|outputs| outputs.merge(hash, output.writes)) }?;
@@ -431,6 +740,25 @@ mod driver_module {
         }))
     }
 
+    /// Like `run_tera`, but for a template that's already in the store.
+    pub async fn render_tera(template: JsBlob, arg: JsValue) -> JsResult<JsValue> {
+        let ctx = &get_context()?;
+
+        let task = RenderTera {
+            template: template.blob,
+            arg,
+        };
+        let (hash, output) = query_with_hash(ctx, task.clone()).await;
+
+        unsafe { with_outputs(|outputs| outputs.merge(hash, output.writes)) }?;
+
+        Ok(JsValue::Store(JsBlob {
+            blob: output
+                .export
+                .map_err(|e| JsNativeError::eval().with_message(format!("{task}:\n\t{e}")))?,
+        }))
+    }
+
     pub fn file_type(entry_name: String) -> JsResult<String> {
         let metadata = std::fs::metadata(PathBuf::from(entry_name)).map_err(JsError::from_rust)?;
 
@@ -457,6 +785,43 @@ mod driver_module {
         Ok(JsBlob { blob })
     }
 
+    pub async fn env(name: String) -> JsResult<JsValue> {
+        let ctx = &get_context()?;
+
+        let expose = |secret: Option<Secret>| -> driver_util::Result<JsValue> {
+            match secret {
+                Some(secret) => Ok(JsValue::String(secret.expose()?.to_string())),
+                None => Ok(JsValue::Undefined),
+            }
+        };
+
+        // The .env file wins, so it can override whatever's in the environment locally
+        if let Some(dotenv) = DotEnv::new(name.clone(), ctx.options()) {
+            let value = query(ctx, dotenv.clone())
+                .await
+                .and_then(expose)
+                .map_err(|e| JsNativeError::eval().with_message(format!("{dotenv}: {e}")))?;
+            if value != JsValue::Undefined {
+                return Ok(value);
+            }
+        }
+
+        let env_var = EnvVar(name);
+        let value = query(ctx, env_var.clone())
+            .await
+            .and_then(expose)
+            .map_err(|e| JsNativeError::eval().with_message(format!("{env_var}: {e}")))?;
+        Ok(value)
+    }
+
+    pub fn build_time() -> JsResult<String> {
+        let ctx = &get_context()?;
+
+        let time = jiff::Timestamp::try_from(ctx.build_time())
+            .map_err(|e| JsNativeError::eval().with_message(format!("build_time: {e}")))?;
+        Ok(time.to_string())
+    }
+
     pub async fn get_url(url: String) -> JsResult<JsBlob> {
         let ctx = &get_context()?;
         let uri = url
@@ -470,26 +835,185 @@ mod driver_module {
         Ok(JsBlob { blob })
     }
 
-    pub async fn markdown_to_html(contents: JsBlob) -> JsResult<JsBlob> {
+    pub async fn get_url_verified(url: String, sha256: String) -> JsResult<JsBlob> {
+        let blob = get_url(url.clone()).await?;
+        blob.blob.verify_sha256(&sha256).map_err(|e| {
+            JsNativeError::eval().with_message(format!("get_url_verified(\"{url}\"): {e}"))
+        })?;
+        Ok(blob)
+    }
+
+    /// Applies an optional object of `{ name: boolean }` overrides with `set`.
+    fn set_bool_options(
+        what: &str,
+        options: JsValue,
+        mut set: impl FnMut(&str, bool) -> driver_util::Result<()>,
+    ) -> JsResult<()> {
+        let options = match options {
+            JsValue::Undefined => return Ok(()),
+            JsValue::Object(options) => options,
+            other => {
+                return Err(JsNativeError::typ()
+                    .with_message(format!("expected {what} options, got {other}"))
+                    .into());
+            }
+        };
+        for (name, value) in options {
+            let JsValue::Bool(value) = value else {
+                return Err(JsNativeError::typ()
+                    .with_message(format!("{what} option {name:?} must be a boolean"))
+                    .into());
+            };
+            set(&name, value).map_err(|e| JsNativeError::eval().with_message(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub async fn markdown_to_html(contents: JsBlob, options: JsValue) -> JsResult<JsBlob> {
         let ctx = &get_context()?;
 
-        let markdown_to_html = MarkdownToHtml(contents.blob.clone());
+        let mut markdown_to_html = MarkdownToHtml::new(contents.blob.clone());
+        set_bool_options("markdown", options, |name, value| {
+            markdown_to_html.options.set(name, value)
+        })?;
         let blob = query(ctx, markdown_to_html.clone())
             .await
             .map_err(|e| JsNativeError::eval().with_message(format!("{markdown_to_html}: {e}")))?;
         Ok(JsBlob { blob })
     }
 
-    pub async fn minify_html(contents: JsBlob) -> JsResult<JsBlob> {
+    pub async fn minify_html(contents: JsBlob, options: JsValue) -> JsResult<JsBlob> {
         let ctx = &get_context()?;
 
-        let minify_html = MinifyHtml(contents.blob.clone());
+        let mut minify_html = MinifyHtml::new(contents.blob.clone(), ctx.options());
+        set_bool_options("minify", options, |name, value| {
+            minify_html.config.set(name, value)
+        })?;
         let blob = query(ctx, minify_html.clone())
             .await
             .map_err(|e| JsNativeError::eval().with_message(format!("{minify_html}: {e}")))?;
         Ok(JsBlob { blob })
     }
 
+    pub async fn compile_scss(contents: JsBlob) -> JsResult<JsBlob> {
+        let ctx = &get_context()?;
+
+        let compile_scss = CompileScss(contents.blob.clone());
+        let blob = query(ctx, compile_scss.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{compile_scss}: {e}")))?;
+        Ok(JsBlob { blob })
+    }
+
+    pub async fn concat(objects: Vec<JsBlob>) -> JsResult<JsBlob> {
+        let ctx = &get_context()?;
+
+        let concat = Concat(objects.into_iter().map(|object| object.blob).collect());
+        let blob = query(ctx, concat.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{concat}: {e}")))?;
+        Ok(JsBlob { blob })
+    }
+
+    pub async fn optimize_wasm(contents: JsBlob) -> JsResult<JsBlob> {
+        let ctx = &get_context()?;
+
+        let optimize_wasm = OptimizeWasm::new(contents.blob.clone(), ctx.options());
+        let blob = query(ctx, optimize_wasm.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{optimize_wasm}: {e}")))?;
+        Ok(JsBlob { blob })
+    }
+
+    pub async fn reading_time(contents: JsBlob) -> JsResult<JsValue> {
+        let ctx = &get_context()?;
+
+        let reading_time = ReadingTime::new(contents.blob.clone(), ctx.options());
+        let stats = query(ctx, reading_time.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{reading_time}: {e}")))?;
+        let int = |n: u32| JsValue::Int(n.try_into().unwrap_or(i32::MAX));
+        Ok(JsValue::Object(
+            [
+                ("words".to_string(), int(stats.words)),
+                ("minutes".to_string(), int(stats.minutes)),
+            ]
+            .into(),
+        ))
+    }
+
+    pub async fn parse_json(contents: JsBlob) -> JsResult<JsValue> {
+        let ctx = &get_context()?;
+
+        let parse_json = ParseJson(contents.blob.clone());
+        let value = query(ctx, parse_json.clone())
+            .await
+            .map_err(|e| JsNativeError::eval().with_message(format!("{parse_json}: {e}")))?;
+        Ok(value)
+    }
+
+    pub async fn parse_front_matter(contents: JsBlob) -> JsResult<JsValue> {
+        let ctx = &get_context()?;
+
+        let parse_front_matter = ParseFrontMatter(contents.blob.clone());
+        let front_matter = query(ctx, parse_front_matter.clone()).await.map_err(|e| {
+            JsNativeError::eval().with_message(format!("{parse_front_matter}: {e}"))
+        })?;
+        Ok(JsValue::Object(
+            [
+                ("data".to_string(), front_matter.data),
+                (
+                    "body".to_string(),
+                    JsValue::Store(JsBlob {
+                        blob: front_matter.body,
+                    }),
+                ),
+            ]
+            .into(),
+        ))
+    }
+
+    pub fn to_json(value: JsValue, options: JsValue) -> JsResult<JsBlob> {
+        let ctx = &get_context()?;
+
+        let mut non_finite = NonFinite::default();
+        match options {
+            JsValue::Undefined => {}
+            JsValue::Object(options) => {
+                for (name, value) in options {
+                    match (name.as_str(), value) {
+                        ("non_finite", JsValue::String(policy)) => {
+                            non_finite = NonFinite::from_name(&policy).map_err(|e| {
+                                JsNativeError::eval().with_message(format!("to_json: {e}"))
+                            })?;
+                        }
+                        ("non_finite", _) => {
+                            return Err(JsNativeError::typ()
+                                .with_message("to_json option \"non_finite\" must be a string")
+                                .into());
+                        }
+                        (name, _) => {
+                            return Err(JsNativeError::typ()
+                                .with_message(format!("unknown to_json option {name:?}"))
+                                .into());
+                        }
+                    }
+                }
+            }
+            other => {
+                return Err(JsNativeError::typ()
+                    .with_message(format!("expected to_json options, got {other}"))
+                    .into());
+            }
+        }
+        let json = crate::json::to_json(&value, non_finite)
+            .map_err(|e| JsNativeError::eval().with_message(format!("to_json: {e}")))?;
+        let blob = ctx
+            .store(json.to_string().into_bytes())
+            .map_err(|err| JsNativeError::eval().with_message(format!("loading {err}")))?;
+        Ok(JsBlob { blob })
+    }
+
     pub async fn parse_image(blob: JsBlob) -> JsResult<JsImage> {
         let ctx = &get_context()?;
 
@@ -590,6 +1114,28 @@ mod driver_module {
         };
         Ok(())
     }
+
+    /// Picks one output out of a query that produced several (see [`driver_engine::NamedBlobs`]).
+    /// Unlike just indexing the object, this throws if there's no output with that name.
+    pub fn pick_output(outputs: JsValue, name: String) -> JsResult<JsBlob> {
+        let JsValue::Object(outputs) = outputs else {
+            return Err(JsNativeError::typ()
+                .with_message(format!("expected named outputs, got {outputs}"))
+                .into());
+        };
+        match outputs.get(&name) {
+            Some(JsValue::Store(blob)) => Ok(blob.clone()),
+            Some(other) => Err(JsNativeError::typ()
+                .with_message(format!("output {name:?} is not a store object: {other}"))
+                .into()),
+            None => {
+                let names = outputs.keys().cloned().collect::<Vec<_>>().join(", ");
+                Err(JsNativeError::eval()
+                    .with_message(format!("no output named {name:?} (have: {names})"))
+                    .into())
+            }
+        }
+    }
 }
 
 driver_engine::key!(
@@ -611,7 +1157,19 @@ driver_engine::blob_trace!(RunJsOutput => {
     writes,
 });
 
-driver_engine::producer!(RunJs(self, ctx) as (crate::QueryKey) -> RunJsOutput {
+impl RunJsOutput {
+    /// An output that failed before writing anything.
+    pub fn failed(error: driver_util::Error) -> Self {
+        Self {
+            export: Err(error),
+            writes: WriteOutput::builder().finalize(),
+        }
+    }
+}
+
+driver_engine::producer!(RunJs(self, ctx) as (crate::QueryKey) -> RunJsOutput, on_error(error) {
+    RunJsOutput::failed(error)
+} {
     println!("{}", self);
 
     let file = self.file.clone();
@@ -620,6 +1178,7 @@ driver_engine::producer!(RunJs(self, ctx) as (crate::QueryKey) -> RunJsOutput {
     let (export, writes) = with_query_context(ctx.clone(), async move || {
         let key = format!("{}({})", file.display(), arg);
         trace!("with_query_context start {key}");
+        ctx.cancellation().check()?;
 
         let blob = query(ctx, ReadFile(self.file.clone())).await?;
         let contents = ctx.load_bytes(&blob)?;
@@ -627,11 +1186,12 @@ driver_engine::producer!(RunJs(self, ctx) as (crate::QueryKey) -> RunJsOutput {
         let out = with_js_ctx(ctx.clone(), arg.clone(), async move |js_ctx| {
             trace!("with_js_ctx start");
             let source = boa_engine::Source::from_bytes(&contents).with_path(&file);
-            let module = boa_engine::Module::parse(source, None, js_ctx)?;
+            let module = boa_engine::Module::parse(source, None, js_ctx)
+                .map_err(|err| diagnostic::annotate(&contents, &file, err.into()))?;
             let promise = module.load_link_evaluate(js_ctx);
             let executor = js_ctx.downcast_job_executor::<Executor>().unwrap();
             trace!("starting to run jobs");
-            executor.run_jobs_async(&RefCell::new(js_ctx)).await?;
+            executor.clone().run_jobs_async(&RefCell::new(js_ctx)).await?;
 
             match promise.state() {
                 PromiseState::Pending => {
@@ -639,11 +1199,43 @@ driver_engine::producer!(RunJs(self, ctx) as (crate::QueryKey) -> RunJsOutput {
                 }
                 PromiseState::Fulfilled(v) => assert_eq!(v, boa_engine::JsValue::undefined()),
                 PromiseState::Rejected(err) => {
-                    return Err(JsError::from_opaque(err).try_native(js_ctx)?.into());
+                    return Err(diagnostic::uncaught(err, &file, js_ctx));
                 }
             }
 
-            let value = module.namespace(js_ctx).get(js_str!("default"), js_ctx)?;
+            let mut value = module.namespace(js_ctx).get(js_str!("default"), js_ctx)?;
+
+            // `export default someAsyncFunction()` is an easy thing to write, so let's just wait
+            // for it like the author probably meant.
+            if let Some(promise) = value
+                .as_object()
+                .and_then(|object| JsPromise::from_object(object.clone()).ok())
+            {
+                trace!("waiting on default export");
+                executor.run_jobs_async(&RefCell::new(js_ctx)).await?;
+                value = match promise.state() {
+                    PromiseState::Pending => {
+                        return Err(driver_util::Error::new(&format!(
+                            "{}: default export is a promise that never settled",
+                            file.display()
+                        )));
+                    }
+                    PromiseState::Fulfilled(v) => v,
+                    PromiseState::Rejected(err) => {
+                        return Err(diagnostic::uncaught(err, &file, js_ctx));
+                    }
+                };
+            }
+
+            // Functions can't be turned into values we can cache, but it's also a pretty common
+            // mistake to forget to call one, so point that out specifically.
+            if value.is_callable() {
+                return Err(driver_util::Error::new(&format!(
+                    "{}: default export is a function; did you mean to export the result of calling it?",
+                    file.display()
+                )));
+            }
+
             let value = JsValue::try_from_js(&value, js_ctx)?;
             trace!("with_js_ctx end");
             Ok(value)
@@ -662,3 +1254,811 @@ impl std::fmt::Display for RunJs {
         write!(f, "run_js(\"{}\", {})", self.file.display(), self.arg)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    #[test]
+    fn reports_run_js_cycles() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let a = dir.join("a.js");
+        let b = dir.join("b.js");
+        let script = |other: &Path| {
+            format!(
+                "import {{ run_js }} from \"driver\";\nexport default await run_js(\"{}\", []);\n",
+                other.display()
+            )
+        };
+        std::fs::write(&a, script(&b)).unwrap();
+        std::fs::write(&b, script(&a)).unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: a.clone(),
+                arg: parse_args([]),
+            },
+        ));
+        let err = output.export.unwrap_err().to_string();
+        let cycle = format!(
+            "dependency cycle detected: {} -> {} -> {}",
+            a.display(),
+            b.display(),
+            a.display()
+        );
+        assert!(err.contains(&cycle), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn limits_run_js_depth() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let chain = dir.join("chain.js");
+        std::fs::write(
+            &chain,
+            format!(
+                "import {{ run_js }} from \"driver\";\nexport default ARG > 0 ? await run_js(\"{}\", ARG - 1) : \"done\";\n",
+                chain.display()
+            ),
+        )
+        .unwrap();
+
+        let mut options = driver_engine::Options::with_base_dir(&dir.join(".driver"));
+        options.max_query_depth = 8;
+        let ctx = QueryContext::create_root(options, None);
+        let run = |depth: i32| {
+            block_on(query(
+                &ctx,
+                RunJs {
+                    file: chain.clone(),
+                    arg: JsValue::Int(depth),
+                },
+            ))
+            .export
+        };
+        assert_eq!(run(3).unwrap(), JsValue::String("done".to_string()));
+        let err = run(20).unwrap_err().to_string();
+        assert!(err.contains("queries nested more than 8 deep"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn default_run_js_depth_fits_the_default_stack() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap().to_path_buf();
+        let chain = dir.join("chain.js");
+        std::fs::write(
+            &chain,
+            format!(
+                "import {{ run_js }} from \"driver\";\nexport default ARG > 0 ? await run_js(\"{}\", ARG - 1) : \"done\";\n",
+                chain.display()
+            ),
+        )
+        .unwrap();
+
+        // Spawned threads get the default stack size, unlike the test's own thread, which can be
+        // configured to be bigger
+        std::thread::spawn(move || {
+            let options = driver_engine::Options::with_base_dir(&dir.join(".driver"));
+            let max = options.max_query_depth;
+            let ctx = QueryContext::create_root(options, None);
+            let run = |depth: usize| {
+                block_on(query(
+                    &ctx,
+                    RunJs {
+                        file: chain.clone(),
+                        arg: JsValue::Int(depth as i32),
+                    },
+                ))
+                .export
+            };
+            assert_eq!(run(max - 8).unwrap(), JsValue::String("done".to_string()));
+            let err = run(max + 8).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("queries nested more than {max} deep")),
+                "{err}"
+            );
+            ctx.destroy_root().unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn reports_where_errors_were_thrown() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let main = dir.join("main.js");
+        let lib = dir.join("lib.js");
+        std::fs::write(
+            &main,
+            "import { boom } from \"./lib.js\";\nexport default boom();\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &lib,
+            "export function boom() {\n  throw new Error(\"boom\");\n}\n",
+        )
+        .unwrap();
+        let other = dir.join("other.js");
+        std::fs::write(&other, "throw \"oops\";\n").unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let run = |file: &Path| {
+            let output = block_on(query(
+                &ctx,
+                RunJs {
+                    file: file.to_path_buf(),
+                    arg: parse_args([]),
+                },
+            ));
+            output.export.unwrap_err().to_string()
+        };
+
+        let err = run(&main);
+        assert!(err.starts_with(&lib.display().to_string()), "{err}");
+        assert!(err.contains("boom"), "{err}");
+        // Not an Error, so there's no stack to go off of, but we at least know which script
+        let err = run(&other);
+        assert!(err.starts_with(&other.display().to_string()), "{err}");
+        assert!(err.contains("oops"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn reports_syntax_errors_in_imports() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let main = dir.join("main.js");
+        std::fs::write(&main, "import \"./bad.js\";\nexport default 1;\n").unwrap();
+        std::fs::write(dir.join("bad.js"), "let x = 1;\nlet y = );\n").unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: main,
+                arg: parse_args([]),
+            },
+        ));
+        // The snippet ends up in the error itself, so it's still there when the error is cached
+        let err = output.export.unwrap_err().to_string();
+        assert!(err.contains("could not parse module './bad.js'"), "{err}");
+        assert!(err.contains("2 | let y = );"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn imports_json() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        std::fs::write(dir.join("site.json"), "{ \"title\": \"hello\" }").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join("common.cjs"), "export default \" from cjs\";\n").unwrap();
+        std::fs::write(dir.join("plain"), "export default \" & plain\";\n").unwrap();
+        let good = dir.join("good.js");
+        std::fs::write(
+            &good,
+            "import site from \"./site.json\";\nimport common from \"./common.cjs\";\nimport plain from \"./plain\";\nexport default site.title + common + plain;\n",
+        )
+        .unwrap();
+        let bad = dir.join("bad.js");
+        std::fs::write(&bad, "import \"./notes.txt\";\nexport default 1;\n").unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let run = |file: &Path| {
+            block_on(query(
+                &ctx,
+                RunJs {
+                    file: file.to_path_buf(),
+                    arg: parse_args([]),
+                },
+            ))
+            .export
+        };
+        assert_eq!(
+            run(&good).unwrap(),
+            JsValue::String("hello from cjs & plain".to_string())
+        );
+        let err = run(&bad).unwrap_err().to_string();
+        assert!(err.contains("can't import './notes.txt'"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    fn greetings_module(js_ctx: &mut Context) -> JsResult<Module> {
+        let source = boa_engine::Source::from_bytes("export const greeting = \"hi\";");
+        Module::parse(source, None, js_ctx)
+    }
+
+    #[test]
+    fn imports_native_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("build.js");
+        std::fs::write(
+            &script,
+            "import { greeting } from \"greetings\";\nexport default greeting;\n",
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.path().join(".driver")),
+            None,
+        );
+        register_native_module(&ctx, "greetings", greetings_module).unwrap();
+        assert!(register_native_module(&ctx, "driver", greetings_module).is_err());
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(output.export.unwrap(), JsValue::String("hi".to_string()));
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn streams_huge_directories() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let huge = dir.join("huge");
+        std::fs::create_dir_all(&huge).unwrap();
+        for i in 0..5000 {
+            std::fs::write(huge.join(format!("{i:04}.md")), "").unwrap();
+        }
+        let script = dir.join("count.js");
+        std::fs::write(
+            &script,
+            format!(
+                r#"import {{ for_each_in_directory }} from "driver";
+let all = 0;
+await for_each_in_directory("{huge}", () => {{ all += 1; }});
+let first = [];
+await for_each_in_directory("{huge}", (entry) => {{ first.push(entry); return first.length < 2; }});
+export default [all, ...first];
+"#,
+                huge = huge.display()
+            ),
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![
+                JsValue::Int(5000),
+                JsValue::String(huge.join("0000.md").display().to_string()),
+                JsValue::String(huge.join("0001.md").display().to_string()),
+            ])
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn awaits_promise_default_exports() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("later.js");
+        std::fs::write(
+            &script,
+            "export default (async () => { await null; return \"later\"; })();\n",
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(output.export.unwrap(), JsValue::String("later".to_string()));
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn rejects_function_default_exports() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("uncalled.js");
+        std::fs::write(&script, "export default async () => \"never\";\n").unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        let err = output.export.unwrap_err().to_string();
+        assert!(err.contains("default export is a function"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn env_prefers_env_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let env_file = dir.join(".env");
+        // Cargo sets this for every test it runs, so it's in the process environment too
+        std::fs::write(&env_file, "CARGO_PKG_NAME=from-env-file\n").unwrap();
+        let script = dir.join("env.js");
+        std::fs::write(
+            &script,
+            r#"import { env } from "driver";
+export default [await env("CARGO_PKG_NAME"), await env("CARGO_PKG_VERSION")];
+"#,
+        )
+        .unwrap();
+
+        let mut options = driver_engine::Options::with_base_dir(&dir.join(".driver"));
+        options.env_file = Some(env_file);
+        let ctx = QueryContext::create_root(options, None);
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![
+                JsValue::String("from-env-file".to_string()),
+                JsValue::String(env!("CARGO_PKG_VERSION").to_string()),
+            ])
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn cached_scripts_keep_their_build_time() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let script = dir.join("stamp.js");
+        std::fs::write(
+            &script,
+            r#"import { build_time } from "driver";
+export default [build_time(), build_time()];
+"#,
+        )
+        .unwrap();
+        let key = RunJs {
+            file: script,
+            arg: parse_args([]),
+        };
+        let build_time = |ctx: &QueryContext| {
+            let time = jiff::Timestamp::try_from(ctx.build_time()).unwrap();
+            JsValue::String(time.to_string())
+        };
+
+        let first = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let stamped = build_time(&first);
+        let output = block_on(query(&first, key.clone()));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![stamped.clone(), stamped.clone()])
+        );
+        first.destroy_root().unwrap();
+
+        // Nothing the script read changed, so it keeps the time from the first build
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let second = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        assert_ne!(build_time(&second), stamped);
+        let output = block_on(query(&second, key));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![stamped.clone(), stamped])
+        );
+        second.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn tracks_input_files() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let posts = dir.join("posts");
+        std::fs::create_dir_all(&posts).unwrap();
+        let data = dir.join("data.txt");
+        std::fs::write(&data, "data").unwrap();
+        let unread = dir.join("unread.txt");
+        std::fs::write(&unread, "unread").unwrap();
+        let script = dir.join("index.js");
+        std::fs::write(
+            &script,
+            format!(
+                r#"import {{ read_file, list_directory }} from "driver";
+await read_file("{data}");
+export default await list_directory("{posts}");
+"#,
+                data = data.display(),
+                posts = posts.display()
+            ),
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let (root, output) = block_on(query_with_hash(
+            &ctx,
+            RunJs {
+                file: script.clone(),
+                arg: parse_args([]),
+            },
+        ));
+        output.export.unwrap();
+
+        let inputs = crate::input_files(&ctx, root);
+        assert!(inputs.contains(&script), "{inputs:?}");
+        assert!(inputs.contains(&data), "{inputs:?}");
+        assert!(inputs.contains(&posts), "{inputs:?}");
+        assert!(!inputs.contains(&unread), "{inputs:?}");
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_catchable() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            format!(
+                "import {{ read_file }} from \"driver\";\nlet code;\ntry {{ await read_file(\"{}/missing.toml\"); }} catch (e) {{ code = e.code; }}\nexport default code;\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::String("ENOENT".to_string())
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn reads_binary_files() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let bytes = [0x89, b'P', b'N', b'G', 0xff, 0x00];
+        std::fs::write(dir.join("image.png"), bytes).unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            format!(
+                "import {{ read_file }} from \"driver\";\nconst file = await read_file(\"{}/image.png\");\nlet decoded = true;\ntry {{ file.toString(); }} catch {{ decoded = false; }}\nexport default [Array.from(file.data()), decoded];\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        // Only asking for a string tries to decode anything
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![
+                JsValue::Array(bytes.map(|b| JsValue::Int(b.into())).to_vec()),
+                JsValue::Bool(false),
+            ])
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn hashes_are_hex() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            "import { store } from \"driver\";\nexport default store(\"hello\").hash();\n",
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script,
+                arg: parse_args([]),
+            },
+        ));
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::String(
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string()
+            )
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+
+    #[test]
+    fn splits_globs() {
+        let split = |pattern| driver_module::split_glob(pattern).unwrap();
+        assert_eq!(split("content/**/*.md"), (PathBuf::from("content"), None));
+        assert_eq!(split("*.md"), (PathBuf::from("."), Some(1)));
+        assert_eq!(split("a/b/*/c.md"), (PathBuf::from("a/b"), Some(2)));
+        assert_eq!(split("a/b.md"), (PathBuf::from("a"), Some(1)));
+        assert!(driver_module::split_glob("../*.md").is_err());
+    }
+
+    #[test]
+    fn glob_stops_at_symlink_loops() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        std::fs::create_dir_all(dir.join("content/posts")).unwrap();
+        std::fs::write(dir.join("content/posts/a.md"), "").unwrap();
+        std::os::unix::fs::symlink(
+            std::fs::canonicalize(dir.join("content")).unwrap(),
+            dir.join("content/posts/up"),
+        )
+        .unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            format!(
+                "import {{ glob }} from \"driver\";\nexport default await glob(\"{}/content/**/*.md\");\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script.clone(),
+                arg: parse_args([]),
+            },
+        ));
+        ctx.destroy_root().unwrap();
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![JsValue::String(
+                dir.join("content/posts/a.md").display().to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn glob_skips_nested_ignores() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        std::fs::create_dir_all(dir.join("content/posts/drafts")).unwrap();
+        std::fs::write(dir.join("content/posts/a.md"), "").unwrap();
+        std::fs::write(dir.join("content/posts/drafts/b.md"), "").unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            format!(
+                "import {{ glob }} from \"driver\";\nexport default await glob(\"{}/content/**/*.md\");\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let mut options = driver_engine::Options::with_base_dir(&dir.join(".driver"));
+        // Relative to where the glob starts looking, not to the directory being listed
+        options.ignore_globs = vec!["posts/drafts".to_string()];
+        let ctx = QueryContext::create_root(options, None);
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script.clone(),
+                arg: parse_args([]),
+            },
+        ));
+        ctx.destroy_root().unwrap();
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![JsValue::String(
+                dir.join("content/posts/a.md").display().to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn glob_picks_up_new_files() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        std::fs::create_dir_all(dir.join("content/posts")).unwrap();
+        std::fs::write(dir.join("content/index.md"), "").unwrap();
+        std::fs::write(dir.join("content/posts/a.md"), "").unwrap();
+        std::fs::write(dir.join("content/posts/a.png"), "").unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            format!(
+                "import {{ glob }} from \"driver\";\nexport default [\n  await glob(\"{0}/content/**/*.md\"),\n  await glob(\"{0}/nothing/*\"),\n];\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let build = || {
+            let ctx = QueryContext::create_root(
+                driver_engine::Options::with_base_dir(&dir.join(".driver")),
+                None,
+            );
+            let output = block_on(query(
+                &ctx,
+                RunJs {
+                    file: script.clone(),
+                    arg: parse_args([]),
+                },
+            ));
+            ctx.destroy_root().unwrap();
+            output.export.unwrap()
+        };
+        let expected = |paths: &[&str]| {
+            JsValue::Array(vec![
+                JsValue::Array(
+                    paths
+                        .iter()
+                        .map(|p| JsValue::String(dir.join(p).display().to_string()))
+                        .collect(),
+                ),
+                JsValue::Array(vec![]),
+            ])
+        };
+
+        assert_eq!(
+            build(),
+            expected(&["content/index.md", "content/posts/a.md"])
+        );
+        std::fs::write(dir.join("content/posts/b.md"), "").unwrap();
+        assert_eq!(
+            build(),
+            expected(&[
+                "content/index.md",
+                "content/posts/a.md",
+                "content/posts/b.md"
+            ])
+        );
+    }
+
+    #[test]
+    fn to_json_non_finite_policy() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let script = dir.join("build.js");
+        std::fs::write(
+            &script,
+            r#"import { to_json } from "driver";
+let threw = false;
+try {
+  to_json([NaN]);
+} catch {
+  threw = true;
+}
+export default [threw, to_json([NaN, Infinity], { non_finite: "null" }).toString()];
+"#,
+        )
+        .unwrap();
+
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            None,
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: script.clone(),
+                arg: parse_args([]),
+            },
+        ));
+        ctx.destroy_root().unwrap();
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![
+                JsValue::Bool(true),
+                JsValue::String("[null,null]".to_string())
+            ])
+        );
+    }
+}
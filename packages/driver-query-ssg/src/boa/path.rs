@@ -15,11 +15,11 @@ impl TryFromJs for JsPath {
             .to_std_string()
             .map_err(JsError::from_rust)?;
 
-        Ok(JsPath(
-            RelativePathBuf::from_path(".")
+        Ok(JsPath(driver_util::normalize_path(
+            &RelativePathBuf::from_path(".")
                 .map_err(JsError::from_rust)?
                 .join_normalized(RelativePath::new(&path))
                 .to_path("."),
-        ))
+        )))
     }
 }
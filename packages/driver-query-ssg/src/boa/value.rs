@@ -16,6 +16,7 @@ pub enum JsValue {
     Null,
     Bool(bool),
     Int(i32),
+    Float(Float),
     String(String),
     Array(Vec<JsValue>),
     Object(BTreeMap<String, JsValue>),
@@ -23,6 +24,61 @@ pub enum JsValue {
     Image(JsImage),
 }
 
+/// A number that isn't an integer. All javascript numbers are doubles, but the ones that are also
+/// integers always become [`JsValue::Int`], so each number has exactly one representation.
+///
+/// Compares & hashes by bit pattern, with every NaN counting as the same NaN, so that it can be
+/// part of a key without breaking caching.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Float(f64);
+
+impl Float {
+    pub fn new(f: f64) -> Self {
+        Self(if f.is_nan() { f64::NAN } else { f })
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl std::hash::Hash for Float {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_le_bytes().hash(state);
+    }
+}
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Spelled the way javascript spells them
+        match self.0 {
+            f64::INFINITY => f.write_str("Infinity"),
+            f64::NEG_INFINITY => f.write_str("-Infinity"),
+            n => std::fmt::Display::fmt(&n, f),
+        }
+    }
+}
+
 impl TryFromJs for JsValue {
     fn try_from_js(value: &boa_engine::JsValue, js_ctx: &mut Context) -> JsResult<Self> {
         match value.variant() {
@@ -37,9 +93,7 @@ impl TryFromJs for JsValue {
                 if (i as f64) == f {
                     Ok(Self::Int(i))
                 } else {
-                    Err(JsNativeError::typ()
-                        .with_message(format!("cannot serialize float {f}"))
-                        .into())
+                    Ok(Self::Float(Float::new(f)))
                 }
             }
             boa_engine::JsVariant::Integer32(i) => Ok(Self::Int(i)),
@@ -83,6 +137,22 @@ impl TryFromJs for JsValue {
     }
 }
 
+/// Queries with several named outputs show up in javascript as a plain object, with one store
+/// object per name.
+impl From<driver_util::NamedBlobs> for JsValue {
+    fn from(outputs: driver_util::NamedBlobs) -> Self {
+        Self::Object(
+            outputs
+                .iter()
+                .map(|(name, blob)| {
+                    let blob = JsBlob { blob: blob.clone() };
+                    (name.to_string(), JsValue::Store(blob))
+                })
+                .collect(),
+        )
+    }
+}
+
 impl TryIntoJs for JsValue {
     fn try_into_js(&self, js_ctx: &mut Context) -> JsResult<boa_engine::JsValue> {
         match self {
@@ -90,6 +160,7 @@ impl TryIntoJs for JsValue {
             JsValue::Null => Ok(boa_engine::JsValue::null()),
             JsValue::Bool(b) => b.try_into_js(js_ctx),
             JsValue::Int(i) => i.try_into_js(js_ctx),
+            JsValue::Float(n) => Ok(boa_engine::JsValue::new(n.get())),
             JsValue::String(s) => s.try_into_js(js_ctx),
             JsValue::Array(values) => values.try_into_js(js_ctx),
             JsValue::Store(js_blob) => js_blob.try_into_js(js_ctx),
@@ -113,6 +184,7 @@ impl std::fmt::Display for JsValue {
             JsValue::Null => f.write_str("null"),
             JsValue::Bool(b) => f.write_str(if *b { "true" } else { "false" }),
             JsValue::Int(i) => std::fmt::Display::fmt(i, f),
+            JsValue::Float(n) => std::fmt::Display::fmt(n, f),
             JsValue::String(s) => write!(f, "\"{}\"", s),
             JsValue::Array(vs) => {
                 f.write_str("[")?;
@@ -153,6 +225,7 @@ impl driver_util::BlobTrace for JsValue {
             JsValue::Null => mk_box(std::iter::empty()),
             JsValue::Bool(_) => mk_box(std::iter::empty()),
             JsValue::Int(_) => mk_box(std::iter::empty()),
+            JsValue::Float(_) => mk_box(std::iter::empty()),
             JsValue::String(_) => mk_box(std::iter::empty()),
             JsValue::Array(js_values) => mk_box(js_values.trace()),
             JsValue::Object(btree_map) => mk_box(btree_map.trace()),
@@ -161,3 +234,29 @@ impl driver_util::BlobTrace for JsValue {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn floats_round_trip() {
+        let js_ctx = &mut Context::default();
+        for n in [3.14, -0.5, f64::INFINITY, 1e300] {
+            let value = JsValue::Float(Float::new(n));
+            let js = value.try_into_js(js_ctx).unwrap();
+            assert_eq!(JsValue::try_from_js(&js, js_ctx).unwrap(), value);
+        }
+        assert_eq!(JsValue::Float(Float::new(3.14)).to_string(), "3.14");
+        // Integers stay integers
+        let js = boa_engine::JsValue::new(2.0);
+        assert_eq!(JsValue::try_from_js(&js, js_ctx).unwrap(), JsValue::Int(2));
+    }
+
+    #[test]
+    fn nans_are_all_the_same() {
+        let other_nan = f64::from_bits(f64::NAN.to_bits() ^ 1);
+        assert!(other_nan.is_nan());
+        assert_eq!(Float::new(other_nan), Float::new(f64::NAN));
+    }
+}
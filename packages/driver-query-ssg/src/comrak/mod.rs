@@ -2,12 +2,115 @@ use driver_engine::Blob;
 
 driver_engine::key!(
     #[input=|_| false]
-    struct MarkdownToHtml(pub Blob);
+    struct MarkdownToHtml {
+        pub input: Blob,
+        pub options: MarkdownOptions,
+    }
+);
+driver_engine::blob_trace!(MarkdownToHtml => { input });
+
+impl MarkdownToHtml {
+    /// Renders `input` with the default options.
+    pub fn new(input: Blob) -> Self {
+        Self {
+            input,
+            options: MarkdownOptions::default(),
+        }
+    }
+}
+
+macro_rules! markdown_options {
+    ($( $(#[$meta:meta])* $name:ident = $default:literal ),* $(,)?) => {
+        /// The markdown features that scripts can turn on & off. Part of the key, so changing any
+        /// of them re-renders everything that used them.
+        #[derive(
+            Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize,
+        )]
+        pub struct MarkdownOptions {
+            $( $(#[$meta])* pub $name: bool, )*
+        }
+
+        impl Default for MarkdownOptions {
+            fn default() -> Self {
+                Self { $( $name: $default, )* }
+            }
+        }
+
+        impl MarkdownOptions {
+            /// Sets an option by name, erroring if there isn't one with that name.
+            pub fn set(&mut self, name: &str, value: bool) -> driver_util::Result<()> {
+                match name {
+                    $( stringify!($name) => self.$name = value, )*
+                    _ => {
+                        return Err(driver_util::Error::new(&format!(
+                            "unknown markdown option \"{name}\" (expected one of: {})",
+                            [$( stringify!($name) ),*].join(", ")
+                        )));
+                    }
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+markdown_options!(
+    strikethrough = true,
+    table = true,
+    autolink = false,
+    tasklist = true,
+    superscript = false,
+    subscript = false,
+    footnotes = true,
+    math_dollars = true,
+    shortcodes = false,
+    underline = false,
+    spoiler = true,
+    subtext = true,
+    highlight = true,
+    block_directive = true,
+    /// Turns straight quotes into curly ones, `--` into en dashes, etc.
+    smart = false,
+    /// Renders every newline inside a paragraph as a `<br>`.
+    hardbreaks = false,
 );
-driver_engine::blob_trace!(MarkdownToHtml => (0));
+
+impl MarkdownOptions {
+    fn comrak_options(&self) -> comrak::Options<'static> {
+        comrak::Options {
+            extension: comrak::options::Extension::builder()
+                .strikethrough(self.strikethrough)
+                .table(self.table)
+                .autolink(self.autolink)
+                .tasklist(self.tasklist)
+                .header_id_prefix("heading-".to_string())
+                .superscript(self.superscript)
+                .subscript(self.subscript)
+                .footnotes(self.footnotes)
+                .math_dollars(self.math_dollars)
+                .shortcodes(self.shortcodes)
+                .underline(self.underline)
+                .spoiler(self.spoiler)
+                .subtext(self.subtext)
+                .highlight(self.highlight)
+                .block_directive(self.block_directive)
+                .build(),
+            parse: comrak::options::Parse::builder()
+                .smart(self.smart)
+                .tasklist_in_table(true)
+                .ignore_setext(true)
+                .build(),
+            render: comrak::options::Render::builder()
+                .hardbreaks(self.hardbreaks)
+                .r#unsafe(true)
+                .escape(false)
+                .tasklist_classes(true)
+                .build(),
+        }
+    }
+}
 
 struct Options {
-    comrak_options: comrak::Options<'static>,
     comrak_plugins: comrak::options::Plugins<'static>,
     katex_ctx: katex::KatexContext,
     katex_settings: katex::Settings,
@@ -23,36 +126,6 @@ impl Default for Options {
         }
 
         Self {
-            comrak_options: comrak::Options {
-                extension: comrak::options::Extension::builder()
-                    .strikethrough(true)
-                    .table(true)
-                    .autolink(false)
-                    .tasklist(true)
-                    .header_id_prefix("heading-".to_string())
-                    .superscript(false)
-                    .subscript(false)
-                    .footnotes(true)
-                    .math_dollars(true)
-                    .shortcodes(false)
-                    .underline(false)
-                    .spoiler(true)
-                    .subtext(true)
-                    .highlight(true)
-                    .block_directive(true)
-                    .build(),
-                parse: comrak::options::Parse::builder()
-                    .smart(false)
-                    .tasklist_in_table(true)
-                    .ignore_setext(true)
-                    .build(),
-                render: comrak::options::Render::builder()
-                    .hardbreaks(false)
-                    .r#unsafe(true)
-                    .escape(false)
-                    .tasklist_classes(true)
-                    .build(),
-            },
             comrak_plugins: comrak::options::Plugins::builder()
                 .render(comrak::options::RenderPlugins {
                     codefence_renderers: Default::default(),
@@ -112,7 +185,8 @@ impl comrak::adapters::SyntaxHighlighterAdapter for ArboriumHighlighter {
 }
 
 driver_engine::producer!(MarkdownToHtml(self, ctx) -> driver_util::Result<Blob> {
-    let contents = ctx.load_string(&self.0)?;
+    let contents = ctx.load_string(&self.input)?;
+    let comrak_options = self.options.comrak_options();
 
     thread_local! {
         static OPTIONS: Options = Options::default();
@@ -120,7 +194,7 @@ driver_engine::producer!(MarkdownToHtml(self, ctx) -> driver_util::Result<Blob>
 
     let output = OPTIONS.with(|options| -> driver_util::Result<_> {
         let arena = comrak::Arena::new();
-        let root = comrak::parse_document(&arena, &contents, &options.comrak_options);
+        let root = comrak::parse_document(&arena, &contents, &comrak_options);
 
         for node in root.descendants() {
             let node_value = &mut node.data_mut().value;
@@ -142,7 +216,7 @@ driver_engine::producer!(MarkdownToHtml(self, ctx) -> driver_util::Result<Blob>
         let mut out = String::new();
         comrak::html::format_document_with_plugins(
             root,
-            &options.comrak_options,
+            &comrak_options,
             &mut out,
             &options.comrak_plugins,
         )?;
@@ -161,6 +235,27 @@ driver_engine::producer!(MarkdownToHtml(self, ctx) -> driver_util::Result<Blob>
 
 impl std::fmt::Display for MarkdownToHtml {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "markdown_to_html({})", self.0)
+        write!(f, "markdown_to_html({}", self.input)?;
+        if self.options != MarkdownOptions::default() {
+            write!(f, ", {:?}", self.options)?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sets_options_by_name() {
+        let mut options = MarkdownOptions::default();
+        options.set("autolink", true).unwrap();
+        options.set("table", false).unwrap();
+        assert!(options.autolink);
+        assert!(!options.table);
+
+        let err = options.set("tables", false).unwrap_err().to_string();
+        assert!(err.contains("unknown markdown option \"tables\""), "{err}");
     }
 }
@@ -0,0 +1,57 @@
+//! Joining blobs end-to-end, for things like bundling CSS partials into one file. Works on bytes,
+//! so it's just as happy with things that aren't text.
+
+use driver_engine::Blob;
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct Concat(pub Vec<Blob>);
+);
+driver_engine::blob_trace!(Concat => (0));
+
+driver_engine::producer!(Concat(self, ctx) -> driver_util::Result<Blob> {
+    let mut contents = Vec::new();
+    for blob in &self.0 {
+        contents.extend(ctx.load_bytes(blob)?);
+    }
+    ctx.store(contents)
+});
+
+impl std::fmt::Display for Concat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("concat([")?;
+        for (i, blob) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{blob}")?;
+        }
+        f.write_str("])")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::QueryContext;
+
+    #[test]
+    fn joins_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+
+        let parts = [b"a { }\n".to_vec(), vec![0xff, 0x00], b"b { }\n".to_vec()]
+            .map(|part| ctx.store(part).unwrap());
+        let joined = block_on(query(&ctx, Concat(parts.to_vec()))).unwrap();
+        assert_eq!(ctx.load_bytes(&joined).unwrap(), b"a { }\n\xff\x00b { }\n");
+
+        let empty = block_on(query(&ctx, Concat(Vec::new()))).unwrap();
+        assert_eq!(ctx.load_bytes(&empty).unwrap(), b"");
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Reading variables out of a `.env` file (for local config & secrets that shouldn't be committed
+//! alongside the build scripts), or out of the process environment.
+
+use std::path::PathBuf;
+
+use driver_engine::{Options, query};
+use driver_query_fs::ReadFile;
+use driver_util::Secret;
+
+driver_engine::key!(
+    #[input=|_| true]
+    struct DotEnv {
+        /// The `.env` file to read.
+        pub path: PathBuf,
+        pub name: String,
+    }
+);
+driver_engine::no_blobs!(DotEnv);
+
+impl DotEnv {
+    /// Looks up `name` in whatever `.env` file is configured in the options, if there is one.
+    pub fn new(name: String, options: &Options) -> Option<Self> {
+        let path = options.env_file.clone()?;
+        Some(Self { path, name })
+    }
+}
+
+// This is an input (so that its value is never trusted from the cache; see `Secret`), but still
+// goes through `ReadFile`, so the `.env` file shows up in depfiles & gets watched like any other.
+driver_engine::producer!(DotEnv(self, ctx) as (crate::QueryKey) -> driver_util::Result<Option<Secret>> {
+    let blob = query(ctx, ReadFile(self.path.clone())).await?;
+    let contents = ctx.load_bytes(&blob)?;
+    for item in dotenvy::from_read_iter(contents.as_slice()) {
+        let (name, value) = item.map_err(|e| {
+            driver_util::Error::new(&format!("{}: {e}", self.path.display()))
+        })?;
+        if name == self.name {
+            return Ok(Some(Secret::new(value)));
+        }
+    }
+    Ok(None)
+});
+
+impl std::fmt::Display for DotEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dotenv(\"{}\", {})", self.path.display(), self.name)
+    }
+}
+
+driver_engine::key!(
+    #[input=|_| true]
+    struct EnvVar(pub String);
+);
+driver_engine::no_blobs!(EnvVar);
+
+// Also an input holding a `Secret`, since API keys live in the environment just as often.
+driver_engine::producer!(EnvVar(self, _ctx) -> driver_util::Result<Option<Secret>> {
+    match std::env::var(&self.0) {
+        Ok(value) => Ok(Some(Secret::new(value))),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(driver_util::Error::new(&format!("{}: {e}", self.0))),
+    }
+});
+
+impl std::fmt::Display for EnvVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "env_var({})", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use crate::QueryContext;
+    use crate::boa::{JsValue, RunJs, parse_args};
+
+    #[test]
+    fn script_reads_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        let script = dir.path().join("build.js");
+        std::fs::write(&env_file, "# config\nGREETING=hello\nOTHER=x\n").unwrap();
+        std::fs::write(
+            &script,
+            r#"import { env } from "driver";
+export default `${await env("GREETING")} ${await env("MISSING")}`;
+"#,
+        )
+        .unwrap();
+
+        let build = || {
+            let mut options = driver_engine::Options::with_base_dir(&dir.path().join(".driver"));
+            options.env_file = Some(env_file.clone());
+            let ctx = QueryContext::create_root(options, None);
+            let key = RunJs {
+                file: script.clone(),
+                arg: parse_args([]),
+            };
+            let output = block_on(query(&ctx, key));
+            ctx.destroy_root().unwrap();
+            output.export.unwrap()
+        };
+
+        assert_eq!(build(), JsValue::String("hello undefined".to_string()));
+        // Nothing changed, so the cached (secret-free) output is fine
+        assert_eq!(build(), JsValue::String("hello undefined".to_string()));
+
+        std::fs::write(&env_file, "GREETING=bye\n").unwrap();
+        assert_eq!(build(), JsValue::String("bye undefined".to_string()));
+    }
+
+    #[test]
+    fn falls_back_to_process_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        let script = dir.path().join("build.js");
+        std::fs::write(&env_file, "FROM_FILE=file\n").unwrap();
+        // Cargo sets this for every test it runs, so there's no need to touch the environment
+        std::fs::write(
+            &script,
+            r#"import { env } from "driver";
+export default [await env("FROM_FILE"), await env("CARGO_PKG_NAME"), await env("DRIVER_UNSET_FOR_TESTS")];
+"#,
+        )
+        .unwrap();
+
+        let mut options = driver_engine::Options::with_base_dir(&dir.path().join(".driver"));
+        options.env_file = Some(env_file.clone());
+        let ctx = QueryContext::create_root(options, None);
+        let key = RunJs {
+            file: script.clone(),
+            arg: parse_args([]),
+        };
+        let output = block_on(query(&ctx, key));
+        ctx.destroy_root().unwrap();
+
+        assert_eq!(
+            output.export.unwrap(),
+            JsValue::Array(vec![
+                JsValue::String("file".to_string()),
+                JsValue::String(env!("CARGO_PKG_NAME").to_string()),
+                JsValue::Undefined,
+            ])
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Splitting the metadata block off the top of a markdown file, like
+//!
+//! ```markdown
+//! ---
+//! title: Hello
+//! tags: [intro]
+//! ---
+//! # Hello!
+//! ```
+//!
+//! `---` fences mean YAML, and `+++` fences mean TOML, same as most other static site generators.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item, Table};
+use yaml_rust::{Yaml, YamlLoader};
+
+use driver_engine::Blob;
+
+use crate::boa::{Float, JsValue};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct ParseFrontMatter(pub Blob);
+);
+driver_engine::blob_trace!(ParseFrontMatter => (0));
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrontMatter {
+    /// Always an object. Empty if there wasn't any front matter.
+    pub data: JsValue,
+    /// Everything after the front matter. The same blob that went in if there wasn't any.
+    pub body: Blob,
+}
+driver_engine::blob_trace!(FrontMatter => { data, body });
+
+driver_engine::producer!(ParseFrontMatter(self, ctx) -> driver_util::Result<FrontMatter> {
+    let contents = ctx.load_string(&self.0)?;
+    let (data, body) = match split(&contents) {
+        None => {
+            return Ok(FrontMatter {
+                data: JsValue::Object(BTreeMap::new()),
+                body: self.0.clone(),
+            });
+        }
+        Some((Format::Yaml, front, body)) => (from_yaml(front)?, body),
+        Some((Format::Toml, front, body)) => (from_toml(front)?, body),
+    };
+    Ok(FrontMatter {
+        data,
+        body: ctx.store(body.as_bytes().to_vec())?,
+    })
+});
+
+impl std::fmt::Display for ParseFrontMatter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse_front_matter({})", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Format {
+    Yaml,
+    Toml,
+}
+
+/// Splits `contents` into its front matter & the body after it. The opening fence has to be the
+/// very first line; if it never gets closed, there isn't any front matter.
+fn split(contents: &str) -> Option<(Format, &str, &str)> {
+    let mut lines = contents.split_inclusive('\n');
+    let first = lines.next()?;
+    let (format, fence) = match first.trim_end() {
+        "---" => (Format::Yaml, "---"),
+        "+++" => (Format::Toml, "+++"),
+        _ => return None,
+    };
+
+    let start = first.len();
+    let mut end = start;
+    for line in lines {
+        if line.trim_end() == fence {
+            return Some((format, &contents[start..end], &contents[end + line.len()..]));
+        }
+        end += line.len();
+    }
+    None
+}
+
+/// Like [`crate::json::from_json`], numbers become [`JsValue::Int`] whenever they fit.
+fn from_number(n: i64) -> JsValue {
+    match i32::try_from(n) {
+        Ok(i) => JsValue::Int(i),
+        Err(_) => JsValue::Float(Float::new(n as f64)),
+    }
+}
+
+fn from_yaml(front: &str) -> driver_util::Result<JsValue> {
+    let mut documents = YamlLoader::load_from_str(front)?;
+    if documents.is_empty() {
+        return Ok(JsValue::Object(BTreeMap::new()));
+    }
+    match yaml_to_js(documents.swap_remove(0))? {
+        object @ JsValue::Object(_) => Ok(object),
+        _ => Err(driver_util::Error::new(
+            "YAML front matter must be a mapping",
+        )),
+    }
+}
+
+fn yaml_to_js(yaml: Yaml) -> driver_util::Result<JsValue> {
+    Ok(match yaml {
+        Yaml::Null => JsValue::Null,
+        Yaml::Boolean(b) => JsValue::Bool(b),
+        Yaml::Integer(n) => from_number(n),
+        // Handles `.inf` & friends, which Rust's float parsing doesn't
+        real @ Yaml::Real(_) => JsValue::Float(Float::new(real.as_f64().unwrap_or(f64::NAN))),
+        Yaml::String(s) => JsValue::String(s),
+        Yaml::Array(values) => JsValue::Array(
+            values
+                .into_iter()
+                .map(yaml_to_js)
+                .collect::<driver_util::Result<_>>()?,
+        ),
+        Yaml::Hash(map) => JsValue::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Yaml::String(s) => s,
+                        Yaml::Integer(n) => n.to_string(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        other => {
+                            return Err(driver_util::Error::new(&format!(
+                                "unsupported key in front matter: {other:?}"
+                            )));
+                        }
+                    };
+                    Ok((key, yaml_to_js(value)?))
+                })
+                .collect::<driver_util::Result<_>>()?,
+        ),
+        Yaml::Alias(_) | Yaml::BadValue => {
+            return Err(driver_util::Error::new("unsupported value in front matter"));
+        }
+    })
+}
+
+fn from_toml(front: &str) -> driver_util::Result<JsValue> {
+    let document: DocumentMut = front.parse()?;
+    Ok(toml_table_to_js(document.as_table()))
+}
+
+fn toml_table_to_js(table: &Table) -> JsValue {
+    JsValue::Object(
+        table
+            .iter()
+            .map(|(key, item)| (key.to_string(), toml_item_to_js(item)))
+            .collect(),
+    )
+}
+
+fn toml_item_to_js(item: &Item) -> JsValue {
+    match item {
+        Item::None => JsValue::Undefined,
+        Item::Value(value) => toml_value_to_js(value),
+        Item::Table(table) => toml_table_to_js(table),
+        Item::ArrayOfTables(tables) => {
+            JsValue::Array(tables.iter().map(toml_table_to_js).collect())
+        }
+    }
+}
+
+fn toml_value_to_js(value: &toml_edit::Value) -> JsValue {
+    match value {
+        toml_edit::Value::String(s) => JsValue::String(s.value().clone()),
+        toml_edit::Value::Integer(n) => from_number(*n.value()),
+        toml_edit::Value::Float(n) => JsValue::Float(Float::new(*n.value())),
+        toml_edit::Value::Boolean(b) => JsValue::Bool(*b.value()),
+        // Same as how YAML dates come through
+        toml_edit::Value::Datetime(d) => JsValue::String(d.value().to_string()),
+        toml_edit::Value::Array(values) => {
+            JsValue::Array(values.iter().map(toml_value_to_js).collect())
+        }
+        toml_edit::Value::InlineTable(table) => JsValue::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), toml_value_to_js(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::QueryContext;
+
+    #[test]
+    fn splits_fences() {
+        assert_eq!(
+            split("---\ntitle: a\n---\nbody\n"),
+            Some((Format::Yaml, "title: a\n", "body\n"))
+        );
+        assert_eq!(
+            split("+++\r\ntitle = 'a'\r\n+++\r\n"),
+            Some((Format::Toml, "title = 'a'\r\n", ""))
+        );
+        // Has to be at the very start, and has to be closed
+        assert_eq!(split("\n---\ntitle: a\n---\n"), None);
+        assert_eq!(split("---\ntitle: a\n"), None);
+        assert_eq!(
+            split("--- \ntitle: a\n---"),
+            Some((Format::Yaml, "title: a\n", ""))
+        );
+    }
+
+    #[test]
+    fn parses_yaml_and_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+        let parse = |contents: &str| {
+            let blob = ctx.store(contents.as_bytes().to_vec()).unwrap();
+            let output = block_on(query(&ctx, ParseFrontMatter(blob.clone()))).unwrap();
+            (
+                output.data,
+                ctx.load_string(&output.body).unwrap(),
+                output.body == blob,
+            )
+        };
+        let expected = JsValue::Object(BTreeMap::from([
+            ("title".to_string(), JsValue::String("Hello".to_string())),
+            ("draft".to_string(), JsValue::Bool(false)),
+            (
+                "tags".to_string(),
+                JsValue::Array(vec![
+                    JsValue::String("intro".to_string()),
+                    JsValue::String("meta".to_string()),
+                ]),
+            ),
+            ("weight".to_string(), JsValue::Int(3)),
+        ]));
+
+        let yaml = "---\ntitle: Hello\ndraft: false\ntags: [intro, meta]\nweight: 3\n---\n# Hi\n";
+        assert_eq!(parse(yaml), (expected.clone(), "# Hi\n".to_string(), false));
+        let toml = "+++\ntitle = \"Hello\"\ndraft = false\ntags = [\"intro\", \"meta\"]\nweight = 3\n+++\n# Hi\n";
+        assert_eq!(parse(toml), (expected, "# Hi\n".to_string(), false));
+
+        // Nothing to split off, so the body is exactly what went in
+        let empty = JsValue::Object(BTreeMap::new());
+        assert_eq!(parse("# Hi\n"), (empty, "# Hi\n".to_string(), true));
+
+        let err = block_on(query(
+            &ctx,
+            ParseFrontMatter(ctx.store(b"---\n- a\n---\n".to_vec()).unwrap()),
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("must be a mapping"), "{err}");
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+//! Converting between JSON and [`JsValue`]s, so build scripts can read data files without having
+//! to re-parse them on every run.
+
+use std::collections::BTreeMap;
+
+use driver_engine::Blob;
+
+use crate::boa::{Float, JsValue};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct ParseJson(pub Blob);
+);
+driver_engine::blob_trace!(ParseJson => (0));
+
+driver_engine::producer!(ParseJson(self, ctx) -> driver_util::Result<JsValue> {
+    let contents = ctx.load_bytes(&self.0)?;
+    let value = serde_json::from_slice(&contents)?;
+    Ok(from_json(value))
+});
+
+impl std::fmt::Display for ParseJson {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse_json({})", self.0)
+    }
+}
+
+/// Numbers become [`JsValue::Int`] when they fit, and [`JsValue::Float`] otherwise, same as they
+/// would coming from javascript.
+pub fn from_json(value: serde_json::Value) -> JsValue {
+    match value {
+        serde_json::Value::Null => JsValue::Null,
+        serde_json::Value::Bool(b) => JsValue::Bool(b),
+        serde_json::Value::Number(n) => match n.as_i64().map(i32::try_from) {
+            Some(Ok(i)) => JsValue::Int(i),
+            _ => JsValue::Float(Float::new(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        serde_json::Value::String(s) => JsValue::String(s),
+        serde_json::Value::Array(values) => {
+            JsValue::Array(values.into_iter().map(from_json).collect())
+        }
+        serde_json::Value::Object(map) => JsValue::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, from_json(v)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
+/// What [`to_json`] does with `NaN` & `Infinity`, which JSON can't represent.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum NonFinite {
+    /// Error out, so a bad calculation can't quietly end up in the output. The default.
+    #[default]
+    Error,
+    /// Write `null` instead, same as `JSON.stringify()`.
+    Null,
+}
+
+impl NonFinite {
+    /// Parses the name scripts use for a policy, erroring if there isn't one with that name.
+    pub fn from_name(name: &str) -> driver_util::Result<Self> {
+        match name {
+            "error" => Ok(Self::Error),
+            "null" => Ok(Self::Null),
+            _ => Err(driver_util::Error::new(&format!(
+                "unknown non_finite policy \"{name}\" (expected one of: error, null)"
+            ))),
+        }
+    }
+}
+
+/// Follows `JSON.stringify()`: `undefined` is left out of objects, and becomes `null` everywhere
+/// else. `NaN` & `Infinity` are handled according to `non_finite`. Store objects & images are
+/// always an error, since they only mean something inside this build.
+pub fn to_json(value: &JsValue, non_finite: NonFinite) -> driver_util::Result<serde_json::Value> {
+    Ok(match value {
+        JsValue::Undefined | JsValue::Null => serde_json::Value::Null,
+        JsValue::Bool(b) => (*b).into(),
+        JsValue::Int(i) => (*i).into(),
+        JsValue::Float(n) => match (serde_json::Number::from_f64(n.get()), non_finite) {
+            (Some(number), _) => number.into(),
+            (None, NonFinite::Null) => serde_json::Value::Null,
+            (None, NonFinite::Error) => {
+                return Err(driver_util::Error::new(&format!("{n} is not valid JSON")));
+            }
+        },
+        JsValue::String(s) => s.clone().into(),
+        JsValue::Array(values) => values
+            .iter()
+            .map(|v| to_json(v, non_finite))
+            .collect::<driver_util::Result<serde_json::Value>>()?,
+        JsValue::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !matches!(v, JsValue::Undefined))
+            .map(|(k, v)| Ok((k.clone(), to_json(v, non_finite)?)))
+            .collect::<driver_util::Result<serde_json::Value>>()?,
+        JsValue::Store(_) | JsValue::Image(_) => {
+            return Err(driver_util::Error::new(&format!(
+                "cannot convert {value} to JSON"
+            )));
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let json = serde_json::json!({
+            "title": "hi",
+            "draft": false,
+            "tags": ["a", "b"],
+            "weight": 3,
+            "ratio": 0.5,
+            "big": 1e10,
+            "nothing": null,
+        });
+        let value = from_json(json.clone());
+        let JsValue::Object(map) = &value else {
+            panic!("expected object, got {value}");
+        };
+        assert_eq!(map["weight"], JsValue::Int(3));
+        assert_eq!(map["ratio"], JsValue::Float(Float::new(0.5)));
+        assert_eq!(map["big"], JsValue::Float(Float::new(1e10)));
+        assert_eq!(to_json(&value, NonFinite::default()).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_non_finite_by_default() {
+        let value = JsValue::Array(vec![JsValue::Float(Float::new(f64::NAN))]);
+        let err = to_json(&value, NonFinite::default()).unwrap_err();
+        assert!(err.to_string().contains("not valid JSON"), "{err}");
+        let value = JsValue::Float(Float::new(f64::INFINITY));
+        assert!(to_json(&value, NonFinite::Error).is_err());
+        // But leaves out undefined like JSON.stringify()
+        let value = JsValue::Object([("x".to_string(), JsValue::Undefined)].into());
+        assert_eq!(
+            to_json(&value, NonFinite::default()).unwrap(),
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn writes_non_finite_as_null() {
+        let value = JsValue::Object(
+            [
+                ("nan".to_string(), JsValue::Float(Float::new(f64::NAN))),
+                (
+                    "inf".to_string(),
+                    JsValue::Float(Float::new(f64::NEG_INFINITY)),
+                ),
+                ("half".to_string(), JsValue::Float(Float::new(0.5))),
+            ]
+            .into(),
+        );
+        assert_eq!(
+            to_json(&value, NonFinite::Null).unwrap(),
+            serde_json::json!({ "nan": null, "inf": null, "half": 0.5 })
+        );
+        assert_eq!(NonFinite::from_name("null").unwrap(), NonFinite::Null);
+        assert!(NonFinite::from_name("zero").is_err());
+    }
+}
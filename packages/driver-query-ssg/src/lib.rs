@@ -1,7 +1,20 @@
 pub mod boa;
 pub mod comrak;
+pub mod concat;
+pub mod env;
+pub mod front_matter;
+pub mod json;
+pub mod links;
+pub mod manifest;
 pub mod minify_html;
+pub mod post_process;
+pub mod profile;
+pub mod reading_time;
+pub mod scss;
+pub mod sitemap;
+pub mod stream;
 pub mod tera;
+pub mod wasm;
 pub mod zune;
 
 mod query;
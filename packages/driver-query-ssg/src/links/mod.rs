@@ -0,0 +1,184 @@
+//! Whole-site link checking: pull every internal link out of the HTML we're about to write, and
+//! make sure each one points at something we're actually writing.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use futures_concurrency::future::Join as _;
+
+use driver_engine::{Blob, query};
+
+use crate::{QueryContext, WriteOutput};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct ExtractLinks(pub Blob);
+);
+driver_engine::blob_trace!(ExtractLinks => (0));
+
+driver_engine::producer!(ExtractLinks(self, ctx) -> driver_util::Result<Vec<String>> {
+    let contents = ctx.load_string(&self.0)?;
+    Ok(extract_links(&contents))
+});
+
+impl std::fmt::Display for ExtractLinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "extract_links({})", self.0)
+    }
+}
+
+/// Finds the values of all `href` and `src` attributes that look like they point somewhere on the
+/// same site. Not a real HTML parser, but good enough for what we generate.
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for attr in ["href=", "src="] {
+        let mut rest = html;
+        while let Some(i) = rest.find(attr) {
+            let before = rest[..i].chars().next_back();
+            rest = &rest[i + attr.len()..];
+            // Make sure we matched a whole attribute name, not e.g. `data-href=`
+            if !matches!(before, Some(c) if c.is_whitespace()) {
+                continue;
+            }
+            let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                continue;
+            };
+            rest = &rest[1..];
+            let Some(end) = rest.find(quote) else {
+                break;
+            };
+            let link = &rest[..end];
+            rest = &rest[end + 1..];
+            if is_internal(link) {
+                links.push(link.to_string());
+            }
+        }
+    }
+    links
+}
+
+fn is_internal(link: &str) -> bool {
+    !(link.is_empty() || link.starts_with('#') || link.starts_with("//") || link.contains(':'))
+}
+
+/// Figures out which output file a link on `page` refers to.
+fn resolve_link(page: &Path, link: &str) -> PathBuf {
+    let link = link.split(['#', '?']).next().unwrap_or_default();
+    let path = match link.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page.parent().unwrap_or(Path::new("")).join(link),
+    };
+    let path = match driver_util::normalize_path(&path) {
+        root if root == Path::new(".") => PathBuf::new(),
+        path => path,
+    };
+    if link.is_empty() || link.ends_with('/') {
+        path.join("index.html")
+    } else {
+        path
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DanglingLink {
+    /// The output page the link is on.
+    pub page: PathBuf,
+    /// The link, exactly as written.
+    pub link: String,
+}
+
+impl std::fmt::Display for DanglingLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: broken link \"{}\"", self.page.display(), self.link)
+    }
+}
+
+/// Checks every internal link in every HTML file in `outputs`, returning the ones that don't point
+/// at another output. Links to `foo` are also satisfied by `foo/index.html`.
+pub async fn check_links(
+    ctx: &QueryContext,
+    outputs: &WriteOutput,
+) -> driver_util::Result<Vec<DanglingLink>> {
+    let paths: BTreeSet<&Path> = outputs.iter().map(|(path, _)| path.as_path()).collect();
+
+    let pages = outputs
+        .iter()
+        .filter(|(path, _)| path.extension().is_some_and(|ext| ext == "html"))
+        .map(|(path, blob)| async move {
+            let links = query(ctx, ExtractLinks(blob.clone())).await?;
+            driver_util::Result::Ok((path, links))
+        })
+        .collect::<Vec<_>>()
+        .join()
+        .await;
+
+    let mut dangling = Vec::new();
+    for page in pages {
+        let (page, links) = page?;
+        for link in links {
+            let target = resolve_link(page, &link);
+            if !paths.contains(target.as_path())
+                && !paths.contains(target.join("index.html").as_path())
+            {
+                dangling.push(DanglingLink {
+                    page: page.clone(),
+                    link,
+                });
+            }
+        }
+    }
+    dangling.sort();
+    Ok(dangling)
+}
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    #[test]
+    fn extracts_internal_links() {
+        let html = r##"<a href="/about/">a</a> <a href='https://example.com'>b</a>
+            <img src="img/cat.png"> <a href="#top">c</a> <div data-href="/nope"></div>"##;
+        assert_eq!(extract_links(html), ["/about/", "img/cat.png"]);
+    }
+
+    #[test]
+    fn resolves() {
+        let page = Path::new("blog/post/index.html");
+        assert_eq!(resolve_link(page, "/"), PathBuf::from("index.html"));
+        assert_eq!(resolve_link(page, "../"), PathBuf::from("blog/index.html"));
+        assert_eq!(
+            resolve_link(page, "cat.png#x"),
+            PathBuf::from("blog/post/cat.png")
+        );
+    }
+
+    #[test]
+    fn reports_dangling() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+        let store = |s: &str| ctx.store(s.as_bytes().to_vec()).unwrap();
+
+        let mut outputs = WriteOutput::builder();
+        outputs.push(
+            "index.html".into(),
+            store(r#"<a href="/about/">ok</a> <a href="/missing.html">bad</a>"#),
+        );
+        outputs.push("about/index.html".into(), store("<p>hi</p>"));
+        let outputs = outputs.finalize();
+
+        let dangling = block_on(check_links(&ctx, &outputs)).unwrap();
+        assert_eq!(
+            dangling,
+            [DanglingLink {
+                page: "index.html".into(),
+                link: "/missing.html".into(),
+            }]
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+//! A record of every file a build wrote & what was in it, so two builds can be compared without
+//! having both of their outputs around (say, for deploy previews).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{QueryContext, WriteOutput};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Sorted by path, so the same outputs always make the same file.
+    pub files: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The sha256 of the file's contents, as hex.
+    pub hash: String,
+    /// In bytes.
+    pub size: u64,
+}
+
+impl Manifest {
+    pub fn from_outputs(ctx: &QueryContext, outputs: &WriteOutput) -> driver_util::Result<Self> {
+        let mut files = BTreeMap::new();
+        for (path, blob) in outputs.iter() {
+            // Outputs can be big, so don't load them all into memory just to count them
+            let size = std::io::copy(&mut ctx.open_blob(blob)?, &mut std::io::sink())?;
+            let hash = blob.to_hex();
+            files.insert(path.clone(), ManifestEntry { hash, size });
+        }
+        Ok(Self { files })
+    }
+
+    pub fn read(path: &Path) -> driver_util::Result<Self> {
+        let contents = std::fs::read(path)?;
+        serde_json::from_slice(&contents).map_err(|e| {
+            driver_util::Error::new(&format!("invalid manifest {}: {e}", path.display()))
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> driver_util::Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| driver_util::Error::new(&e.to_string()))?;
+        std::fs::write(path, contents + "\n")?;
+        Ok(())
+    }
+
+    /// Everything that's different in `self` compared to `old`.
+    pub fn diff(&self, old: &Manifest) -> ManifestDiff {
+        let mut diff = ManifestDiff::default();
+        for (path, new) in self.files.iter() {
+            match old.files.get(path) {
+                None => diff.added.push(FileChange {
+                    path: path.clone(),
+                    old_size: 0,
+                    new_size: new.size,
+                }),
+                Some(old) if old.hash != new.hash => diff.changed.push(FileChange {
+                    path: path.clone(),
+                    old_size: old.size,
+                    new_size: new.size,
+                }),
+                Some(_) => {}
+            }
+        }
+        for (path, old) in old.files.iter() {
+            if !self.files.contains_key(path) {
+                diff.removed.push(FileChange {
+                    path: path.clone(),
+                    old_size: old.size,
+                    new_size: 0,
+                });
+            }
+        }
+        diff
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDiff {
+    pub added: Vec<FileChange>,
+    pub removed: Vec<FileChange>,
+    pub changed: Vec<FileChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl FileChange {
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn to_json(&self) -> driver_util::Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| driver_util::Error::new(&e.to_string()))
+    }
+}
+
+impl std::fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (sign, changes) in [
+            ("+", &self.added),
+            ("-", &self.removed),
+            ("~", &self.changed),
+        ] {
+            for change in changes {
+                writeln!(
+                    f,
+                    "{sign} {} ({:+} bytes)",
+                    change.path.display(),
+                    change.size_delta()
+                )?;
+            }
+        }
+        let total: i64 = [&self.added, &self.removed, &self.changed]
+            .into_iter()
+            .flatten()
+            .map(FileChange::size_delta)
+            .sum();
+        write!(
+            f,
+            "{} added, {} removed, {} changed, {total:+} bytes",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn manifest(files: &[(&str, &str, u64)]) -> Manifest {
+        Manifest {
+            files: files
+                .iter()
+                .map(|(path, hash, size)| {
+                    let entry = ManifestEntry {
+                        hash: hash.to_string(),
+                        size: *size,
+                    };
+                    (PathBuf::from(path), entry)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn categorizes() {
+        let old = manifest(&[
+            ("index.html", "aa", 100),
+            ("old.html", "bb", 50),
+            ("style.css", "cc", 10),
+        ]);
+        let new = manifest(&[
+            ("index.html", "ab", 120),
+            ("new.html", "dd", 30),
+            ("style.css", "cc", 10),
+        ]);
+
+        let diff = new.diff(&old);
+        let paths = |changes: &[FileChange]| -> Vec<PathBuf> {
+            changes.iter().map(|c| c.path.clone()).collect()
+        };
+        assert_eq!(paths(&diff.added), [PathBuf::from("new.html")]);
+        assert_eq!(paths(&diff.removed), [PathBuf::from("old.html")]);
+        assert_eq!(paths(&diff.changed), [PathBuf::from("index.html")]);
+        assert_eq!(diff.changed[0].size_delta(), 20);
+        assert!(
+            diff.to_string()
+                .ends_with("1 added, 1 removed, 1 changed, +0 bytes")
+        );
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.json");
+        let manifest = manifest(&[("index.html", "aa", 100)]);
+        manifest.write(&path).unwrap();
+        assert_eq!(Manifest::read(&path).unwrap(), manifest);
+    }
+}
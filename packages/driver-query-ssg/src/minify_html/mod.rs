@@ -1,28 +1,295 @@
-use driver_engine::Blob;
+use driver_engine::{Blob, Options};
 
 driver_engine::key!(
     #[input=|_| false]
-    struct MinifyHtml(pub Blob);
+    struct MinifyHtml {
+        pub input: Blob,
+        /// Elements whose contents are passed through byte-for-byte. Part of the key so that
+        /// changing it re-minifies everything.
+        pub preserve_tags: Vec<String>,
+        pub config: MinifyConfig,
+    }
 );
-driver_engine::blob_trace!(MinifyHtml => (0));
+driver_engine::blob_trace!(MinifyHtml => { input });
+
+impl MinifyHtml {
+    /// Minifies `input`, preserving whatever tags are configured in the options.
+    pub fn new(input: Blob, options: &Options) -> Self {
+        Self {
+            input,
+            preserve_tags: options.minify_preserve_tags.clone(),
+            config: MinifyConfig::default(),
+        }
+    }
+}
+
+/// The parts of [`minify_html::Cfg`] that scripts can change. The defaults are what we've always
+/// used, so not passing any options gives exactly the same output as before.
+///
+/// Being part of [`MinifyHtml`] changed that key's serialized layout though, and postcard can't
+/// skip fields, so caches saved before this existed fail to restore & get rebuilt from scratch
+/// once.
+#[derive(
+    Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize,
+)]
+pub struct MinifyConfig {
+    pub keep_closing_tags: bool,
+    pub keep_comments: bool,
+    pub minify_css: bool,
+    pub minify_js: bool,
+}
+
+impl Default for MinifyConfig {
+    fn default() -> Self {
+        Self {
+            keep_closing_tags: true,
+            keep_comments: true,
+            minify_css: true,
+            minify_js: true,
+        }
+    }
+}
+
+impl MinifyConfig {
+    /// Sets an option by name, erroring if there isn't one with that name.
+    pub fn set(&mut self, name: &str, value: bool) -> driver_util::Result<()> {
+        match name {
+            "keep_closing_tags" => self.keep_closing_tags = value,
+            "keep_comments" => self.keep_comments = value,
+            "minify_css" => self.minify_css = value,
+            "minify_js" => self.minify_js = value,
+            _ => {
+                return Err(driver_util::Error::new(&format!(
+                    "unknown minify option \"{name}\" (expected one of: keep_closing_tags, keep_comments, minify_css, minify_js)"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
 
 driver_engine::producer!(MinifyHtml(self, ctx) -> driver_util::Result<Blob> {
-    let contents = ctx.load_string(&self.0)?;
+    let contents = ctx.load_string(&self.input)?;
+    let output = minify_preserving(&contents, &self.preserve_tags, &self.config)?;
+    let blob = ctx.store(output)?;
+    Ok(blob)
+});
+
+fn minify(contents: &str, config: &MinifyConfig) -> Vec<u8> {
     let cfg = minify_html::Cfg {
-        keep_closing_tags: true,
-        keep_comments: true,
+        keep_closing_tags: config.keep_closing_tags,
+        keep_comments: config.keep_comments,
         keep_html_and_head_opening_tags: true,
-        minify_css: true,
-        minify_js: true,
+        minify_css: config.minify_css,
+        minify_js: config.minify_js,
         ..Default::default()
     };
-    let output = minify_html::minify(contents.as_bytes(), &cfg);
-    let blob = ctx.store(output)?;
-    Ok(blob)
-});
+    minify_html::minify(contents.as_bytes(), &cfg)
+}
+
+/// Finds every `<tag ...>...</tag>` in `lower` (which MUST already be lowercase), returning the
+/// byte ranges of their contents in order. Only scans forward, so this is linear in the document.
+fn find_elements(lower: &str, tag: &str) -> Vec<(usize, usize)> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}");
+    let mut elements = Vec::new();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(&open) {
+        let after_name = from + start + open.len();
+        // Make sure we found `<pre>` and not `<prefix>`
+        if !lower[after_name..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            from = after_name;
+            continue;
+        }
+        let Some(open_end) = lower[after_name..].find('>') else {
+            break;
+        };
+        let contents_start = after_name + open_end + 1;
+        let Some(contents_len) = lower[contents_start..].find(&close) else {
+            break;
+        };
+        let contents_end = contents_start + contents_len;
+        elements.push((contents_start, contents_end));
+        from = contents_end + close.len();
+    }
+    elements
+}
+
+/// minify-html is supposed to leave `<pre>` and `<textarea>` alone, but collapses whitespace
+/// everywhere else, which isn't always what you want (say, for `<code>` blocks styled with
+/// `white-space: pre`). So we swap the contents of every preserved element out for a placeholder before
+/// minifying, then swap them back in after.
+fn minify_preserving(
+    contents: &str,
+    preserve_tags: &[String],
+    config: &MinifyConfig,
+) -> driver_util::Result<Vec<u8>> {
+    if preserve_tags.is_empty() {
+        return Ok(minify(contents, config));
+    }
+
+    // Make sure the placeholder can't show up in the document by accident
+    let mut prefix = String::from("driver-preserved-");
+    while contents.contains(&prefix) {
+        prefix.insert(0, '_');
+    }
+
+    let mut protected = Vec::new();
+    let mut html = contents.to_string();
+    for tag in preserve_tags {
+        // ASCII lowercasing keeps every byte where it was, so offsets carry back over to `html`
+        let lower = html.to_ascii_lowercase();
+        let mut replaced = String::with_capacity(html.len());
+        let mut last = 0;
+        for (start, end) in find_elements(&lower, &tag.to_ascii_lowercase()) {
+            replaced.push_str(&html[last..start]);
+            replaced.push_str(&format!("{prefix}{}", protected.len()));
+            protected.push(html[start..end].to_string());
+            last = end;
+        }
+        replaced.push_str(&html[last..]);
+        html = replaced;
+    }
+
+    let output = String::from_utf8(minify(&html, config)).map_err(|e| {
+        driver_util::Error::new(&format!("minify-html output isn't valid UTF-8: {e}"))
+    })?;
+    // Read the whole number after each placeholder, so that `{prefix}1` doesn't clobber the start
+    // of `{prefix}10`
+    let mut restored = String::with_capacity(output.len());
+    let mut rest = output.as_str();
+    while let Some(at) = rest.find(&prefix) {
+        restored.push_str(&rest[..at]);
+        rest = &rest[at + prefix.len()..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        match rest[..digits]
+            .parse()
+            .ok()
+            .and_then(|i: usize| protected.get(i))
+        {
+            Some(original) => restored.push_str(original),
+            None => {
+                restored.push_str(&prefix);
+                restored.push_str(&rest[..digits]);
+            }
+        }
+        rest = &rest[digits..];
+    }
+    restored.push_str(rest);
+    Ok(restored.into_bytes())
+}
 
 impl std::fmt::Display for MinifyHtml {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "minify_html({})", self.0)
+        write!(f, "minify_html({})", self.input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    use driver_engine::{Hooks, query};
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::links::ExtractLinks;
+    use crate::{HashKey, QueryContext, QueryKey};
+
+    #[test]
+    fn preserves_pre() {
+        let pre = "\n  fn main() {\n\t    println!(\"hi\");\n  }\n\n";
+        let html = format!(
+            "<html>\n  <body>\n    <p>  some   text  </p>\n    <pre class=\"x\">{pre}</pre>\n  </body>\n</html>\n"
+        );
+        let tags = vec!["pre".to_string(), "code".to_string()];
+        let output =
+            String::from_utf8(minify_preserving(&html, &tags, &MinifyConfig::default()).unwrap())
+                .unwrap();
+
+        assert!(output.contains(&format!(">{pre}</pre>")), "{output}");
+        // Everything else still got minified
+        assert!(output.len() < html.len());
+        assert!(!output.contains("some   text"));
+    }
+
+    #[test]
+    fn strips_comments_when_asked() {
+        let html = "<p>hi</p><!-- secret -->";
+        let mut config = MinifyConfig::default();
+        let kept = String::from_utf8(minify(html, &config)).unwrap();
+        assert!(kept.contains("secret"), "{kept}");
+
+        config.set("keep_comments", false).unwrap();
+        let stripped = String::from_utf8(minify(html, &config)).unwrap();
+        assert!(!stripped.contains("secret"), "{stripped}");
+
+        assert!(config.set("keep_everything", true).is_err());
+    }
+
+    #[test]
+    fn preserves_many_elements() {
+        let mut html = String::from("<html><body>");
+        for i in 0..2000 {
+            html.push_str(&format!(
+                "<p>  {i}  </p><PRE>  {i}\n    kept  </PRE><prefix>  </prefix>"
+            ));
+        }
+        html.push_str("</body></html>");
+        let output = String::from_utf8(
+            minify_preserving(&html, &["pre".to_string()], &MinifyConfig::default()).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(output.matches("\n    kept  </").count(), 2000);
+        assert!(output.contains(">  0\n    kept  </"), "{output}");
+        assert!(output.contains(">  1999\n    kept  </"), "{output}");
+        assert!(!output.contains("driver-preserved-"));
+        assert!(!output.contains("<p>  "));
+    }
+
+    /// Remembers every key that actually got computed.
+    #[derive(Clone, Default)]
+    struct Computed(Arc<Mutex<Vec<String>>>);
+
+    impl Hooks<QueryKey> for Computed {
+        fn on_compute(
+            &self,
+            _ctx: &QueryContext,
+            key: QueryKey,
+            _old_deps: HashSet<HashKey>,
+            _new_deps: HashSet<HashKey>,
+        ) {
+            self.0.lock().unwrap().push(key.to_string());
+        }
+    }
+
+    #[test]
+    fn option_change_reruns_only_affected() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = |preserve_tags: &[&str]| {
+            let mut options = driver_engine::Options::with_base_dir(dir.path());
+            options.minify_preserve_tags = preserve_tags.iter().map(|t| t.to_string()).collect();
+            let computed = Computed::default();
+            let ctx = QueryContext::create_root(options, Some(Box::new(computed.clone())));
+
+            let html = ctx
+                .store(b"<p>  hi  </p> <code>  x  </code>".to_vec())
+                .unwrap();
+            block_on(query(&ctx, MinifyHtml::new(html.clone(), ctx.options()))).unwrap();
+            block_on(query(&ctx, ExtractLinks(html))).unwrap();
+
+            ctx.destroy_root().unwrap();
+            computed.0.lock().unwrap().clone()
+        };
+
+        assert_eq!(build(&["pre"]).len(), 2);
+        // Same options, so everything's cached
+        assert!(build(&["pre"]).is_empty());
+        // Only the minification depends on the preserved tags
+        let rerun = build(&["pre", "code"]);
+        assert_eq!(rerun.len(), 1);
+        assert!(rerun[0].starts_with("minify_html"), "{rerun:?}");
     }
 }
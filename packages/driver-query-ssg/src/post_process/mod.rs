@@ -0,0 +1,201 @@
+//! Transforms that get run over every output file matching a glob, once the script is done but
+//! before anything's written, so scripts don't have to remember to call them on every page.
+
+use futures_concurrency::future::TryJoin as _;
+use globset::Glob;
+
+use driver_engine::{Blob, Options, query};
+
+use crate::boa::{RunJs, RunJsOutput};
+use crate::minify_html::{MinifyConfig, MinifyHtml};
+use crate::wasm::OptimizeWasm;
+use crate::{QueryContext, WriteOutput};
+
+/// Each transform is just one of the existing queries, so its results are memoized exactly like
+/// they would be if the script had asked for them itself.
+#[derive(
+    Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize,
+)]
+pub enum Transform {
+    MinifyHtml { preserve_tags: Vec<String> },
+    OptimizeWasm { level: u8 },
+}
+
+impl Transform {
+    /// Looks up a transform by name, configuring it from the options.
+    pub fn new(name: &str, options: &Options) -> driver_util::Result<Self> {
+        match name {
+            "minify_html" => Ok(Self::MinifyHtml {
+                preserve_tags: options.minify_preserve_tags.clone(),
+            }),
+            "optimize_wasm" => Ok(Self::OptimizeWasm {
+                level: options.wasm_opt_level,
+            }),
+            other => Err(driver_util::Error::new(&format!(
+                "unknown post-processor \"{other}\" (expected minify_html or optimize_wasm)"
+            ))),
+        }
+    }
+
+    async fn apply(&self, ctx: &QueryContext, input: Blob) -> driver_util::Result<Blob> {
+        match self {
+            Self::MinifyHtml { preserve_tags } => {
+                let preserve_tags = preserve_tags.clone();
+                query(
+                    ctx,
+                    MinifyHtml {
+                        input,
+                        preserve_tags,
+                        config: MinifyConfig::default(),
+                    },
+                )
+                .await
+            }
+            Self::OptimizeWasm { level } => {
+                let level = *level;
+                query(ctx, OptimizeWasm { input, level }).await
+            }
+        }
+    }
+}
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct PostProcess {
+        pub run: RunJs,
+        /// `(glob, transform)` pairs, applied in order. Part of the key so that changing them
+        /// re-writes everything.
+        pub rules: Vec<(String, Transform)>,
+    }
+);
+driver_engine::blob_trace!(PostProcess => { run });
+
+impl PostProcess {
+    /// Runs `run`, then applies whatever post-processing rules are configured in the options to
+    /// everything it writes.
+    pub fn new(run: RunJs, options: &Options) -> driver_util::Result<Self> {
+        let rules = options
+            .post_process
+            .iter()
+            .map(|rule| Ok((rule.glob.clone(), Transform::new(&rule.transform, options)?)))
+            .collect::<driver_util::Result<_>>()?;
+        Ok(Self { run, rules })
+    }
+}
+
+driver_engine::producer!(PostProcess(self, ctx) as (crate::QueryKey) -> RunJsOutput, on_error(error) {
+    RunJsOutput::failed(error)
+} {
+    let output = query(ctx, self.run.clone()).await;
+    if self.rules.is_empty() {
+        return output;
+    }
+    match post_process(ctx, &output.writes, &self.rules).await {
+        Ok(writes) => RunJsOutput {
+            export: output.export,
+            writes,
+        },
+        Err(e) => RunJsOutput {
+            export: Err(e),
+            writes: output.writes,
+        },
+    }
+});
+
+async fn post_process(
+    ctx: &QueryContext,
+    writes: &WriteOutput,
+    rules: &[(String, Transform)],
+) -> driver_util::Result<WriteOutput> {
+    let matchers = rules
+        .iter()
+        .map(|(glob, transform)| {
+            let glob = Glob::new(glob).map_err(|e| {
+                driver_util::Error::new(&format!("invalid post-process glob \"{glob}\": {e}"))
+            })?;
+            Ok((glob.compile_matcher(), transform))
+        })
+        .collect::<driver_util::Result<Vec<_>>>()?;
+
+    let files = writes
+        .iter()
+        .map(|(path, blob)| {
+            let matchers = &matchers;
+            async move {
+                let mut blob = blob.clone();
+                for (matcher, transform) in matchers {
+                    if matcher.is_match(path) {
+                        blob = transform.apply(ctx, blob).await?;
+                    }
+                }
+                driver_util::Result::Ok((path.clone(), blob))
+            }
+        })
+        .collect::<Vec<_>>()
+        .try_join()
+        .await?;
+
+    let mut builder = WriteOutput::builder();
+    for (path, blob) in files {
+        builder.push(path, blob);
+    }
+    Ok(builder.finalize())
+}
+
+impl std::fmt::Display for PostProcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "post_process({})", self.run)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::PostProcessRule;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::boa::parse_args;
+
+    #[test]
+    fn minifies_html_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("build.js");
+        std::fs::write(
+            &script,
+            r#"import { store, write_output } from "driver";
+write_output("index.html", store("<p>  hello   world  </p>"));
+write_output("style.css", store("p  {  color:  red  }"));
+"#,
+        )
+        .unwrap();
+
+        let mut options = driver_engine::Options::with_base_dir(&dir.path().join(".driver"));
+        options.post_process = vec![PostProcessRule {
+            glob: "*.html".to_string(),
+            transform: "minify_html".to_string(),
+        }];
+        let ctx = QueryContext::create_root(options, None);
+        let run = RunJs {
+            file: script,
+            arg: parse_args([]),
+        };
+        let key = PostProcess::new(run, ctx.options()).unwrap();
+        let output = block_on(query(&ctx, key));
+        output.export.unwrap();
+
+        let files: Vec<_> = output.writes.iter().collect();
+        assert_eq!(files.len(), 2);
+        for (path, blob) in files {
+            let contents = ctx.load_string(blob).unwrap();
+            if path.ends_with("index.html") {
+                assert!(contents.contains("hello world"), "{contents}");
+                assert!(!contents.contains("  "), "{contents}");
+            } else {
+                // Didn't match the glob, so it's left alone
+                assert_eq!(contents, "p  {  color:  red  }");
+            }
+        }
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+//! Timing queries by kind, for finding out what's making a build slow.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use driver_engine::Hooks;
+use driver_util::Key as _;
+
+use crate::{HashKey, QueryContext, QueryKey, QueryOutput};
+
+/// How much time one kind of query took.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KindProfile {
+    /// Total time spent in queries that actually got produced. Includes time spent waiting on
+    /// dependencies, so scripts will always look at least as slow as everything they call.
+    pub total: Duration,
+    /// How many times a query of this kind got produced.
+    pub produced: usize,
+    /// How many times a query of this kind was answered from the cache. These aren't timed.
+    pub cached: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    kinds: HashMap<&'static str, KindProfile>,
+    /// Keys that got computed, but whose `query()` call hasn't returned yet.
+    computing: HashMap<QueryKey, usize>,
+}
+
+/// [`Hooks`] that record how long each kind of query spends being produced. Clones all record
+/// into the same place, so keep one around to read the report from once the build is done.
+#[derive(Clone, Default)]
+pub struct Profiler(Arc<Mutex<Inner>>);
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every kind of query seen so far, slowest first.
+    pub fn kinds(&self) -> Vec<(&'static str, KindProfile)> {
+        let inner = self.0.lock().unwrap();
+        let mut kinds: Vec<_> = inner
+            .kinds
+            .iter()
+            .map(|(kind, profile)| (*kind, profile.clone()))
+            .collect();
+        kinds.sort_by(|(a_kind, a), (b_kind, b)| b.total.cmp(&a.total).then(a_kind.cmp(b_kind)));
+        kinds
+    }
+
+    /// A table of [`Self::kinds`], for printing.
+    pub fn report(&self) -> ProfileReport {
+        ProfileReport(self.kinds())
+    }
+
+    /// Forgets everything recorded so far, so the next report only covers what comes after. For
+    /// profiling each build separately when watching for changes.
+    pub fn reset(&self) {
+        self.0.lock().unwrap().kinds.clear();
+    }
+}
+
+impl Hooks<QueryKey> for Profiler {
+    fn on_compute(
+        &self,
+        _ctx: &QueryContext,
+        key: QueryKey,
+        _old_deps: HashSet<HashKey>,
+        _new_deps: HashSet<HashKey>,
+    ) {
+        // `on_compute` happens right before the `query()` call that caused it returns, which is
+        // when we find out how long it took.
+        *self.0.lock().unwrap().computing.entry(key).or_default() += 1;
+    }
+
+    fn on_query_finish(
+        &self,
+        _ctx: &QueryContext,
+        key: &QueryKey,
+        _output: &QueryOutput,
+        elapsed: Duration,
+    ) {
+        let mut inner = self.0.lock().unwrap();
+        let computed = match inner.computing.get_mut(key) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    inner.computing.remove(key);
+                }
+                true
+            }
+            None => false,
+        };
+        let profile = inner.kinds.entry(key.kind()).or_default();
+        if computed {
+            profile.total += elapsed;
+            profile.produced += 1;
+        } else {
+            profile.cached += 1;
+        }
+    }
+}
+
+/// See [`Profiler::report`].
+pub struct ProfileReport(Vec<(&'static str, KindProfile)>);
+
+impl Display for ProfileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.0.iter().map(|(kind, _)| kind.len()).max().unwrap_or(0);
+        for (kind, profile) in self.0.iter() {
+            writeln!(
+                f,
+                "{kind:width$}  {:>10.1?}  {} produced, {} cached",
+                profile.total, profile.produced, profile.cached
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::json::ParseJson;
+
+    #[test]
+    fn counts_hits_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let profiler = Profiler::new();
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(dir.path()),
+            Some(Box::new(profiler.clone())),
+        );
+        let json = ctx.store(b"[1, 2, 3]".to_vec()).unwrap();
+        block_on(query(&ctx, ParseJson(json.clone()))).unwrap();
+        block_on(query(&ctx, ParseJson(json))).unwrap();
+        ctx.destroy_root().unwrap();
+
+        let kinds = profiler.kinds();
+        assert_eq!(kinds.len(), 1);
+        let (kind, profile) = &kinds[0];
+        assert_eq!(*kind, "ParseJson");
+        assert_eq!((profile.produced, profile.cached), (1, 1));
+        assert!(profiler.report().to_string().starts_with("ParseJson"));
+
+        profiler.reset();
+        assert!(profiler.kinds().is_empty());
+    }
+}
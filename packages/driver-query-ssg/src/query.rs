@@ -4,29 +4,71 @@
 //! I would like to not have to do this, but the alternatives are Much Worse + I don't see anyone
 //! besides myself actually using this, so it'll do lol.
 
-use driver_query_fs::{ListDirectory, ReadFile};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use driver_query_fs::{HashDirectory, ListDirectory, ReadFile, WalkDirectory};
 use driver_query_hyper::GetUrl;
 
 use crate::boa::RunJs;
 use crate::comrak::MarkdownToHtml;
+use crate::concat::Concat;
+use crate::env::{DotEnv, EnvVar};
+use crate::front_matter::ParseFrontMatter;
+use crate::json::ParseJson;
+use crate::links::ExtractLinks;
 use crate::minify_html::MinifyHtml;
-use crate::tera::RunTera;
+use crate::post_process::PostProcess;
+use crate::reading_time::ReadingTime;
+use crate::scss::CompileScss;
+use crate::sitemap::Sitemap;
+use crate::tera::{RenderTera, RunTera};
+use crate::wasm::OptimizeWasm;
 use crate::zune::{ConvertImage, ParseImage};
 
 driver_engine::query!(
 QueryKey {
     ReadFile,
     ListDirectory,
+    HashDirectory,
+    WalkDirectory,
     GetUrl,
     RunJs,
     MarkdownToHtml,
     MinifyHtml,
     RunTera,
+    RenderTera,
     ConvertImage,
     ParseImage,
+    ExtractLinks,
+    OptimizeWasm,
+    Sitemap,
+    DotEnv,
+    EnvVar,
+    ReadingTime,
+    PostProcess,
+    ParseJson,
+    CompileScss,
+    Concat,
+    ParseFrontMatter,
 } with QueryOutput);
 
 pub type QueryContext = driver_engine::Context<QueryKey>;
 pub type HashKey = driver_db::Hashed<QueryKey>;
 pub type WriteOutput = driver_util::WriteOutput<HashKey>;
 pub type WriteOutputBuilder = driver_util::WriteOutputBuilder<HashKey>;
+
+/// Collects every file & directory that the given query (transitively) read, for things like
+/// depfiles. SHOULD only be called when computation isn't taking place.
+pub fn input_files(ctx: &QueryContext, root: HashKey) -> BTreeSet<PathBuf> {
+    ctx.db()
+        .transitive_keys(root)
+        .into_iter()
+        .filter_map(|key| match &*key {
+            QueryKey::ReadFile(ReadFile(path)) => Some(path.clone()),
+            QueryKey::ListDirectory(ListDirectory { path, .. }) => Some(path.clone()),
+            QueryKey::HashDirectory(HashDirectory { path, .. }) => Some(path.clone()),
+            _ => None,
+        })
+        .collect()
+}
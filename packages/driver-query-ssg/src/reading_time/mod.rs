@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use driver_engine::{Blob, Options};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct ReadingTime {
+        /// HTML or markdown.
+        pub input: Blob,
+        /// Words per minute. Part of the key so that changing it recomputes everything.
+        pub words_per_minute: u32,
+    }
+);
+driver_engine::blob_trace!(ReadingTime => { input });
+
+impl ReadingTime {
+    /// Estimates the reading time of `input` using whatever reading speed is configured in the
+    /// options.
+    pub fn new(input: Blob, options: &Options) -> Self {
+        Self {
+            input,
+            words_per_minute: options.words_per_minute,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadingStats {
+    pub words: u32,
+    /// Rounded up, so anything with words in it takes at least a minute.
+    pub minutes: u32,
+}
+driver_engine::no_blobs!(ReadingStats);
+
+driver_engine::producer!(ReadingTime(self, ctx) -> driver_util::Result<ReadingStats> {
+    let contents = ctx.load_string(&self.input)?;
+    let words = count_words(&strip_tags(&contents));
+    Ok(ReadingStats {
+        words,
+        minutes: words.div_ceil(self.words_per_minute.max(1)),
+    })
+});
+
+/// Drops everything between `<` and `>`. Not a real HTML parser, but we only need the words.
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                // So `a<br>b` is still two words
+                out.push(' ');
+            }
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Chinese & Japanese don't put spaces between words, so each character counts as one instead.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana & Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+    )
+}
+
+/// Counts runs of letters & numbers as words, ignoring all punctuation (including markdown
+/// syntax like `#` and `*`).
+fn count_words(text: &str) -> u32 {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            words += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() || (in_word && c == '\'') {
+            if !in_word {
+                words += 1;
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    words
+}
+
+impl std::fmt::Display for ReadingTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reading_time({}, wpm={})",
+            self.input, self.words_per_minute
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::QueryContext;
+
+    #[test]
+    fn counts_words() {
+        assert_eq!(count_words("# Hello, *world*! It's   me."), 4);
+        assert_eq!(
+            count_words(&strip_tags("<p>one<br>two</p> <a href=\"x\">three</a>")),
+            3
+        );
+        // 5 characters, plus one English word
+        assert_eq!(count_words("日本語です OK"), 6);
+    }
+
+    #[test]
+    fn estimates_minutes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = driver_engine::Options::with_base_dir(dir.path());
+        options.words_per_minute = 100;
+        let ctx = QueryContext::create_root(options, None);
+
+        let post = "<p>word </p>".repeat(250);
+        let blob = ctx.store(post.into_bytes()).unwrap();
+        let stats = block_on(query(&ctx, ReadingTime::new(blob, ctx.options()))).unwrap();
+        assert_eq!(
+            stats,
+            ReadingStats {
+                words: 250,
+                minutes: 3,
+            }
+        );
+
+        ctx.destroy_root().unwrap();
+    }
+}
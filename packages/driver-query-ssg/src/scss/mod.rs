@@ -0,0 +1,107 @@
+//! Compiling SCSS into CSS.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use driver_engine::{Blob, query};
+use driver_query_fs::ReadFile;
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct CompileScss(pub Blob);
+);
+driver_engine::blob_trace!(CompileScss => (0));
+
+/// Reads straight from disk (grass wants to read files synchronously, mid-compile), but remembers
+/// which files it read, so we can depend on them afterwards.
+#[derive(Debug, Default)]
+struct RecordingFs {
+    read: Mutex<Vec<PathBuf>>,
+}
+
+impl grass::Fs for RecordingFs {
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        self.read.lock().unwrap().push(path.to_path_buf());
+        std::fs::read(path)
+    }
+}
+
+// `@import`/`@use` are resolved relative to the project root, same as every other path.
+driver_engine::producer!(CompileScss(self, ctx) as (crate::QueryKey) -> driver_util::Result<Blob> {
+    let contents = ctx.load_string(&self.0)?;
+
+    let fs = RecordingFs::default();
+    let options = grass::Options::default().fs(&fs);
+    let output = grass::from_string(contents, &options)
+        .map_err(|e| driver_util::Error::new(&e.to_string()));
+
+    // Even if compilation failed, fixing an imported file might fix it
+    let read = fs.read.into_inner().unwrap();
+    for path in read {
+        query(ctx, ReadFile(driver_util::normalize_path(&path))).await?;
+    }
+
+    let blob = ctx.store(output?.into_bytes())?;
+    Ok(blob)
+});
+
+impl std::fmt::Display for CompileScss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "compile_scss({})", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::QueryContext;
+
+    #[test]
+    fn recompiles_when_import_changes() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since imports are resolved from the project root
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        std::fs::write(dir.join("_vars.scss"), "$color: red;").unwrap();
+        let scss = format!(
+            "@import \"{}/vars\";\na {{ color: $color; }}\n",
+            dir.display()
+        );
+
+        let compile = || {
+            let ctx = QueryContext::create_root(
+                driver_engine::Options::with_base_dir(&dir.join(".driver")),
+                None,
+            );
+            let input = ctx.store(scss.clone().into_bytes()).unwrap();
+            let css = block_on(query(&ctx, CompileScss(input))).unwrap();
+            let css = ctx.load_string(&css).unwrap();
+            ctx.destroy_root().unwrap();
+            css
+        };
+
+        assert!(compile().contains("color: red"));
+        std::fs::write(dir.join("_vars.scss"), "$color: blue;").unwrap();
+        assert!(compile().contains("color: blue"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+        let input = ctx.store(b"a { color: $undefined; }".to_vec()).unwrap();
+        let err = block_on(query(&ctx, CompileScss(input))).unwrap_err();
+        assert!(err.to_string().contains("Undefined variable"), "{err}");
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Generating a sitemap.xml for the whole site, from the HTML files a build is about to write.
+
+use std::path::{Path, PathBuf};
+
+use jiff::Timestamp;
+
+use crate::WriteOutput;
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct Sitemap {
+        /// Every page to list, relative to the output directory.
+        pub pages: Vec<PathBuf>,
+        /// What the output directory is served at, like `https://example.com`.
+        pub base_url: String,
+        /// Stamped onto every page, if set.
+        pub lastmod: Option<String>,
+    }
+);
+driver_engine::no_blobs!(Sitemap);
+
+impl Sitemap {
+    /// A sitemap listing every HTML file in `outputs`.
+    pub fn new(outputs: &WriteOutput, base_url: &str, lastmod: Option<Timestamp>) -> Self {
+        let mut pages: Vec<PathBuf> = outputs
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .cloned()
+            .collect();
+        pages.sort();
+        Self {
+            pages,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            lastmod: lastmod.map(|time| time.to_string()),
+        }
+    }
+}
+
+driver_engine::producer!(Sitemap(self, ctx) -> driver_util::Result<driver_engine::Blob> {
+    let blob = ctx.store(render(self).into_bytes())?;
+    Ok(blob)
+});
+
+fn render(sitemap: &Sitemap) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    ));
+    for page in sitemap.pages.iter() {
+        out.push_str("  <url>\n");
+        out.push_str(&format!(
+            "    <loc>{}</loc>\n",
+            escape(&page_url(&sitemap.base_url, page))
+        ));
+        if let Some(lastmod) = &sitemap.lastmod {
+            out.push_str(&format!("    <lastmod>{}</lastmod>\n", escape(lastmod)));
+        }
+        out.push_str("  </url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// Where `page` ends up being served. `foo/index.html` is listed as `foo/`, since that's the URL
+/// people actually link to.
+fn page_url(base_url: &str, page: &Path) -> String {
+    let path = page
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let path = match path.strip_suffix("index.html") {
+        Some(dir) if dir.is_empty() || dir.ends_with('/') => dir,
+        _ => &path,
+    };
+    format!("{base_url}/{path}")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+impl std::fmt::Display for Sitemap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sitemap({}, {} pages)", self.base_url, self.pages.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::QueryContext;
+
+    #[test]
+    fn lists_html_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+        let store = |s: &str| ctx.store(s.as_bytes().to_vec()).unwrap();
+
+        let mut outputs = WriteOutput::builder();
+        outputs.push("index.html".into(), store("<p>home</p>"));
+        outputs.push("blog/first-post.html".into(), store("<p>post</p>"));
+        outputs.push("style.css".into(), store("p {}"));
+        let outputs = outputs.finalize();
+
+        let sitemap = Sitemap::new(&outputs, "https://example.com/", None);
+        let blob = block_on(query(&ctx, sitemap)).unwrap();
+        let xml = ctx.load_string(&blob).unwrap();
+
+        assert!(xml.contains("<loc>https://example.com/</loc>"), "{xml}");
+        assert!(
+            xml.contains("<loc>https://example.com/blog/first-post.html</loc>"),
+            "{xml}"
+        );
+        assert_eq!(xml.matches("<url>").count(), 2);
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! Streaming output files as soon as the script that wrote them finishes, instead of waiting for
+//! the whole build. Useful for deploys that upload as they go.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::Duration;
+
+use driver_engine::{Blob, Hooks};
+
+use crate::{HashKey, QueryContext, QueryKey, QueryOutput};
+
+/// [`Hooks`] that send every output file down a channel, as soon as whatever wrote it finishes.
+///
+/// Outputs of nested scripts get merged into their parents' outputs, so the same file can show
+/// up more than once; each distinct (path, contents) pair is only ever sent once. Writes that
+/// something else in the same output overrides (like a script writing a file that a script it ran
+/// also wrote) are never sent at all. So once the build is done, everything sent is exactly what
+/// ends up in the root's outputs, unless two scripts that didn't run each other wrote the same
+/// file, in which case both versions get sent.
+pub struct OutputStream {
+    sender: Mutex<Sender<(PathBuf, Blob)>>,
+    sent: Mutex<HashSet<(PathBuf, Blob)>>,
+}
+
+impl OutputStream {
+    /// Pass the first half to [`QueryContext::create_root`], and read outputs from the second.
+    pub fn new() -> (Self, Receiver<(PathBuf, Blob)>) {
+        let (sender, receiver) = channel();
+        let stream = Self {
+            sender: Mutex::new(sender),
+            sent: Default::default(),
+        };
+        (stream, receiver)
+    }
+}
+
+impl Hooks<QueryKey> for OutputStream {
+    fn on_compute(
+        &self,
+        _ctx: &QueryContext,
+        _key: QueryKey,
+        _old_deps: HashSet<HashKey>,
+        _new_deps: HashSet<HashKey>,
+    ) {
+    }
+
+    fn on_query_finish(
+        &self,
+        _ctx: &QueryContext,
+        _key: &QueryKey,
+        output: &QueryOutput,
+        _elapsed: Duration,
+    ) {
+        let writes = match output {
+            QueryOutput::RunJs(output) => &output.writes,
+            QueryOutput::RunTera(output) => &output.writes,
+            _ => return,
+        };
+        // Later writes to the same path win, same as when actually writing everything out
+        let files: BTreeMap<&PathBuf, &Blob> = writes.iter().collect();
+        let mut sent = self.sent.lock().unwrap();
+        let sender = self.sender.lock().unwrap();
+        for (path, blob) in files {
+            let file = (path.clone(), blob.clone());
+            if sent.insert(file.clone()) {
+                // Nobody listening anymore is fine, they just don't get told
+                let _ = sender.send(file);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use driver_engine::query;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::boa::{RunJs, parse_args};
+
+    #[test]
+    fn each_output_once() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        // Relative, since that's how scripts see paths
+        let dir = tmp.path().strip_prefix(".").unwrap();
+        let index = dir.join("index.js");
+        let post = dir.join("post.js");
+        std::fs::write(
+            &index,
+            format!(
+                r#"import {{ run_js, store, write_output }} from "driver";
+write_output("index.html", store("index"));
+write_output("post.html", store("draft"));
+await run_js("{}", []);
+"#,
+                post.display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            &post,
+            r#"import { store, write_output } from "driver";
+write_output("post.html", store("post"));
+"#,
+        )
+        .unwrap();
+
+        let (stream, receiver) = OutputStream::new();
+        let ctx = QueryContext::create_root(
+            driver_engine::Options::with_base_dir(&dir.join(".driver")),
+            Some(Box::new(stream)),
+        );
+        let output = block_on(query(
+            &ctx,
+            RunJs {
+                file: index,
+                arg: parse_args([]),
+            },
+        ));
+        output.export.unwrap();
+        let expected: BTreeMap<_, _> = output
+            .writes
+            .iter()
+            .map(|(path, blob)| (path.clone(), blob.clone()))
+            .collect();
+        assert_eq!(
+            ctx.load_string(&expected[&PathBuf::from("post.html")])
+                .unwrap(),
+            "post"
+        );
+        // Drops the stream, so the receiver sees the end
+        ctx.destroy_root().unwrap();
+
+        // The root's own "draft" never gets sent, since the nested script's write wins
+        let streamed: Vec<_> = receiver.iter().collect();
+        assert_eq!(streamed.len(), 2);
+        let streamed: BTreeMap<_, _> = streamed.into_iter().collect();
+        assert_eq!(streamed, expected);
+    }
+}
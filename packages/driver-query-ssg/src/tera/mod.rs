@@ -1,5 +1,5 @@
 use std::collections::{BTreeMap, HashMap};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::task::Poll;
 
@@ -33,16 +33,25 @@ driver_engine::blob_trace!(RunTeraOutput => {
     writes,
 });
 
-driver_engine::producer!(RunTera(self, ctx) as (crate::QueryKey) -> RunTeraOutput {
+impl RunTeraOutput {
+    /// An output that failed before writing anything.
+    pub fn failed(error: driver_util::Error) -> Self {
+        Self {
+            export: Err(error),
+            writes: WriteOutput::builder().finalize(),
+        }
+    }
+}
+
+driver_engine::producer!(RunTera(self, ctx) as (crate::QueryKey) -> RunTeraOutput, on_error(error) {
+    RunTeraOutput::failed(error)
+} {
     println!("run_tera(\"{}\", {})", self.file.display(), self.arg);
     let input = match query(ctx, ReadFile(self.file.clone())).await {
         Ok(input) => input,
-        Err(e) => return RunTeraOutput {
-            export: Err(e),
-            writes: WriteOutput::builder().finalize(),
-        },
+        Err(e) => return RunTeraOutput::failed(e),
     };
-    render_tera_async(ctx, &input, &self.file, &self.arg).await
+    render_tera_async(ctx, &input, &self.file.display().to_string(), &self.arg).await
 });
 
 impl std::fmt::Display for RunTera {
@@ -51,16 +60,41 @@ impl std::fmt::Display for RunTera {
     }
 }
 
+/// Like [`RunTera`], but for a template that's already in the store, like one that was fetched or
+/// put together by a script. Both the template & the argument are part of the key, so changing
+/// either renders again.
+driver_engine::key!(
+    #[input=|_| false]
+    struct RenderTera {
+        pub template: Blob,
+        pub arg: JsValue,
+    }
+);
+driver_engine::blob_trace!(RenderTera => { template, arg });
+
+driver_engine::producer!(RenderTera(self, ctx) as (crate::QueryKey) -> RunTeraOutput, on_error(error) {
+    RunTeraOutput::failed(error)
+} {
+    render_tera_async(ctx, &self.template, &self.template.to_string(), &self.arg).await
+});
+
+impl std::fmt::Display for RenderTera {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "render_tera({}, {})", self.template, self.arg)
+    }
+}
+
 enum State<T> {
     NotStarted,
     Running,
     Complete(T),
 }
 
+/// `name` is what the template is called in error messages.
 fn render_tera_async(
     ctx: &QueryContext,
     input: &Blob,
-    file: &Path,
+    name: &str,
     arg: &JsValue,
 ) -> impl Future<Output = RunTeraOutput> {
     // The main reason why I can't just block is because the blocking tera template will itself
@@ -90,12 +124,12 @@ fn render_tera_async(
         // If we passed the check, spawn the thread
         let ctx = ctx.clone();
         let input = input.clone();
-        let file = PathBuf::from(file);
+        let name = name.to_string();
         let arg = arg.clone();
         let state = state.clone();
         let waker = poll_ctx.waker().clone();
         std::thread::spawn(move || {
-            let output = render_tera(&ctx, &input, &file, &arg);
+            let output = render_tera(&ctx, &input, &name, &arg);
             {
                 *state.lock().unwrap() = State::Complete(output);
             }
@@ -105,7 +139,7 @@ fn render_tera_async(
     })
 }
 
-fn render_tera(ctx: &QueryContext, input: &Blob, file: &Path, arg: &JsValue) -> RunTeraOutput {
+fn render_tera(ctx: &QueryContext, input: &Blob, name: &str, arg: &JsValue) -> RunTeraOutput {
     let writes = Arc::new(Mutex::new(WriteOutput::builder()));
 
     let export = (|| -> driver_util::Result<_> {
@@ -114,10 +148,9 @@ fn render_tera(ctx: &QueryContext, input: &Blob, file: &Path, arg: &JsValue) ->
         let mut tera = Tera::default();
         register_functions(&mut tera, ctx, writes.clone());
 
-        let name = format!("{}", file.display());
-        tera.add_raw_template(&name, &input)?;
+        tera.add_raw_template(name, &input)?;
         let context = js_to_tera_context(arg)?;
-        let output = tera.render(&name, &context)?;
+        let output = tera.render(name, &context)?;
 
         ctx.store(output.into_bytes())
     })();
@@ -170,7 +203,7 @@ fn register_functions(tera: &mut Tera, ctx: &QueryContext, writes: Arc<Mutex<Wri
         wrap_function!(move(ctx) |args| {
             let dir: &str = args.must_get("dir")?;
             let dir = resolve_path(dir)?;
-            let list_directory = ListDirectory(dir);
+            let list_directory = ListDirectory::new(dir, ctx.options());
             let files =
                 future::block_on(query(&ctx, list_directory.clone())).map_err(|e| tera::Error::message(format!("{list_directory}: {e}")))?;
             Ok(files
@@ -344,10 +377,12 @@ fn register_functions(tera: &mut Tera, ctx: &QueryContext, writes: Arc<Mutex<Wri
 
 /// Resolves a path to normalized relative to the cwd
 fn resolve_path(path: &str) -> TeraResult<PathBuf> {
-    Ok(RelativePathBuf::from_path(".")
-        .map_err(tera::Error::message)?
-        .join_normalized(RelativePath::new(path))
-        .to_path("."))
+    Ok(driver_util::normalize_path(
+        &RelativePathBuf::from_path(".")
+            .map_err(tera::Error::message)?
+            .join_normalized(RelativePath::new(path))
+            .to_path("."),
+    ))
 }
 
 fn js_to_tera_context(value: &JsValue) -> driver_util::Result<tera::Context> {
@@ -399,6 +434,7 @@ fn js_to_tera_value(value: &JsValue) -> TeraResult<tera::Value> {
         JsValue::Null => tera::Value::none(),
         JsValue::Bool(b) => (*b).into(),
         JsValue::Int(i) => (*i).into(),
+        JsValue::Float(n) => n.get().into(),
         JsValue::String(s) => s.to_string().into(),
         JsValue::Array(arr) => arr
             .iter()
@@ -469,3 +505,33 @@ fn tera_to_js_store_object(obj: &tera::Map) -> TeraResult<Blob> {
     let blob = unsafe { Blob::from_hash(hash) };
     Ok(blob)
 }
+
+#[cfg(test)]
+mod test {
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    #[test]
+    fn renders_stored_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx =
+            QueryContext::create_root(driver_engine::Options::with_base_dir(dir.path()), None);
+        let render = |template: &str, name: &str| {
+            let template = ctx.store(template.as_bytes().to_vec()).unwrap();
+            let arg = JsValue::Object(BTreeMap::from([(
+                "name".to_string(),
+                JsValue::String(name.to_string()),
+            )]));
+            block_on(query(&ctx, RenderTera { template, arg })).export
+        };
+
+        let output = render("Hello {{ name }}!", "world").unwrap();
+        assert_eq!(ctx.load_string(&output).unwrap(), "Hello world!");
+        let output = render("Hello {{ name }}!", "again").unwrap();
+        assert_eq!(ctx.load_string(&output).unwrap(), "Hello again!");
+        assert!(render("Hello {{ name", "world").is_err());
+
+        ctx.destroy_root().unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use driver_engine::{Blob, Options};
+
+driver_engine::key!(
+    #[input=|_| false]
+    struct OptimizeWasm {
+        pub input: Blob,
+        /// How hard to try. Part of the key so that changing it re-optimizes everything.
+        pub level: u8,
+    }
+);
+driver_engine::blob_trace!(OptimizeWasm => { input });
+
+impl OptimizeWasm {
+    /// Optimizes `input` at whatever level is configured in the options.
+    pub fn new(input: Blob, options: &Options) -> Self {
+        Self {
+            input,
+            level: options.wasm_opt_level,
+        }
+    }
+}
+
+driver_engine::producer!(OptimizeWasm(self, ctx) -> driver_util::Result<Blob> {
+    let contents = ctx.load_bytes(&self.input)?;
+    let output = optimize(&contents, self.level)?;
+    let blob = ctx.store(output)?;
+    Ok(blob)
+});
+
+/// Re-encodes a module, shrinking it along the way:
+///
+/// * level 0 just parses & re-emits it (so still validates it);
+/// * level 1 also removes every function, global, etc. that nothing uses;
+/// * level 2 and up also drops the name & producers sections, which are only for debugging.
+///
+/// walrus always lays out the module the same way for the same input, so this is reproducible.
+fn optimize(contents: &[u8], level: u8) -> driver_util::Result<Vec<u8>> {
+    let mut config = walrus::ModuleConfig::new();
+    config
+        .generate_name_section(level < 2)
+        .generate_producers_section(level < 2);
+    let mut module = config
+        .parse(contents)
+        .map_err(|e| driver_util::Error::new(&format!("invalid wasm: {e}")))?;
+    if level >= 1 {
+        walrus::passes::gc::run(&mut module);
+    }
+    Ok(module.emit_wasm())
+}
+
+impl std::fmt::Display for OptimizeWasm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "optimize_wasm({}, level={})", self.input, self.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `(module (func (export "ans") (result i32) i32.const 42) (func))`, where the second
+    /// function isn't used by anything.
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic & version
+        0x01, 0x08, 0x02, 0x60, 0x00, 0x01, 0x7f, 0x60, 0x00, 0x00, // types
+        0x03, 0x03, 0x02, 0x00, 0x01, // functions
+        0x07, 0x07, 0x01, 0x03, 0x61, 0x6e, 0x73, 0x00, 0x00, // exports
+        0x0a, 0x09, 0x02, 0x04, 0x00, 0x41, 0x2a, 0x0b, 0x02, 0x00, 0x0b, // code
+    ];
+
+    #[test]
+    fn round_trips() {
+        let optimized = optimize(MODULE, 2).unwrap();
+        assert_eq!(optimize(MODULE, 2).unwrap(), optimized);
+        assert!(optimized.len() < MODULE.len());
+
+        // Still valid, and still exports the function that matters
+        let module = walrus::Module::from_buffer(&optimized).unwrap();
+        assert!(module.exports.iter().any(|export| export.name == "ans"));
+        assert_eq!(module.funcs.iter().count(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let err = optimize(b"\0asm not really", 1).unwrap_err();
+        assert!(err.to_string().contains("invalid wasm"), "{err}");
+    }
+}
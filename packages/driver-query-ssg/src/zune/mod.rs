@@ -37,6 +37,27 @@ impl ImageSize {
     }
 }
 
+/// The size to convert an image to. If only one dimension is given, the other one is picked to
+/// keep the aspect ratio of the source image.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TargetSize {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+}
+
+impl TargetSize {
+    /// Returns (width, height), filling in whichever is missing from `source`.
+    fn resolve(&self, source: ImageSize) -> (usize, usize) {
+        let scale = |n: usize, to: usize, from: usize| (n * to / from.max(1)).max(1);
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, scale(source.height, width, source.width)),
+            (None, Some(height)) => (scale(source.width, height, source.height), height),
+            (None, None) => source.as_dimensions(),
+        }
+    }
+}
+
 /// Parsed data about an image, so that we can access cruicial information about it without having
 /// to re-parse the headers.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
@@ -184,7 +205,7 @@ driver_engine::key!(
         /// if not.
         pub format: Option<ImageFormat>,
         /// If None, will preserve the dimensions of the source image.
-        pub size: Option<ImageSize>,
+        pub size: Option<TargetSize>,
         /// If None, will use ImageFit::Contain
         pub fit: Option<ImageFit>,
         /// If None, will use Zune's defaults
@@ -210,8 +231,7 @@ driver_engine::producer!(ConvertImage(self, ctx) -> driver_util::Result<ImageObj
     let format = self.format.unwrap_or_default();
 
     let (target_width, target_height) = size
-        .as_ref()
-        .map(ImageSize::as_dimensions)
+        .map(|size| size.resolve(self.input.size))
         .unwrap_or((source_width, source_height));
     let (dest_width, dest_height) = match fit {
         ImageFit::Fill => (target_width, target_height),
@@ -292,6 +312,17 @@ impl Display for ImageSize {
     }
 }
 
+impl Display for TargetSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => write!(f, "{{ width: {width}, height: {height} }}"),
+            (Some(width), None) => write!(f, "{{ width: {width} }}"),
+            (None, Some(height)) => write!(f, "{{ height: {height} }}"),
+            (None, None) => f.write_str("{}"),
+        }
+    }
+}
+
 impl Display for ImageFit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -398,3 +429,21 @@ impl Display for ConvertImage {
         f.write_str("})")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_aspect_ratio_with_one_dimension() {
+        let source = ImageSize {
+            width: 1200,
+            height: 800,
+        };
+        let size = |width, height| TargetSize { width, height }.resolve(source);
+        assert_eq!(size(Some(300), None), (300, 200));
+        assert_eq!(size(None, Some(100)), (150, 100));
+        assert_eq!(size(Some(10), Some(10)), (10, 10));
+        assert_eq!(size(None, None), (1200, 800));
+    }
+}
@@ -13,6 +13,23 @@ impl Blob {
     pub unsafe fn from_hash(hash: Hash) -> Self {
         Self(hash)
     }
+
+    /// The sha256 of the blob's contents, as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Errors unless the blob's contents hash to `expected`, a hex-encoded sha256.
+    pub fn verify_sha256(&self, expected: &str) -> crate::Result<()> {
+        let actual = self.to_hex();
+        if actual.eq_ignore_ascii_case(expected.trim()) {
+            Ok(())
+        } else {
+            Err(crate::Error::new(&format!(
+                "sha256 mismatch: expected {expected}, got {actual}"
+            )))
+        }
+    }
 }
 
 impl std::fmt::Display for Blob {
@@ -105,3 +122,25 @@ no_blobs!(i8);
 no_blobs!(i16);
 no_blobs!(i32);
 no_blobs!(i64);
+no_blobs!(crate::Hash);
+
+#[cfg(test)]
+mod test {
+    use sha2::Digest as _;
+
+    use super::*;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn verify_sha256() {
+        // SAFETY: it is the hash of the contents
+        let blob = unsafe { Blob::from_hash(sha2::Sha256::digest(b"hello")) };
+        assert_eq!(blob.to_hex(), HELLO_SHA256);
+        assert!(blob.verify_sha256(HELLO_SHA256).is_ok());
+        assert!(blob.verify_sha256(&HELLO_SHA256.to_uppercase()).is_ok());
+
+        let other = HELLO_SHA256.replace('2', "3");
+        assert!(blob.verify_sha256(&other).is_err());
+    }
+}
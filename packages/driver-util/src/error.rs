@@ -5,17 +5,79 @@ use serde::{Deserialize, Serialize};
 /// A very simple arbitrary error wrapper that just serializes everything to a String. Used in
 /// place of anyhow so that we can clone it.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub struct Error(String);
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+/// The few kinds of errors callers actually want to tell apart. Everything else is just
+/// [`ErrorKind::Other`] with a message.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum ErrorKind {
+    #[default]
+    Other,
+    /// The thing we were asked to read doesn't exist (as opposed to existing but being
+    /// unreadable, or being empty).
+    NotFound,
+    /// Something that might work if we just try again later, like a dropped connection or a
+    /// server having a bad time. Deterministic failures SHOULD NOT use this.
+    Transient,
+    /// The build was asked to stop before it finished. Nothing produced after that SHOULD be
+    /// trusted, since it may have been cut short.
+    Cancelled,
+}
 
 impl Error {
     pub fn new(s: &str) -> Self {
-        Self(s.to_string())
+        Self::with_kind(ErrorKind::Other, s)
+    }
+
+    pub fn not_found(s: &str) -> Self {
+        Self::with_kind(ErrorKind::NotFound, s)
+    }
+
+    pub fn transient(s: &str) -> Self {
+        Self::with_kind(ErrorKind::Transient, s)
+    }
+
+    pub fn cancelled(s: &str) -> Self {
+        Self::with_kind(ErrorKind::Cancelled, s)
+    }
+
+    /// Re-tags an existing error with a different kind, keeping the message.
+    pub fn into_kind(self, kind: ErrorKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    pub fn with_kind(kind: ErrorKind, s: &str) -> Self {
+        Self {
+            kind,
+            message: s.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind == ErrorKind::NotFound
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.kind == ErrorKind::Transient
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.kind == ErrorKind::Cancelled
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.0)
+        f.write_str(&self.message)
     }
 }
 
@@ -32,7 +94,7 @@ where
             msg.push_str(&format!("\n\t{e}"));
             err = e.source();
         }
-        Self(msg)
+        Self::new(&msg)
     }
 }
 
@@ -1,3 +1,4 @@
+use std::fmt::{Debug, Display};
 use std::ops::{Deref, DerefMut};
 
 use sha2::Digest;
@@ -69,3 +70,97 @@ where
         hasher.finalize()
     }
 }
+
+/// Hashes a collection without caring what order it's iterated in, for things like [`HashMap`]s
+/// & [`HashSet`]s that can't implement [`std::hash::Hash`] themselves. Ordered maps like
+/// [`BTreeMap`] don't need this: they always iterate in key order, so two that are equal always
+/// hash the same through [`ToHash`].
+///
+/// [`HashMap`]: std::collections::HashMap
+/// [`HashSet`]: std::collections::HashSet
+/// [`BTreeMap`]: std::collections::BTreeMap
+pub fn hash_unordered<T: ToHash>(items: impl IntoIterator<Item = T>) -> Hash {
+    let mut hashes: Vec<Hash> = items.into_iter().map(|item| item.to_hash()).collect();
+    hashes.sort();
+    let mut hasher = Sha256Hasher::new();
+    std::hash::Hasher::write(&mut hasher, b"Unordered[");
+    for hash in hashes.iter() {
+        std::hash::Hasher::write(&mut hasher, hash);
+    }
+    std::hash::Hasher::write(&mut hasher, b"]");
+    hasher.finalize()
+}
+
+/// How many bytes of a [`Hash`] to show in a [`ShortHash`]. 4 bytes = 8 hex characters, same as
+/// git's default, which is plenty to tell things apart in logs.
+const SHORT_HASH_BYTES: usize = 4;
+
+/// Displays a [`Hash`] as a short hex prefix, for use in logs/debug output where the full
+/// `[12, 34, 56, ...]` byte list is just noise.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ShortHash<'a>(pub &'a Hash);
+
+impl Display for ShortHash<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&hex::encode(&self.0[..SHORT_HASH_BYTES]))
+    }
+}
+
+impl Debug for ShortHash<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// Shorthand for [`ShortHash`].
+pub fn short_hex(hash: &Hash) -> ShortHash<'_> {
+    ShortHash(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+
+    #[test]
+    fn maps_hash_regardless_of_order() {
+        let entries = [("title", 1), ("draft", 2), ("tags", 3)];
+        let forwards: BTreeMap<_, _> = entries.into_iter().collect();
+        let backwards: BTreeMap<_, _> = entries.into_iter().rev().collect();
+        assert_eq!(forwards.to_hash(), backwards.to_hash());
+
+        let forwards: HashMap<_, _> = entries.into_iter().collect();
+        let mut backwards = HashMap::with_capacity(100);
+        backwards.extend(entries.into_iter().rev());
+        assert_eq!(hash_unordered(&forwards), hash_unordered(&backwards));
+        assert_ne!(
+            hash_unordered(&forwards),
+            hash_unordered(entries.into_iter().take(2))
+        );
+    }
+
+    #[test]
+    fn sequences_hash_the_same() {
+        // So producers can hash borrowed data directly, without collecting it into a Vec first
+        let array = [1u32, 2, 3];
+        let slice: &[u32] = &array;
+        assert_eq!(array.to_hash(), slice.to_hash());
+        assert_eq!(array.to_hash(), array.to_vec().to_hash());
+        assert_ne!(array.to_hash(), [1u32, 2].to_hash());
+    }
+
+    #[test]
+    fn short_hex_is_prefix() {
+        let hash = "hello".to_hash();
+        let full = hex::encode(hash);
+        let short = short_hex(&hash).to_string();
+        assert_eq!(short.len(), SHORT_HASH_BYTES * 2);
+        assert!(full.starts_with(&short));
+        assert_eq!(format!("{:?}", short_hex(&hash)), short);
+
+        // sha256("hello") is 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
+        let known = sha2::Sha256::digest(b"hello");
+        assert_eq!(short_hex(&known).to_string(), "2cf24dba");
+    }
+}
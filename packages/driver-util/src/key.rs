@@ -22,6 +22,21 @@ pub trait Key:
     /// always be up-to-date (because everything they can possibly do is provided by the key
     /// itself), whereas input keys interact with some external system (network, filesystem).
     fn is_input(&self) -> bool;
+
+    /// Returns whether a given key is "volatile", meaning its output can change even when nothing
+    /// it depends on has (think: the current time, or a random number). Volatile keys are re-run
+    /// every time they're queried, and are never persisted to disk, so anything depending on them
+    /// gets re-run too.
+    fn is_volatile(&self) -> bool {
+        false
+    }
+
+    /// A short name for what sort of key this is, for grouping keys together in reports. Defaults
+    /// to the name of the type.
+    fn kind(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
 }
 
 /// Helper that allows you to define query keys that derive all the appropriate marker trait `Key`.
@@ -39,16 +54,21 @@ pub trait Key:
 ///     x: i32,
 ///     y: i32,
 /// });
+/// key!(#[input=|_| true, volatile=|_| true] struct Quux;);
 /// key!(enum Qux {
 ///     Foo,
 ///     Bar,
 ///     Baz,
+///     Quux,
 /// });
 ///
 /// assert_eq!(Qux::Foo(Foo).is_input(), true);
 /// assert_eq!(Qux::Bar(Bar(1337)).is_input(), false);
 /// assert_eq!(Qux::Baz(Baz { x: 6, y: 9 }).is_input(), false);
 /// assert_eq!(Qux::Baz(Baz { x: 7, y: 7 }).is_input(), true);
+/// assert_eq!(Qux::Foo(Foo).is_volatile(), false);
+/// assert_eq!(Qux::Quux(Quux).is_volatile(), true);
+/// assert_eq!(Qux::Bar(Bar(1337)).kind(), "Bar");
 ///
 /// no_blobs!(Foo);
 /// impl std::fmt::Display for Foo {
@@ -68,11 +88,17 @@ pub trait Key:
 ///         write!(f, "Baz {{ x: {}, y: {} }}", self.x, self.y)
 ///     }
 /// }
+/// no_blobs!(Quux);
+/// impl std::fmt::Display for Quux {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         f.write_str("Quux")
+///     }
+/// }
 /// // `impl Display for Qux` is derived automatically.
 /// ```
 #[macro_export]
 macro_rules! key {
-    (#[ input = $input:expr ] struct $name:ident $tt:tt) => {
+    (#[ input = $input:expr $(, volatile = $volatile:expr)? ] struct $name:ident $tt:tt) => {
         #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize)]
         pub struct $name $tt
 
@@ -80,11 +106,16 @@ macro_rules! key {
             fn is_input(&self) -> bool {
                 ($input)(self)
             }
+            $(
+            fn is_volatile(&self) -> bool {
+                ($volatile)(self)
+            }
+            )?
         }
     };
     // I wish Rust had a way of saying "this token is optional, but we want to condition on it
     // later"...
-    (#[ input = $input:expr ] struct $name:ident $($tt:tt)? ;) => {
+    (#[ input = $input:expr $(, volatile = $volatile:expr)? ] struct $name:ident $($tt:tt)? ;) => {
         #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, serde::Serialize, serde::Deserialize)]
         pub struct $name $($tt)?;
 
@@ -92,6 +123,11 @@ macro_rules! key {
             fn is_input(&self) -> bool {
                 ($input)(self)
             }
+            $(
+            fn is_volatile(&self) -> bool {
+                ($volatile)(self)
+            }
+            )?
         }
     };
 
@@ -121,6 +157,24 @@ macro_rules! key {
                 #[allow(unreachable_code)]
                 false
             }
+
+            fn is_volatile(&self) -> bool {
+                match self { $(
+                    Self::$key(x) => return $crate::Key::is_volatile(x),
+                )* };
+                // Just in case the enum is empty
+                #[allow(unreachable_code)]
+                false
+            }
+
+            fn kind(&self) -> &'static str {
+                match self { $(
+                    Self::$key(x) => return $crate::Key::kind(x),
+                )* };
+                // Just in case the enum is empty
+                #[allow(unreachable_code)]
+                ""
+            }
         }
 
         impl $crate::BlobTrace for $name {
@@ -2,6 +2,7 @@
 
 mod error;
 pub use error::Error;
+pub use error::ErrorKind;
 pub use error::StdError;
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -15,12 +16,25 @@ mod blob;
 pub use blob::Blob;
 pub use blob::BlobTrace;
 
+mod named_blobs;
+pub use named_blobs::NamedBlobs;
+
 mod output;
 pub use output::Output;
 
 mod hash;
 pub use hash::Hash;
+pub use hash::ShortHash;
 pub use hash::ToHash;
+pub use hash::hash_unordered;
+pub use hash::short_hex;
+
+mod secret;
+pub use secret::Secret;
+
+mod path;
+pub use path::is_within;
+pub use path::normalize_path;
 
 mod write_output;
 pub use write_output::WriteOutput;
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Blob, BlobTrace};
+
+/// Output for queries that naturally produce several artifacts at once (say, compiled code & its
+/// sourcemap). Rather than splitting the work across several keys, the producer returns all of
+/// them by name, and callers pick out the ones they want.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedBlobs(BTreeMap<String, Blob>);
+
+impl NamedBlobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, blob: Blob) -> &mut Self {
+        self.0.insert(name.into(), blob);
+        self
+    }
+
+    /// Picks out the output called `name`, erroring (with the names that _do_ exist) if there
+    /// isn't one.
+    pub fn get(&self, name: &str) -> crate::Result<&Blob> {
+        self.0.get(name).ok_or_else(|| {
+            let names = self.names().collect::<Vec<_>>().join(", ");
+            crate::Error::not_found(&format!("no output named {name:?} (have: {names})"))
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Blob)> {
+        self.0.iter().map(|(name, blob)| (name.as_str(), blob))
+    }
+}
+
+impl FromIterator<(String, Blob)> for NamedBlobs {
+    fn from_iter<T: IntoIterator<Item = (String, Blob)>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl BlobTrace for NamedBlobs {
+    fn trace(&self) -> impl Iterator<Item = &'_ Blob> {
+        self.0.values()
+    }
+}
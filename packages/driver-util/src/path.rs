@@ -0,0 +1,80 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Lexically normalizes a path, so that `./a/b`, `a//b/`, and `a/c/../b` all end up as the same
+/// `a/b`. This matters because keys hash their paths as-is, so two spellings of the same file would
+/// otherwise get cached (and tracked) separately.
+///
+/// Doesn't touch the filesystem, so symlinks aren't resolved; `..` just pops the previous
+/// component. Leading `..`s that can't be popped are kept, and `..` past the root is dropped.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = Vec::<Component>::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.last() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                Some(Component::ParentDir | Component::CurDir) | None => out.push(component),
+            },
+            component => out.push(component),
+        }
+    }
+
+    if out.is_empty() {
+        PathBuf::from(".")
+    } else {
+        out.into_iter().collect()
+    }
+}
+
+/// Whether `path` is `root` or somewhere inside it. Like [`normalize_path`], this only goes off the
+/// paths themselves, so a symlink inside `root` can still point outside it. Relative paths are
+/// taken relative to the current directory.
+pub fn is_within(root: &Path, path: &Path) -> std::io::Result<bool> {
+    let root = normalize_path(&std::path::absolute(normalize_path(root))?);
+    let path = normalize_path(&std::path::absolute(normalize_path(path))?);
+    Ok(path.starts_with(root))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ToHash;
+
+    fn n(path: &str) -> PathBuf {
+        normalize_path(Path::new(path))
+    }
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(n("./a/b"), PathBuf::from("a/b"));
+        assert_eq!(n("a//b/"), PathBuf::from("a/b"));
+        assert_eq!(n("a/./c/../b"), PathBuf::from("a/b"));
+        assert_eq!(n("../a"), PathBuf::from("../a"));
+        assert_eq!(n("a/../../b"), PathBuf::from("../b"));
+        assert_eq!(n("/../a"), PathBuf::from("/a"));
+        assert_eq!(n(""), PathBuf::from("."));
+        assert_eq!(n("./"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn within() {
+        let within = |root, path| is_within(Path::new(root), Path::new(path)).unwrap();
+        assert!(within(".", "a/b"));
+        assert!(within("a", "a"));
+        assert!(within("a", "./a/../a/b"));
+        assert!(!within("a", "ab"));
+        assert!(!within(".", "../a"));
+        assert!(!within(".", "/etc/passwd"));
+        assert!(!within("a/b", "a/b/../c"));
+    }
+
+    #[test]
+    fn same_hash() {
+        assert_eq!(n("./a/b").to_hash(), n("a/b").to_hash());
+        assert_eq!(n("a/b/").to_hash(), n("a/./b").to_hash());
+        assert_ne!(n("a/b").to_hash(), n("b/a").to_hash());
+    }
+}
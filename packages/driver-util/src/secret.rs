@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, ToHash as _, no_blobs, short_hex};
+
+/// A value (like an API key) that must never end up in the persisted cache. Only a hash of it is
+/// ever serialized, which is enough to tell whether it changed between builds, but means a secret
+/// restored from disk can't actually be read. That's fine in practice: secrets come from input
+/// queries, which get re-run every revision before anything can read them.
+///
+/// NOTE: the hash is unsalted, so a short or guessable secret could still be brute-forced from
+/// the cache. Don't share caches built with secrets in them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Secret {
+    #[serde(skip)]
+    value: Option<String>,
+    hash: Hash,
+}
+no_blobs!(Secret);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self {
+            hash: value.to_hash(),
+            value: Some(value),
+        }
+    }
+
+    /// Errors if this was restored from disk, and so never had a value to begin with.
+    pub fn expose(&self) -> crate::Result<&str> {
+        self.value.as_deref().ok_or_else(|| {
+            crate::Error::new("secret was restored from the cache, so its value isn't available")
+        })
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Eq for Secret {}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({})", short_hex(&self.hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn never_serialized() {
+        let secret = Secret::new("hunter2".to_string());
+        let bytes = postcard::to_stdvec(&secret).unwrap();
+        assert!(!bytes.windows(7).any(|w| w == b"hunter2"));
+
+        let restored: Secret = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, secret);
+        assert!(restored.expose().is_err());
+        assert_eq!(secret.expose().unwrap(), "hunter2");
+        assert!(!format!("{secret:?}").contains("hunter2"));
+    }
+}
@@ -25,13 +25,23 @@ pub struct WriteOutputBuilder<Key: Ord> {
     indirect: BTreeMap<Key, WriteOutput<Key>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WriteOutput<Key: Ord> {
     inner: WriteOutputBuilder<Key>,
     /// Memoized hash of [`inner`] to make comparisons faster.
     hash: Hash,
 }
 
+/// Compares only the memoized hashes. The database compares every output it re-produces against
+/// the cached one, and comparing the trees themselves would walk every file written by every
+/// script the output (transitively) merged in, even when just one of them changed.
+impl<Key: Ord> PartialEq for WriteOutput<Key> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+impl<Key: Ord> Eq for WriteOutput<Key> {}
+
 /// We want this clone impl, but we _don't_ want a clone impl for [`WriteOutputBuilder`], so we have
 /// to write it manually.
 impl<Key: Ord + Clone> Clone for WriteOutput<Key> {
@@ -64,6 +74,9 @@ impl<Key: Ord + std::hash::Hash> WriteOutputBuilder<Key> {
 
         for (path, blob) in self.direct.iter() {
             hasher.update(path.as_os_str().as_encoded_bytes());
+            // Same as in [`WriteOutput::fingerprint`]. Equality goes by this hash alone, so it
+            // can't be ambiguous about where one path ends.
+            hasher.update([0u8]);
             hasher.update(blob);
         }
 
@@ -135,6 +148,21 @@ impl<Key: Ord> WriteOutput<Key> {
             parent: None,
         }
     }
+
+    /// A single hash of every path & the contents written there. Unlike the memoized hash, this
+    /// only depends on the final files, not on which keys they came from, so two builds producing
+    /// identical files always have the same fingerprint.
+    pub fn fingerprint(&self) -> Hash {
+        let files: BTreeMap<&PathBuf, &Blob> = self.iter().collect();
+        let mut hasher = Sha256Hasher::new();
+        for (path, blob) in files {
+            hasher.update(path.as_os_str().as_encoded_bytes());
+            // Paths can't contain NUL, so this keeps `a` + `bc` from looking like `ab` + `c`
+            hasher.update([0u8]);
+            hasher.update(blob);
+        }
+        hasher.finalize()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -267,6 +295,48 @@ mod test {
         unsafe { crate::Blob::from_hash([i; 32].into()) }
     }
 
+    #[test]
+    fn fingerprint() {
+        let build = |last: crate::Blob| {
+            let mut inner = WriteOutput::builder();
+            inner.push("b".into(), o(2));
+            inner.push("c".into(), last);
+            let mut outer = WriteOutput::builder();
+            outer.push("a".into(), o(1));
+            outer.merge("inner".to_string(), inner.finalize());
+            outer.finalize()
+        };
+
+        // Same files, so same fingerprint
+        assert_eq!(build(o(3)).fingerprint(), build(o(3)).fingerprint());
+        // One file changed, so a different fingerprint
+        assert_ne!(build(o(3)).fingerprint(), build(o(4)).fingerprint());
+
+        // Where the files came from doesn't matter, only what they are
+        let mut flat = WriteOutput::<String>::builder();
+        flat.push("a".into(), o(1));
+        flat.push("b".into(), o(2));
+        flat.push("c".into(), o(3));
+        assert_eq!(flat.finalize().fingerprint(), build(o(3)).fingerprint());
+    }
+
+    #[test]
+    fn compares_by_hash() {
+        let build = |files: &[(&str, u8)]| {
+            let mut inner = WriteOutput::builder();
+            for (path, i) in files {
+                inner.push(path.into(), o(*i));
+            }
+            let mut outer = WriteOutput::builder();
+            outer.merge("inner".to_string(), inner.finalize());
+            outer.finalize()
+        };
+
+        assert_eq!(build(&[("a", 1), ("b", 2)]), build(&[("a", 1), ("b", 2)]));
+        assert_ne!(build(&[("a", 1), ("b", 2)]), build(&[("a", 1), ("b", 3)]));
+        assert_ne!(build(&[("a", 1), ("b", 2)]), build(&[("a", 1)]));
+    }
+
     #[test]
     fn nested_iter() {
         let mut a = WriteOutput::builder();
@@ -387,4 +457,44 @@ mod test {
         );
         assert_eq!(diff.to_remove, [&PathBuf::from("b1")].into_iter().collect());
     }
+
+    /// What equality used to do: walk every file of every merged-in output.
+    fn deep_eq(a: &WriteOutput<String>, b: &WriteOutput<String>) -> bool {
+        a.inner.direct == b.inner.direct
+            && a.inner.indirect.len() == b.inner.indirect.len()
+            && a.inner
+                .indirect
+                .iter()
+                .zip(b.inner.indirect.iter())
+                .all(|((key_a, a), (key_b, b))| key_a == key_b && deep_eq(a, b))
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_compare_many_outputs() {
+        // Like a site built by 100 scripts that each write 1000 pages
+        let build = || {
+            let mut outer = WriteOutput::builder();
+            for script in 0..100 {
+                let mut inner = WriteOutput::builder();
+                for page in 0..1000u32 {
+                    inner.push(format!("{script}/{page}.html").into(), o(page as u8));
+                }
+                outer.merge(format!("script{script}.js"), inner.finalize());
+            }
+            outer.finalize()
+        };
+        let (a, b) = (build(), build());
+
+        let time = |eq: &dyn Fn() -> bool| {
+            let start = std::time::Instant::now();
+            for _ in 0..100 {
+                assert!(std::hint::black_box(eq()));
+            }
+            start.elapsed() / 100
+        };
+        let walked = time(&|| deep_eq(&a, &b));
+        let hashed = time(&|| a == b);
+        println!("comparing 100,000 writes: {walked:?} walking every file, {hashed:?} by hash");
+    }
 }